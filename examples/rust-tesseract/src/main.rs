@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use hypetrigger::error::Result;
-use hypetrigger::photon::{Crop, ThresholdFilter};
+use hypetrigger::photon::{Anchor, Crop, ThresholdFilter};
 use hypetrigger::pipeline::Hypetrigger;
 use hypetrigger::tesseract::{init_tesseract, TesseractTrigger};
 
@@ -20,17 +20,19 @@ fn main() -> Result<()> {
             left_percent: 1024.0 * 100.0 / 1280.0,
             width_percent: 128.0 * 100.0 / 1280.0,
             height_percent: 208.0 * 100.0 / 720.0,
+            anchor: Anchor::TopLeft,
         }),
         threshold_filter: Some(ThresholdFilter {
             r: 255,
             g: 255,
             b: 255,
             threshold: 42,
+            ..Default::default()
         }),
         callback: Some(Arc::new(|test| {
             println!("{}", test.text); // print the recognized text
         })),
-        enable_debug_breakpoints: false,
+        ..Default::default()
     };
 
     // Start the job
@@ -1,37 +1,144 @@
 use crate::error::{Error, Result};
 use crate::trigger::{Frame, Trigger};
 use std::{
-    sync::{mpsc::SyncSender, Arc},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
     thread::{self, JoinHandle},
 };
 
+/// Capacity of a `TriggerThread`'s queue when created with `spawn`
+pub const TRIGGER_THREAD_CAPACITY: usize = 100;
+
+/// What an `AsyncTrigger` does with a new frame when its `TriggerThread` can't
+/// keep up, i.e. its queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Wait for room in the queue, which in turn pauses ffmpeg. Every frame is
+    /// processed.
+    #[default]
+    Block,
+
+    /// Drop the new frame, keeping the ones already queued.
+    DropNewest,
+
+    /// Keep at most one frame waiting for this trigger, replacing it with
+    /// each new frame. Favors freshness for real-time inputs: the trigger
+    /// always sees the most recent frame once it catches up.
+    DropOldest,
+}
+
 /// A wrapper around any other Trigger that sends it across a channel to run on
 /// a separate thread.
 #[derive(Clone)]
 pub struct AsyncTrigger {
     pub trigger: Arc<dyn Trigger>,
     pub runner_tx: SyncSender<TriggerCommand>,
+
+    /// What to do with frames when the thread falls behind. Defaults to
+    /// `DropPolicy::Block`.
+    pub drop_policy: DropPolicy,
+
+    /// Number of frames dropped so far, shared between clones. See
+    /// `dropped_frames`.
+    pub dropped: Arc<AtomicU64>,
+
+    /// The frame waiting to be processed, with `DropPolicy::DropOldest`
+    latest: Arc<Mutex<Option<Frame>>>,
 }
 
 impl Trigger for AsyncTrigger {
     fn on_frame(&self, frame: &Frame) -> Result<()> {
-        self.runner_tx
-            .send(TriggerCommand::Packet(TriggerPacket {
-                frame: frame.clone(),
-                trigger: self.trigger.clone(),
-            }))
-            .map_err(Error::from_std)
+        match self.drop_policy {
+            DropPolicy::Block => self
+                .runner_tx
+                .send(TriggerCommand::Packet(self.packet(frame)))
+                .map_err(Error::from_std),
+            DropPolicy::DropNewest => {
+                match self
+                    .runner_tx
+                    .try_send(TriggerCommand::Packet(self.packet(frame)))
+                {
+                    Err(TrySendError::Full(_)) => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        Ok(())
+                    }
+                    result => result.map_err(Error::from_std),
+                }
+            }
+            DropPolicy::DropOldest => {
+                let replaced = self.latest.lock()?.replace(frame.clone());
+                if replaced.is_some() {
+                    // Already queued; the thread will pick up this frame instead
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                self.runner_tx
+                    .send(TriggerCommand::Latest(LatestPacket {
+                        latest: self.latest.clone(),
+                        trigger: self.trigger.clone(),
+                    }))
+                    .map_err(Error::from_std)
+            }
+        }
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.trigger.desired_fps()
+    }
+
+    /// Wait for the `TriggerThread` to finish every frame queued so far.
+    fn flush(&self) -> Result<()> {
+        flush_trigger_thread(&self.runner_tx)
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.trigger.on_start()
+    }
+
+    /// Wait for the `TriggerThread` to finish every queued frame, then end the
+    /// inner trigger on the calling thread.
+    fn on_end(&self) -> Result<()> {
+        self.flush()?;
+        self.trigger.on_end()
     }
 }
 
 impl AsyncTrigger {
+    pub fn new(trigger: Arc<dyn Trigger>, runner_tx: SyncSender<TriggerCommand>) -> Self {
+        Self {
+            trigger,
+            runner_tx,
+            drop_policy: DropPolicy::default(),
+            dropped: Arc::new(AtomicU64::new(0)),
+            latest: Arc::new(Mutex::new(None)),
+        }
+    }
+
     pub fn from_trigger<T>(trigger: T, runner_tx: SyncSender<TriggerCommand>) -> Self
     where
         T: Trigger + 'static,
     {
-        Self {
-            trigger: Arc::new(trigger),
-            runner_tx,
+        Self::new(Arc::new(trigger), runner_tx)
+    }
+
+    pub fn with_drop_policy(mut self, drop_policy: DropPolicy) -> Self {
+        self.drop_policy = drop_policy;
+        self
+    }
+
+    /// Number of frames that were never processed because of the
+    /// `drop_policy`.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn packet(&self, frame: &Frame) -> TriggerPacket {
+        TriggerPacket {
+            frame: frame.clone(),
+            trigger: self.trigger.clone(),
         }
     }
 }
@@ -47,7 +154,13 @@ impl TriggerThread {
     /// Prepares a new thread capable of running Triggers, including the
     /// communication channels, and spawns the thread.
     pub fn spawn() -> Self {
-        let (tx, rx) = std::sync::mpsc::sync_channel::<TriggerCommand>(100);
+        Self::spawn_with_capacity(TRIGGER_THREAD_CAPACITY)
+    }
+
+    /// Spawn a thread whose queue holds at most `capacity` commands before
+    /// `AsyncTrigger`s block or drop frames (see `DropPolicy`).
+    pub fn spawn_with_capacity(capacity: usize) -> Self {
+        let (tx, rx) = sync_channel::<TriggerCommand>(capacity);
         let join_handle = thread::spawn(move || {
             println!("[trigger_thread] Listening for async trigger commands.");
             while let Ok(command) = rx.recv() {
@@ -56,12 +169,26 @@ impl TriggerThread {
                         println!("[trigger_thread] Received stop command.");
                         break;
                     }
+                    TriggerCommand::Flush(done_tx) => {
+                        done_tx.send(()).ok();
+                    }
                     TriggerCommand::Packet(payload) => {
                         let result = payload.trigger.on_frame(&payload.frame);
                         if let Err(e) = result {
                             eprintln!("Error in async trigger: {}", e);
                         }
                     }
+                    TriggerCommand::Latest(payload) => {
+                        let frame = match payload.latest.lock() {
+                            Ok(mut latest) => latest.take(),
+                            Err(_) => None,
+                        };
+                        if let Some(frame) = frame {
+                            if let Err(e) = payload.trigger.on_frame(&frame) {
+                                eprintln!("Error in async trigger: {}", e);
+                            }
+                        }
+                    }
                 }
             }
             println!("[trigger_thread] Exiting.");
@@ -69,6 +196,12 @@ impl TriggerThread {
         Self { tx, join_handle }
     }
 
+    /// Block until the thread has finished running every packet sent to it so
+    /// far, without stopping it.
+    pub fn flush(&self) -> Result<()> {
+        flush_trigger_thread(&self.tx)
+    }
+
     /// Send a stop command to the thread, and join while waiting for it to exit.
     /// Since `TriggerThread`'s will stick around indefinitely waiting for more
     /// input, it's important to call this in your program's flow when you know
@@ -88,6 +221,21 @@ pub enum TriggerCommand {
 
     /// Tell the thread to run a trigger
     Packet(TriggerPacket),
+
+    /// Tell the thread to run a trigger on its most recent frame
+    /// (`DropPolicy::DropOldest`)
+    Latest(LatestPacket),
+
+    /// Tell the thread to reply on the given channel once it reaches this
+    /// command, i.e. once every command before it has been handled
+    Flush(SyncSender<()>),
+}
+
+/// Send a `Flush` command over the channel and wait for the reply.
+fn flush_trigger_thread(runner_tx: &SyncSender<TriggerCommand>) -> Result<()> {
+    let (done_tx, done_rx) = sync_channel(1);
+    runner_tx.send(TriggerCommand::Flush(done_tx))?;
+    done_rx.recv().map_err(Error::from_std)
 }
 
 /// Everything a `TriggerThread` needs to run a `AsyncTrigger`
@@ -96,3 +244,97 @@ pub struct TriggerPacket {
     frame: Frame,
     trigger: Arc<dyn Trigger>,
 }
+
+/// Everything a `TriggerThread` needs to run a `AsyncTrigger` with
+/// `DropPolicy::DropOldest`
+#[derive(Clone)]
+pub struct LatestPacket {
+    latest: Arc<Mutex<Option<Frame>>>,
+    trigger: Arc<dyn Trigger>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncTrigger, DropPolicy, TriggerThread};
+    use crate::{
+        error::Result,
+        pipeline::Hypetrigger,
+        simple_trigger::SimpleTrigger,
+        trigger::{Frame, Trigger},
+    };
+    use image::RgbImage;
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    /// Send 20 frames to a trigger that is stuck until they've all been sent,
+    /// through a queue with room for 2, and return the frames it processed.
+    fn slow_consumer(drop_policy: DropPolicy) -> Result<(Vec<u64>, u64)> {
+        let runner_thread = TriggerThread::spawn_with_capacity(2);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let slow_trigger = SimpleTrigger::new(move |frame| {
+            release_rx.lock().unwrap().recv().ok(); // until `release_tx` is dropped
+            frames_clone.lock().unwrap().push(frame.frame_num);
+        });
+        let trigger = AsyncTrigger::from_trigger(slow_trigger, runner_thread.tx.clone())
+            .with_drop_policy(drop_policy);
+
+        // The producer never waits on the stuck trigger, or this would hang
+        for frame_num in 0..20 {
+            trigger.on_frame(&Frame::new(RgbImage::new(1, 1), frame_num, 0.0))?;
+        }
+        drop(release_tx);
+
+        trigger.flush()?;
+        runner_thread.stop()?;
+        let frames = frames.lock()?.clone();
+        Ok((frames, trigger.dropped_frames()))
+    }
+
+    #[test]
+    fn drop_newest() -> Result<()> {
+        let (frames, dropped) = slow_consumer(DropPolicy::DropNewest)?;
+        assert!(dropped > 0);
+        assert_eq!(frames.len() as u64 + dropped, 20);
+        assert_eq!(frames[0], 0);
+        assert!(!frames.contains(&19));
+        Ok(())
+    }
+
+    #[test]
+    fn drop_oldest() -> Result<()> {
+        let (frames, dropped) = slow_consumer(DropPolicy::DropOldest)?;
+        assert!(dropped > 0);
+        assert_eq!(frames.len() as u64 + dropped, 20);
+        assert_eq!(frames.last(), Some(&19));
+        Ok(())
+    }
+
+    #[test]
+    fn flush_before_complete() -> Result<()> {
+        let runner_thread = TriggerThread::spawn();
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let slow_trigger = SimpleTrigger::new(move |frame| {
+            thread::sleep(Duration::from_millis(50));
+            frames_clone.lock().unwrap().push(frame.frame_num);
+        });
+
+        Hypetrigger::new()
+            .test_input()
+            .add_trigger(AsyncTrigger::from_trigger(
+                slow_trigger,
+                runner_thread.tx.clone(),
+            ))
+            .run()?;
+
+        // Every frame has been handled, even though the thread is still running
+        assert_eq!(frames.lock()?.last(), Some(&19));
+        runner_thread.stop()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::error::Result;
+use std::sync::Arc;
+
+/// Sample rate that the audio stream is resampled to, in Hz
+pub const AUDIO_SAMPLE_RATE: u32 = 16_000;
+
+/// Number of samples in each `AudioChunk` (0.1 seconds)
+pub const AUDIO_CHUNK_SAMPLES: usize = 1_600;
+
+/// A short window of mono audio from the input, as signed 16-bit PCM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioChunk {
+    pub samples: Vec<i16>,
+
+    /// Position of the first sample in the input, in seconds
+    pub timestamp: f64,
+
+    /// Samples per second, always `AUDIO_SAMPLE_RATE`
+    pub sample_rate: u32,
+}
+
+impl AudioChunk {
+    /// Root mean square level of the chunk, from `0.0` (silence) to `1.0`
+    /// (a full-scale square wave).
+    pub fn rms(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let sum_of_squares: f64 = self
+            .samples
+            .iter()
+            .map(|sample| (*sample as f64 / i16::MAX as f64).powi(2))
+            .sum();
+        (sum_of_squares / self.samples.len() as f64).sqrt()
+    }
+
+    /// Highest absolute sample level in the chunk, from `0.0` to `1.0`.
+    pub fn peak(&self) -> f64 {
+        self.samples
+            .iter()
+            .map(|sample| (*sample as f64 / i16::MAX as f64).abs().min(1.0))
+            .fold(0.0, f64::max)
+    }
+
+    /// Length of the chunk, in seconds
+    pub fn duration(&self) -> f64 {
+        self.samples.len() as f64 / self.sample_rate as f64
+    }
+}
+
+/// The audio counterpart of a `Trigger`, run on each `AudioChunk` of the
+/// input's first audio stream instead of on video frames. See
+/// `Hypetrigger::add_audio_trigger`.
+pub trait AudioTrigger: Send + Sync {
+    fn on_chunk(&self, chunk: &AudioChunk) -> Result<()>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioLevels {
+    pub rms: f64,
+    pub peak: f64,
+    pub timestamp: f64,
+}
+
+pub type AudioLevelTriggerCallback = Arc<dyn Fn(AudioLevels) + Send + Sync>;
+
+/// Calls a callback with the RMS and peak level of each chunk of audio, e.g.
+/// to detect a crowd roaring or a gunshot.
+#[derive(Clone)]
+pub struct AudioLevelTrigger {
+    pub callback: AudioLevelTriggerCallback,
+}
+
+impl AudioTrigger for AudioLevelTrigger {
+    fn on_chunk(&self, chunk: &AudioChunk) -> Result<()> {
+        (self.callback)(AudioLevels {
+            rms: chunk.rms(),
+            peak: chunk.peak(),
+            timestamp: chunk.timestamp,
+        });
+        Ok(())
+    }
+}
+
+impl AudioLevelTrigger {
+    pub fn new<T>(callback: T) -> Self
+    where
+        T: Fn(AudioLevels) + Send + Sync + 'static,
+    {
+        Self {
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+/// Decode little-endian signed 16-bit PCM (`-f s16le`). A trailing odd byte
+/// is ignored.
+pub fn decode_s16le(bytes: &[u8]) -> Vec<i16> {
+    bytes
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_s16le, AudioChunk, AudioLevelTrigger, AUDIO_SAMPLE_RATE};
+    use crate::{error::Result, pipeline::Hypetrigger};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn levels() {
+        let chunk = AudioChunk {
+            samples: decode_s16le(&[0xff, 0x7f, 0x01, 0x80, 0x00, 0x00, 0x00, 0x00]),
+            timestamp: 0.0,
+            sample_rate: AUDIO_SAMPLE_RATE,
+        };
+        assert_eq!(chunk.samples, vec![i16::MAX, -i16::MAX, 0, 0]);
+        assert_eq!(chunk.peak(), 1.0);
+        assert!((chunk.rms() - 0.5f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn audio_levels() -> Result<()> {
+        let levels = Arc::new(Mutex::new(Vec::new()));
+        let levels_clone = levels.clone();
+
+        Hypetrigger::new()
+            .set_input_format("lavfi")
+            .set_input("sine=frequency=440:duration=2".to_string())
+            .add_audio_trigger(AudioLevelTrigger::new(move |level| {
+                levels_clone.lock().unwrap().push(level)
+            }))
+            .run()?;
+
+        let levels = levels.lock()?;
+        assert_eq!(levels.len(), 20);
+        assert!(levels.iter().all(|level| level.rms > 0.0));
+        assert!((levels[19].timestamp - 1.9).abs() < 1e-9);
+        Ok(())
+    }
+}
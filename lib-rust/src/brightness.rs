@@ -0,0 +1,178 @@
+use crate::crop::Crop;
+use crate::error::Result;
+use crate::trigger::{Frame, Trigger};
+use image::{imageops, GenericImageView, Rgb};
+use std::sync::{Arc, Mutex};
+
+pub type BrightnessTriggerCallback = Arc<dyn Fn(f64, &Frame) + Send + Sync>;
+
+/// Measures the average brightness of each frame (or a region of it), from 0
+/// (solid black) to 1 (solid white), i.e. the mean of every channel of every
+/// pixel. Works on `frame.image`, so it doesn't need the `photon` feature.
+///
+/// By default, the callback runs on every frame. With a `cut_threshold`, it
+/// only runs when the brightness changes by at least that much since the
+/// previous frame, e.g. to detect cuts or fades to black.
+#[derive(Clone)]
+pub struct BrightnessTrigger {
+    /// The region to measure. `None` measures the whole frame.
+    pub crop: Option<Crop>,
+
+    pub callback: BrightnessTriggerCallback,
+
+    /// Minimum change in brightness between consecutive frames (from 0 to 1)
+    /// for the callback to run. `None` runs it on every frame.
+    pub cut_threshold: Option<f64>,
+
+    /// Brightness of the previous frame, shared between clones
+    previous: Arc<Mutex<Option<f64>>>,
+}
+
+impl Trigger for BrightnessTrigger {
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        let brightness = match &self.crop {
+            Some(crop) => {
+                let (x1, y1, x2, y2) = crop.bounds(frame.image.width(), frame.image.height());
+                let region = imageops::crop_imm(&frame.image, x1, y1, x2 - x1, y2 - y1);
+                brightness(&region)
+            }
+            None => brightness(&frame.image),
+        };
+        let previous = self.previous.lock()?.replace(brightness);
+        let fire = match (self.cut_threshold, previous) {
+            (None, _) => true,
+            (Some(threshold), Some(previous)) => (brightness - previous).abs() >= threshold,
+            (Some(_), None) => false,
+        };
+        if fire {
+            (self.callback)(brightness, frame);
+        }
+        Ok(())
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.crop.iter().try_for_each(Crop::validate)
+    }
+}
+
+impl BrightnessTrigger {
+    /// Call back with the brightness of every frame
+    pub fn new<T>(crop: Option<Crop>, on_brightness: T) -> Self
+    where
+        T: Fn(f64, &Frame) + Send + Sync + 'static,
+    {
+        Self {
+            crop,
+            callback: Arc::new(on_brightness),
+            cut_threshold: None,
+            previous: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Call back with the new brightness only when it changes by at least
+    /// `threshold` from one frame to the next. `0.3` is a reasonable starting
+    /// point for hard cuts.
+    pub fn cuts<T>(crop: Option<Crop>, threshold: f64, on_cut: T) -> Self
+    where
+        T: Fn(f64, &Frame) + Send + Sync + 'static,
+    {
+        Self {
+            cut_threshold: Some(threshold),
+            ..Self::new(crop, on_cut)
+        }
+    }
+}
+
+/// The mean of every channel of every pixel, from 0 to 1. An empty image is 0.
+fn brightness<I>(image: &I) -> f64
+where
+    I: GenericImageView<Pixel = Rgb<u8>>,
+{
+    let (width, height) = image.dimensions();
+    let channels = width as u64 * height as u64 * 3;
+    if channels == 0 {
+        return 0.0;
+    }
+    let sum: u64 = image
+        .pixels()
+        .map(|(_, _, pixel)| pixel.0.iter().map(|&v| v as u64).sum::<u64>())
+        .sum();
+    sum as f64 / (channels as f64 * 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BrightnessTrigger;
+    use crate::{
+        crop::{Anchor, Crop},
+        error::Result,
+        pipeline::Hypetrigger,
+        trigger::{Frame, Trigger},
+    };
+    use image::{Rgb, RgbImage};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn brightness() -> Result<()> {
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let values_clone = values.clone();
+        Hypetrigger::new()
+            .test_input()
+            .add_trigger(BrightnessTrigger::new(None, move |brightness, _| {
+                values_clone.lock().unwrap().push(brightness);
+            }))
+            .run()?;
+
+        let values = values.lock()?;
+        assert_eq!(values.len(), 20);
+        assert!(values.iter().all(|b| (0.0..=1.0).contains(b)));
+        Ok(())
+    }
+
+    #[test]
+    fn cuts() -> Result<()> {
+        let cuts = Arc::new(Mutex::new(Vec::new()));
+        let cuts_clone = cuts.clone();
+        let trigger = BrightnessTrigger::cuts(None, 0.3, move |brightness, frame| {
+            cuts_clone
+                .lock()
+                .unwrap()
+                .push((frame.frame_num, brightness));
+        });
+
+        // A slow fade, then a cut to black
+        for (frame_num, value) in [200, 190, 180, 0].into_iter().enumerate() {
+            let image = RgbImage::from_pixel(2, 2, Rgb([value, value, value]));
+            trigger.on_frame(&Frame::new(image, frame_num as u64, 0.0))?;
+        }
+
+        assert_eq!(*cuts.lock()?, vec![(3, 0.0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn crop() -> Result<()> {
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let values_clone = values.clone();
+        let right_half = Crop {
+            left_percent: 50.0,
+            top_percent: 0.0,
+            width_percent: 50.0,
+            height_percent: 100.0,
+            anchor: Anchor::TopLeft,
+        };
+        let trigger = BrightnessTrigger::new(Some(right_half), move |brightness, _| {
+            values_clone.lock().unwrap().push(brightness);
+        });
+
+        // White left half, mid-grey right half
+        let image = RgbImage::from_fn(4, 2, |x, _| match x {
+            0 | 1 => Rgb([255, 255, 255]),
+            _ => Rgb([51, 102, 153]),
+        });
+        trigger.on_start()?;
+        trigger.on_frame(&Frame::new(image, 0, 0.0))?;
+        assert_eq!(*values.lock()?, vec![0.4]);
+        Ok(())
+    }
+}
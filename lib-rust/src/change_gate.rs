@@ -0,0 +1,170 @@
+use crate::error::Result;
+use crate::photon::Crop;
+use crate::trigger::{Frame, Trigger};
+use image::{imageops, RgbImage};
+use std::sync::{Arc, Mutex};
+
+/// Only runs the inner trigger when a region of the frame has changed since
+/// the previous frame, to skip expensive triggers (e.g. OCR) while e.g. a
+/// scoreboard stays the same.
+///
+/// Change is measured as the mean squared error between the pixels of the
+/// region in consecutive frames, over all three channels, in 8-bit units
+/// (`0.0` for identical regions, up to `65025.0`). The first frame is always
+/// forwarded.
+#[derive(Clone)]
+pub struct ChangeGateTrigger {
+    pub trigger: Arc<dyn Trigger>,
+
+    /// The region to compare. `None` compares the whole frame.
+    pub region: Option<Crop>,
+
+    /// Minimum mean squared error for the frame to be forwarded. Small values
+    /// (around `10.0`) ignore compression noise.
+    pub threshold: f64,
+
+    /// The region in the previous frame, shared between clones
+    previous: Arc<Mutex<Option<RgbImage>>>,
+}
+
+impl Trigger for ChangeGateTrigger {
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        let (x1, y1, x2, y2) = match &self.region {
+            Some(crop) => crop.bounds(frame.image.width(), frame.image.height()),
+            None => (0, 0, frame.image.width(), frame.image.height()),
+        };
+        let (width, height) = (x2.saturating_sub(x1), y2.saturating_sub(y1));
+        if width == 0 || height == 0 {
+            return Ok(()); // the region is outside the frame, so it never changes
+        }
+        let region = imageops::crop_imm(&frame.image, x1, y1, width, height).to_image();
+
+        let changed = {
+            let mut previous = self.previous.lock()?;
+            let changed = match previous.as_ref() {
+                Some(previous) => !matches!(
+                    mean_squared_error(previous, &region),
+                    Some(error) if error < self.threshold
+                ),
+                None => true,
+            };
+            *previous = Some(region);
+            changed
+        };
+
+        if changed {
+            self.trigger.on_frame(frame)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.trigger.desired_fps()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.trigger.flush()
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.region.iter().try_for_each(Crop::validate)?;
+        self.trigger.on_start()
+    }
+
+    fn on_end(&self) -> Result<()> {
+        self.trigger.on_end()
+    }
+}
+
+impl ChangeGateTrigger {
+    pub fn new<T>(trigger: T, region: Option<Crop>, threshold: f64) -> Self
+    where
+        T: Trigger + 'static,
+    {
+        Self {
+            trigger: Arc::new(trigger),
+            region,
+            threshold,
+            previous: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+/// Mean squared error between two images of the same size, in 8-bit units.
+/// Returns `None` if their sizes differ.
+pub fn mean_squared_error(a: &RgbImage, b: &RgbImage) -> Option<f64> {
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+    if a.as_raw().is_empty() {
+        return Some(0.0);
+    }
+    let sum: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum();
+    Some(sum / a.as_raw().len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChangeGateTrigger;
+    use crate::{
+        error::Result,
+        photon::{Anchor, Crop},
+        simple_trigger::SimpleTrigger,
+        trigger::{Frame, Trigger},
+    };
+    use image::{Rgb, RgbImage};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn change_gate() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let inner = SimpleTrigger::new(move |frame| {
+            frames_clone.lock().unwrap().push(frame.frame_num);
+        });
+        let top_half = Crop {
+            left_percent: 0.0,
+            top_percent: 0.0,
+            width_percent: 100.0,
+            height_percent: 50.0,
+            anchor: Anchor::TopLeft,
+        };
+        let gate = ChangeGateTrigger::new(inner, Some(top_half), 10.0);
+
+        let unchanged = RgbImage::from_pixel(16, 16, Rgb([50, 50, 50]));
+        let mut outside_region = unchanged.clone();
+        outside_region.put_pixel(8, 15, Rgb([255, 255, 255]));
+        let mut inside_region = unchanged.clone();
+        for x in 0..16 {
+            inside_region.put_pixel(x, 2, Rgb([255, 255, 255]));
+        }
+
+        gate.on_frame(&Frame::new(unchanged.clone(), 0, 0.0))?;
+        gate.on_frame(&Frame::new(unchanged, 1, 0.5))?;
+        gate.on_frame(&Frame::new(outside_region, 2, 1.0))?;
+        gate.on_frame(&Frame::new(inside_region, 3, 1.5))?;
+
+        assert_eq!(*frames.lock()?, vec![0, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn empty_region() -> Result<()> {
+        let inner = SimpleTrigger::new(|_| panic!("no frame expected"));
+        let outside = Crop {
+            left_percent: 100.0,
+            top_percent: 0.0,
+            width_percent: 10.0,
+            height_percent: 10.0,
+            anchor: Anchor::TopLeft,
+        };
+        let gate = ChangeGateTrigger::new(inner, Some(outside), 10.0);
+        gate.on_frame(&Frame::new(RgbImage::new(16, 16), 0, 0.0))
+    }
+}
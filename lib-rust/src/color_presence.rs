@@ -0,0 +1,187 @@
+use crate::error::Result;
+use crate::photon::{Crop, ThresholdFilter};
+use crate::trigger::{Frame, Trigger};
+use crate::typed_trigger::TypedTrigger;
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+/// Checks whether a color is present in a region of the frame, i.e. whether
+/// at least `min_fraction` of its pixels are within the `filter`'s threshold
+/// of the filter color (see `ThresholdFilter::match_fraction`). Outputs `true`
+/// or `false` for each frame; see `EdgeCountTrigger` to count appearances.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorPresenceTrigger {
+    /// The color to look for, and how close a pixel needs to be to match
+    pub filter: ThresholdFilter,
+
+    /// The region to search. `None` searches the whole frame.
+    pub region: Option<Crop>,
+
+    /// Fraction of the region (`0.0..=1.0`) that has to match
+    pub min_fraction: f64,
+}
+
+impl TypedTrigger for ColorPresenceTrigger {
+    type Output = bool;
+
+    fn process(&self, frame: &Frame) -> Result<bool> {
+        let image = match &self.region {
+            Some(crop) => Cow::Owned(crop.apply(frame.to_photon())),
+            None => Cow::Borrowed(frame.photon()),
+        };
+        Ok(self.filter.match_fraction(&image) >= self.min_fraction)
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.region.iter().try_for_each(Crop::validate)
+    }
+}
+
+pub type EdgeCountTriggerCallback = Arc<dyn Fn(u64, &Frame) + Send + Sync>;
+
+/// Counts the times something appears, e.g. a scoreboard flashing a color
+/// when a point is scored. Runs a presence check (usually a
+/// `ColorPresenceTrigger`) on each frame, and calls back with the new running
+/// count only on rising edges, when it goes from absent to present. Staying
+/// present across several frames counts once. Before the first frame, the
+/// presence is assumed to be `false`.
+#[derive(Clone)]
+pub struct EdgeCountTrigger {
+    pub presence: Arc<dyn TypedTrigger<Output = bool>>,
+    pub callback: EdgeCountTriggerCallback,
+
+    /// The presence on the previous frame and the running count, shared
+    /// between clones
+    state: Arc<Mutex<(bool, u64)>>,
+}
+
+impl Trigger for EdgeCountTrigger {
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        let present = self.presence.process(frame)?;
+        let count = {
+            let mut state = self.state.lock()?;
+            let (was_present, count) = &mut *state;
+            let rising = present && !*was_present;
+            *was_present = present;
+            if !rising {
+                return Ok(());
+            }
+            *count += 1;
+            *count
+        };
+        (self.callback)(count, frame);
+        Ok(())
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.presence.desired_fps()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.presence.flush()
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.presence.on_start()
+    }
+
+    fn on_end(&self) -> Result<()> {
+        self.presence.on_end()
+    }
+}
+
+impl EdgeCountTrigger {
+    pub fn new<T, C>(presence: T, on_edge: C) -> Self
+    where
+        T: TypedTrigger<Output = bool> + 'static,
+        C: Fn(u64, &Frame) + Send + Sync + 'static,
+    {
+        Self {
+            presence: Arc::new(presence),
+            callback: Arc::new(on_edge),
+            state: Arc::new(Mutex::new((false, 0))),
+        }
+    }
+
+    /// Number of rising edges seen so far
+    pub fn count(&self) -> Result<u64> {
+        Ok(self.state.lock()?.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorPresenceTrigger, EdgeCountTrigger};
+    use crate::{
+        error::Result,
+        photon::{Anchor, Crop, ThresholdFilter},
+        trigger::{Frame, Trigger},
+        typed_trigger::TypedTrigger,
+    };
+    use image::{Rgb, RgbImage};
+    use std::sync::{Arc, Mutex};
+
+    /// Reports a scripted presence for each frame number
+    struct ScriptedPresence(Vec<bool>);
+
+    impl TypedTrigger for ScriptedPresence {
+        type Output = bool;
+
+        fn process(&self, frame: &Frame) -> Result<bool> {
+            Ok(self.0[frame.frame_num as usize])
+        }
+    }
+
+    #[test]
+    fn edge_count() -> Result<()> {
+        let presence = ScriptedPresence(vec![false, true, true, false, true]);
+        let edges = Arc::new(Mutex::new(Vec::new()));
+        let edges_clone = edges.clone();
+        let trigger = EdgeCountTrigger::new(presence, move |count, frame| {
+            edges_clone.lock().unwrap().push((count, frame.frame_num));
+        });
+
+        for frame_num in 0..5 {
+            trigger.on_frame(&Frame::new(RgbImage::new(1, 1), frame_num, 0.0))?;
+        }
+
+        assert_eq!(trigger.count()?, 2);
+        assert_eq!(*edges.lock()?, vec![(1, 1), (2, 4)]);
+        Ok(())
+    }
+
+    #[test]
+    fn color_presence() -> Result<()> {
+        // Red right half
+        let image = RgbImage::from_fn(8, 4, |x, _| {
+            if x < 4 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 0, 0])
+            }
+        });
+        let frame = Frame::new(image, 0, 0.0);
+        let mut presence = ColorPresenceTrigger {
+            filter: ThresholdFilter {
+                r: 255,
+                g: 0,
+                b: 0,
+                threshold: 10,
+                ..Default::default()
+            },
+            region: None,
+            min_fraction: 0.75,
+        };
+        assert!(!presence.process(&frame)?);
+
+        presence.region = Some(Crop {
+            left_percent: 0.0,
+            top_percent: 0.0,
+            width_percent: 50.0,
+            height_percent: 100.0,
+            anchor: Anchor::TopRight,
+        });
+        assert!(presence.process(&frame)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+#[cfg(feature = "photon")]
+use serde::{Deserialize, Serialize};
+use std::cmp::min;
+#[cfg(feature = "photon")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// The corner (or center) of the image that a `Crop`'s offsets are measured
+/// from, e.g. `BottomRight` for UI elements pinned to the bottom right corner
+/// across different resolutions.
+#[cfg_attr(feature = "photon", wasm_bindgen)]
+#[derive(Debug, Default, Hash, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "photon", derive(Serialize, Deserialize))]
+pub enum Anchor {
+    /// `left_percent` and `top_percent` are the offsets of the region's top
+    /// left corner from the image's top left corner
+    #[default]
+    TopLeft,
+
+    /// `left_percent` and `top_percent` are the offsets of the region's top
+    /// right corner from the image's top right corner
+    TopRight,
+
+    /// `left_percent` and `top_percent` are the offsets of the region's bottom
+    /// left corner from the image's bottom left corner
+    BottomLeft,
+
+    /// `left_percent` and `top_percent` are the offsets of the region's bottom
+    /// right corner from the image's bottom right corner
+    BottomRight,
+
+    /// `left_percent` and `top_percent` are the offsets of the region's center
+    /// from the image's center
+    Center,
+}
+
+/// A region of an image, as percentages of its size, so the same region can
+/// be used across resolutions.
+#[cfg_attr(feature = "photon", wasm_bindgen)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "photon", derive(Serialize, Deserialize))]
+pub struct Crop {
+    pub left_percent: f64,
+    pub top_percent: f64,
+    pub width_percent: f64,
+    pub height_percent: f64,
+
+    /// Where `left_percent` and `top_percent` are measured from. Defaults to
+    /// `TopLeft`.
+    #[cfg_attr(feature = "photon", serde(default))]
+    pub anchor: Anchor,
+}
+
+impl Crop {
+    /// The `(x1, y1, x2, y2)` pixel coordinates of the region in an image of
+    /// the given size, where `(x2, y2)` is exclusive.
+    pub fn bounds(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let width = width as f64;
+        let height = height as f64;
+        let left = self.left_percent * width / 100.0;
+        let top = self.top_percent * height / 100.0;
+        let region_width = self.width_percent * width / 100.0;
+        let region_height = self.height_percent * height / 100.0;
+        let (x1, y1) = match self.anchor {
+            Anchor::TopLeft => (left, top),
+            Anchor::TopRight => (width - left - region_width, top),
+            Anchor::BottomLeft => (left, height - top - region_height),
+            Anchor::BottomRight => (width - left - region_width, height - top - region_height),
+            Anchor::Center => (
+                (width - region_width) / 2.0 + left,
+                (height - region_height) / 2.0 + top,
+            ),
+        };
+        let x1 = x1.clamp(0.0, width) as u32;
+        let y1 = y1.clamp(0.0, height) as u32;
+        let x2 = min((x1 as f64 + region_width) as u32, width as u32);
+        let y2 = min((y1 as f64 + region_height) as u32, height as u32);
+        (x1, y1, x2, y2)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Crop {
+    /// Check that the region fits inside the frame: each percentage is
+    /// between 0 and 100 (offsets from the center, with `Anchor::Center`, can
+    /// be negative), the size isn't zero, and the offset plus the size is at
+    /// most 100 (twice the offset plus the size, with `Anchor::Center`). The
+    /// error names the offending field.
+    pub fn validate(&self) -> crate::error::Result<()> {
+        let fields = [
+            ("left_percent", self.left_percent),
+            ("top_percent", self.top_percent),
+            ("width_percent", self.width_percent),
+            ("height_percent", self.height_percent),
+        ];
+        for (name, value) in fields {
+            let min = match (self.anchor, name) {
+                (Anchor::Center, "left_percent" | "top_percent") => -100.0,
+                _ => 0.0,
+            };
+            if !value.is_finite() || value < min || value > 100.0 {
+                return Err(format!(
+                    "Invalid crop: {} is {}, expected a value from {} to 100",
+                    name, value, min
+                )
+                .into());
+            }
+        }
+        for (name, value) in [
+            ("width_percent", self.width_percent),
+            ("height_percent", self.height_percent),
+        ] {
+            if value == 0.0 {
+                return Err(format!("Invalid crop: {} is 0", name).into());
+            }
+        }
+        let extents = [
+            (
+                "left_percent",
+                self.left_percent,
+                "width_percent",
+                self.width_percent,
+            ),
+            (
+                "top_percent",
+                self.top_percent,
+                "height_percent",
+                self.height_percent,
+            ),
+        ];
+        for (offset_name, offset, size_name, size) in extents {
+            // The region extends half its size either side of the offset center
+            if self.anchor == Anchor::Center && offset.abs() * 2.0 + size > 100.0 {
+                return Err(format!(
+                    "Invalid crop: {} ({}) centered {} ({}) from the center goes past the edge",
+                    size_name, size, offset_name, offset
+                )
+                .into());
+            }
+            if self.anchor != Anchor::Center && offset + size > 100.0 {
+                return Err(format!(
+                    "Invalid crop: {} ({}) plus {} ({}) is more than 100",
+                    offset_name, offset, size_name, size
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anchor, Crop};
+
+    #[test]
+    fn crop_validate() {
+        let crop = Crop {
+            left_percent: 10.0,
+            top_percent: 20.0,
+            width_percent: 90.0,
+            height_percent: 50.0,
+            anchor: Anchor::TopLeft,
+        };
+        assert!(crop.validate().is_ok());
+        assert!(Crop {
+            left_percent: -20.0,
+            width_percent: 60.0,
+            anchor: Anchor::Center,
+            ..crop
+        }
+        .validate()
+        .is_ok());
+
+        let invalid = [
+            (
+                Crop {
+                    width_percent: 200.0,
+                    ..crop
+                },
+                "width_percent",
+            ),
+            (
+                Crop {
+                    top_percent: -5.0,
+                    ..crop
+                },
+                "top_percent",
+            ),
+            (
+                Crop {
+                    height_percent: 0.0,
+                    ..crop
+                },
+                "height_percent",
+            ),
+            (
+                Crop {
+                    left_percent: f64::NAN,
+                    ..crop
+                },
+                "left_percent",
+            ),
+            (
+                Crop {
+                    top_percent: 60.0,
+                    ..crop
+                },
+                "top_percent (60) plus height_percent (50)",
+            ),
+            (
+                Crop {
+                    left_percent: 30.0,
+                    width_percent: 50.0,
+                    anchor: Anchor::Center,
+                    ..crop
+                },
+                "width_percent (50) centered left_percent (30) from the center",
+            ),
+        ];
+        for (crop, field) in invalid {
+            let error = crop.validate().unwrap_err();
+            assert!(error.message.contains(field), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn crop_anchor_bottom_right() {
+        let crop = Crop {
+            left_percent: 0.0,
+            top_percent: 0.0,
+            width_percent: 10.0,
+            height_percent: 10.0,
+            anchor: Anchor::BottomRight,
+        };
+        // Lands flush with the bottom right corner, including the last pixel
+        assert_eq!(crop.bounds(1280, 720), (1152, 648, 1280, 720));
+        assert_eq!(crop.bounds(1920, 1080), (1728, 972, 1920, 1080));
+
+        // Offsets are measured inward from the anchored corner
+        let inset = Crop {
+            left_percent: 5.0,
+            top_percent: 10.0,
+            ..crop
+        };
+        assert_eq!(inset.bounds(1280, 720), (1088, 576, 1216, 648));
+        assert_eq!(inset.bounds(1920, 1080), (1632, 864, 1824, 972));
+    }
+}
@@ -24,7 +24,7 @@ pub fn debug_frame(frame: &Frame) -> Result<()> {
     println!(
         "[debug] Execution paused on frame {} ({})",
         frame.frame_num,
-        format_seconds(frame.timestamp)
+        format_seconds(frame.timestamp, None)
     );
     debug_rgb(&frame.image)
 }
@@ -40,3 +40,15 @@ pub fn debug_photon_image(image: &photon_rs::PhotonImage) -> Result<()> {
     let dynamic_image = photon_rs::helpers::dyn_image_from_raw(image);
     debug_image(&dynamic_image)
 }
+
+/// Write image to the given path (creating its directory if needed), without
+/// pausing execution.
+#[cfg(feature = "photon")]
+pub fn save_photon_image(image: &photon_rs::PhotonImage, path: &std::path::Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let dynamic_image = photon_rs::helpers::dyn_image_from_raw(image);
+    dynamic_image.save(path)?;
+    Ok(())
+}
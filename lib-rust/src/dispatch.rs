@@ -0,0 +1,379 @@
+use crate::error::{Error, Result, StopPipeline};
+use crate::trigger::{Frame, Trigger};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// A fixed number of worker threads that run `PooledTrigger`s, so that slow
+/// triggers don't stall the thread reading frames from ffmpeg. See
+/// `Hypetrigger::set_parallel_triggers`.
+///
+/// The queue in front of the workers is bounded, and so is each trigger's
+/// queue of frames: once a trigger's queue is full, dispatching another frame
+/// to it blocks (and in turn ffmpeg pauses) until it catches up.
+pub struct DispatchPool {
+    pub tx: SyncSender<DispatchCommand>,
+    pub workers: Vec<JoinHandle<()>>,
+
+    /// Number of frames each trigger may have waiting to run
+    pub capacity: usize,
+
+    /// The earliest frame that any trigger on this pool returned
+    /// `StopPipeline` from, or `u64::MAX`
+    stop_at: Arc<AtomicU64>,
+}
+
+impl DispatchPool {
+    /// Spawn `workers` threads, with queues that hold one pending job or frame
+    /// per worker.
+    pub fn spawn(workers: usize) -> Self {
+        Self::spawn_with_capacity(workers, workers)
+    }
+
+    /// Spawn `workers` threads, with queues that hold at most `capacity` jobs
+    /// that no worker has picked up yet, and `capacity` frames per trigger.
+    pub fn spawn_with_capacity(workers: usize, capacity: usize) -> Self {
+        let (tx, rx) = sync_channel::<DispatchCommand>(capacity);
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || run_worker(&rx))
+            })
+            .collect();
+        Self {
+            tx,
+            workers,
+            capacity: capacity.max(1),
+            stop_at: Arc::new(AtomicU64::new(u64::MAX)),
+        }
+    }
+
+    /// Wrap a trigger so that each frame passed to it runs on this pool.
+    pub fn wrap(&self, trigger: Arc<dyn Trigger>) -> PooledTrigger {
+        PooledTrigger {
+            trigger,
+            tx: self.tx.clone(),
+            capacity: self.capacity,
+            queue: Arc::new(TriggerQueue::default()),
+            stop_at: self.stop_at.clone(),
+        }
+    }
+
+    /// Stop every worker once it has finished the jobs queued before this
+    /// call, and wait for them to exit.
+    pub fn stop(self) -> Result<()> {
+        for _ in &self.workers {
+            self.tx
+                .send(DispatchCommand::Stop)
+                .map_err(|_| Error::from_display("dispatch pool stopped"))?;
+        }
+        for worker in self.workers {
+            worker.join().map_err(|e| format!("{:?}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Take jobs off the shared queue until told to stop.
+fn run_worker(rx: &Mutex<Receiver<DispatchCommand>>) {
+    loop {
+        let command = match rx.lock() {
+            Ok(rx) => rx.recv(),
+            Err(_) => return,
+        };
+        match command {
+            Ok(DispatchCommand::Job(job)) => job(),
+            Ok(DispatchCommand::Stop) | Err(_) => return,
+        }
+    }
+}
+
+/// A command sent over a channel to the workers of a `DispatchPool`
+pub enum DispatchCommand {
+    /// Tell the worker that receives it to exit
+    Stop,
+
+    /// Tell the worker that receives it to run a job
+    Job(Box<dyn FnOnce() + Send>),
+}
+
+/// The frames waiting to be run by a single `PooledTrigger`
+#[derive(Default)]
+struct TriggerQueue {
+    state: Mutex<QueueState>,
+
+    /// Notified whenever a frame is taken off the queue, or it runs dry
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    frames: VecDeque<Frame>,
+
+    /// Whether a job is draining `frames` on one of the workers
+    running: bool,
+
+    /// The first error from the inner trigger that hasn't been returned yet
+    failed: Option<Error>,
+}
+
+/// A wrapper around any other Trigger that runs each frame on the workers of
+/// a `DispatchPool`, instead of on the thread reading from ffmpeg.
+///
+/// Ordering: each trigger has its own queue of frames, which a single job on
+/// the pool works through in order, one frame at a time. Different triggers
+/// run concurrently and may be working on different frames, and a slow
+/// trigger only holds up its own queue (and, once that's full, the frames
+/// after it). Errors are returned from the next call to `on_frame` or `flush`.
+///
+/// A `StopPipeline` takes effect from the frame that returned it: no trigger on
+/// the same pool runs any later frame, and `on_frame` returns `StopPipeline`
+/// for them instead.
+#[derive(Clone)]
+pub struct PooledTrigger {
+    pub trigger: Arc<dyn Trigger>,
+    tx: SyncSender<DispatchCommand>,
+
+    /// Number of frames that may wait in the queue
+    capacity: usize,
+    queue: Arc<TriggerQueue>,
+
+    /// Shared with the `DispatchPool` and every other trigger on it
+    stop_at: Arc<AtomicU64>,
+}
+
+impl PooledTrigger {
+    /// Whether a trigger on the same pool stopped the pipeline before `frame`.
+    fn is_stopped(&self, frame: &Frame) -> bool {
+        is_stopped(&self.stop_at, frame)
+    }
+
+    /// Send a job to the pool that runs the queued frames until there are none
+    /// left. Only one such job runs per trigger at a time.
+    fn schedule(&self) -> Result<()> {
+        let trigger = self.trigger.clone();
+        let queue = self.queue.clone();
+        let stop_at = self.stop_at.clone();
+        let job = move || run_queue(trigger.as_ref(), &queue, &stop_at);
+        if self.tx.send(DispatchCommand::Job(Box::new(job))).is_err() {
+            self.queue.state.lock()?.running = false;
+            self.queue.changed.notify_all();
+            return Err(Error::from_display("dispatch pool stopped"));
+        }
+        Ok(())
+    }
+}
+
+impl Trigger for PooledTrigger {
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        if self.is_stopped(frame) {
+            return Err(StopPipeline.into());
+        }
+        let mut state = self.queue.state.lock()?;
+        if let Some(e) = state.failed.take() {
+            return Err(e);
+        }
+        while state.frames.len() >= self.capacity {
+            state = self.queue.changed.wait(state)?;
+        }
+        state.frames.push_back(frame.clone());
+        if state.running {
+            return Ok(());
+        }
+        state.running = true;
+        drop(state);
+        self.schedule()
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.trigger.desired_fps()
+    }
+
+    /// Wait for every frame dispatched so far to finish, then flush the inner
+    /// trigger. Returns any error from those frames that hasn't been returned
+    /// yet, other than `StopPipeline`.
+    fn flush(&self) -> Result<()> {
+        let mut state = self.queue.state.lock()?;
+        while state.running {
+            state = self.queue.changed.wait(state)?;
+        }
+        let failed = state.failed.take();
+        drop(state);
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        self.trigger.flush()
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.trigger.on_start()
+    }
+
+    /// Wait for every dispatched frame to finish, then end the inner trigger.
+    fn on_end(&self) -> Result<()> {
+        let flushed = self.flush();
+        let ended = self.trigger.on_end();
+        flushed.and(ended)
+    }
+}
+
+/// Whether `frame` comes after the one a trigger returned `StopPipeline` from.
+fn is_stopped(stop_at: &AtomicU64, frame: &Frame) -> bool {
+    frame.frame_num > stop_at.load(Ordering::SeqCst)
+}
+
+/// Run `trigger` on the frames in its `queue`, in order, until the queue is
+/// empty. Frames after a `StopPipeline` are dropped without running.
+fn run_queue(trigger: &dyn Trigger, queue: &TriggerQueue, stop_at: &AtomicU64) {
+    loop {
+        let frame = match queue.state.lock() {
+            Ok(mut state) => match state.frames.pop_front() {
+                Some(frame) => frame,
+                None => {
+                    state.running = false;
+                    queue.changed.notify_all();
+                    return;
+                }
+            },
+            Err(_) => return,
+        };
+        queue.changed.notify_all();
+        if is_stopped(stop_at, &frame) {
+            continue;
+        }
+        match trigger.on_frame(&frame) {
+            Ok(()) => {}
+            Err(e) if e.is::<StopPipeline>() => {
+                stop_at.fetch_min(frame.frame_num, Ordering::SeqCst);
+            }
+            Err(e) => {
+                if let Ok(mut state) = queue.state.lock() {
+                    state.failed.get_or_insert(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DispatchPool;
+    use crate::{
+        error::{Result, StopPipeline},
+        simple_trigger::SimpleTrigger,
+        trigger::{Frame, Trigger},
+    };
+    use image::RgbImage;
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn ordered_per_trigger() -> Result<()> {
+        let pool = DispatchPool::spawn(4);
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let trigger = pool.wrap(Arc::new(SimpleTrigger::new(move |frame| {
+            // Later frames finish faster, so any reordering would show up
+            thread::sleep(Duration::from_millis(20 - frame.frame_num));
+            frames_clone.lock().unwrap().push(frame.frame_num);
+        })));
+
+        for frame_num in 0..10 {
+            trigger.on_frame(&Frame::new(RgbImage::new(1, 1), frame_num, 0.0))?;
+        }
+        trigger.flush()?;
+        pool.stop()?;
+
+        assert_eq!(*frames.lock()?, (0..10).collect::<Vec<u64>>());
+        Ok(())
+    }
+
+    #[test]
+    fn slow_trigger_runs_alone() -> Result<()> {
+        // The first trigger holds on to frame 0 until the second has finished
+        let pool = DispatchPool::spawn(2);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        let slow = pool.wrap(Arc::new(SimpleTrigger::new(move |_| {
+            release_rx.lock().unwrap().recv().ok();
+        })));
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let fast = pool.wrap(Arc::new(SimpleTrigger::new(move |frame| {
+            frames_clone.lock().unwrap().push(frame.frame_num);
+        })));
+
+        slow.on_frame(&Frame::new(RgbImage::new(1, 1), 0, 0.0))?;
+        for frame_num in 0..10 {
+            fast.on_frame(&Frame::new(RgbImage::new(1, 1), frame_num, 0.0))?;
+        }
+        fast.flush()?;
+        assert_eq!(*frames.lock()?, (0..10).collect::<Vec<u64>>());
+
+        drop(release_tx);
+        slow.flush()?;
+        pool.stop()
+    }
+
+    #[test]
+    fn stop_on_frame() -> Result<()> {
+        struct Stopper;
+        impl Trigger for Stopper {
+            fn on_frame(&self, frame: &Frame) -> Result<()> {
+                match frame.frame_num {
+                    1 => Err(StopPipeline.into()),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let pool = DispatchPool::spawn(1);
+        let stopper = pool.wrap(Arc::new(Stopper));
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let other = pool.wrap(Arc::new(SimpleTrigger::new(move |frame| {
+            frames_clone.lock().unwrap().push(frame.frame_num);
+        })));
+
+        for frame_num in 0..2 {
+            let frame = Frame::new(RgbImage::new(1, 1), frame_num, 0.0);
+            stopper.on_frame(&frame)?;
+            other.on_frame(&frame)?;
+        }
+        stopper.flush()?;
+
+        // Every trigger refuses the frames after the one that stopped
+        let frame = Frame::new(RgbImage::new(1, 1), 2, 0.0);
+        assert!(stopper.on_frame(&frame).unwrap_err().is::<StopPipeline>());
+        assert!(other.on_frame(&frame).unwrap_err().is::<StopPipeline>());
+        other.flush()?;
+        assert_eq!(*frames.lock()?, vec![0, 1]);
+        pool.stop()
+    }
+
+    #[test]
+    fn deferred_error() -> Result<()> {
+        struct Failing;
+        impl Trigger for Failing {
+            fn on_frame(&self, _frame: &Frame) -> Result<()> {
+                Err("failed".into())
+            }
+        }
+
+        let pool = DispatchPool::spawn(1);
+        let trigger = pool.wrap(Arc::new(Failing));
+        trigger.on_frame(&Frame::new(RgbImage::new(1, 1), 0, 0.0))?;
+        assert_eq!(trigger.flush().unwrap_err().message, "failed");
+        pool.stop()
+    }
+}
@@ -3,14 +3,14 @@ use std::fmt::{Display, Formatter};
 use std::io;
 use std::sync::mpsc::SendError;
 use std::sync::PoisonError;
+use std::time::Duration;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub struct Error {
     pub message: String,
-    pub source: Option<Box<dyn StdError + 'static>>,
-    // Would an `Arc` be nice here, to cross thread boundaries?
+    pub source: Option<Box<dyn StdError + Send + Sync + 'static>>,
 }
 
 impl Display for Error {
@@ -21,16 +21,21 @@ impl Display for Error {
 
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
-        self.source.as_deref()
+        match &self.source {
+            Some(source) => Some(source.as_ref()),
+            None => None,
+        }
     }
 }
 
 impl Error {
     /// Wrap any standard Error into a library Error.
     /// Similar to [`anyhow`](https://github.com/dtolnay/anyhow/blob/master/src/error.rs#L88).
+    /// The source must be `Send + Sync`, so that results can cross thread
+    /// boundaries (e.g. from `run_async`).
     pub fn from_std<E>(e: E) -> Self
     where
-        E: std::error::Error + 'static,
+        E: std::error::Error + Send + Sync + 'static,
     {
         Error {
             message: e.to_string(),
@@ -38,6 +43,18 @@ impl Error {
         }
     }
 
+    /// Check whether this Error was caused by a specific error type, e.g.
+    /// `error.is::<TimedOut>()`.
+    pub fn is<E>(&self) -> bool
+    where
+        E: StdError + 'static,
+    {
+        match &self.source {
+            Some(source) => source.is::<E>(),
+            None => false,
+        }
+    }
+
     /// Wrap any Display into a library Error.
     pub fn from_display<E>(e: E) -> Self
     where
@@ -70,6 +87,81 @@ impl From<NoneError> for Error {
     }
 }
 
+/// Represents ffmpeg going longer than the configured timeout without
+/// producing a frame.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub timeout: Duration,
+}
+impl Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "timed out waiting {:?} for a frame from ffmpeg",
+            self.timeout
+        )
+    }
+}
+impl std::error::Error for TimedOut {}
+
+impl From<TimedOut> for Error {
+    fn from(e: TimedOut) -> Self {
+        Error::from_std(e)
+    }
+}
+
+/// Represents ffmpeg exiting unsuccessfully, with the last lines it logged as
+/// errors (which usually explain why, e.g. a missing input file).
+#[derive(Debug)]
+pub struct FfmpegFailed {
+    /// Exit code of the ffmpeg process, or `None` if it was killed by a signal
+    pub exit_code: Option<i32>,
+
+    /// The last few error messages logged by ffmpeg, oldest first
+    pub stderr: Vec<String>,
+}
+impl Display for FfmpegFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.exit_code {
+            Some(code) => write!(f, "ffmpeg exited with code {}", code)?,
+            None => write!(f, "ffmpeg was terminated by a signal")?,
+        }
+        for line in &self.stderr {
+            write!(f, "\n[ffmpeg] {}", line)?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for FfmpegFailed {}
+
+impl From<FfmpegFailed> for Error {
+    fn from(e: FfmpegFailed) -> Self {
+        Error::from_std(e)
+    }
+}
+
+/// Returned by a Trigger's `on_frame` to stop the whole pipeline, e.g. once
+/// the first detection is all that's needed. The pipeline tells ffmpeg to quit
+/// and `run()` returns `Ok`, as if the input had ended.
+///
+/// ```rs
+/// return Err(StopPipeline.into());
+/// ```
+#[derive(Debug)]
+pub struct StopPipeline;
+impl Display for StopPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a trigger requested the pipeline to stop")
+    }
+}
+impl std::error::Error for StopPipeline {}
+
+impl From<StopPipeline> for Error {
+    fn from(e: StopPipeline) -> Self {
+        Error::from_std(e)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         Error::from_std(e)
@@ -78,7 +170,8 @@ impl From<io::Error> for Error {
 
 impl From<ffmpeg_sidecar::error::Error> for Error {
     fn from(e: ffmpeg_sidecar::error::Error) -> Self {
-        Error::from_std(e)
+        // Its source isn't `Send + Sync`, so only the message is kept
+        Error::from_display(e)
     }
 }
 
@@ -88,7 +181,7 @@ impl From<image::ImageError> for Error {
     }
 }
 
-impl<T: Send + 'static> From<SendError<T>> for Error {
+impl<T: Send + Sync + 'static> From<SendError<T>> for Error {
     fn from(e: SendError<T>) -> Self {
         Error::from_std(e)
     }
@@ -102,6 +195,13 @@ impl<T> From<PoisonError<T>> for Error {
     }
 }
 
+#[cfg(feature = "photon")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::from_std(e)
+    }
+}
+
 impl From<String> for Error {
     fn from(e: String) -> Self {
         Error::from_display(e)
@@ -142,9 +242,23 @@ impl From<tesseract::plumbing::TessBaseApiGetUtf8TextError> for Error {
     }
 }
 
+#[cfg(feature = "tesseract")]
+impl From<tesseract::plumbing::TessBaseApiGetHocrTextError> for Error {
+    fn from(e: tesseract::plumbing::TessBaseApiGetHocrTextError) -> Self {
+        Error::from_std(e)
+    }
+}
+
 #[cfg(feature = "tensorflow")]
 impl From<tensorflow::Status> for Error {
     fn from(e: tensorflow::Status) -> Self {
         Error::from_std(e)
     }
 }
+
+#[cfg(feature = "onnx")]
+impl From<ort::Error> for Error {
+    fn from(e: ort::Error) -> Self {
+        Error::from_std(e)
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::{self, Display};
+
+/// Builder for an ffmpeg video filter chain (the `-filter:v` argument), for
+/// when the stages need to run in a specific order. Each stage is applied in
+/// the order it was added, e.g. `FilterChain::new().fps(2).crop(...).scale(...)`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilterChain {
+    /// Each filter in the chain, e.g. `fps=2` or `scale=640:360`
+    pub stages: Vec<String>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sample the video at the given framerate (`fps=N`)
+    pub fn fps(self, fps: u64) -> Self {
+        self.custom(&format!("fps={}", fps))
+    }
+
+    /// Crop to a `width`x`height` rectangle with its top left corner at
+    /// (`x`, `y`), in pixels (`crop=w:h:x:y`)
+    pub fn crop(self, width: u32, height: u32, x: u32, y: u32) -> Self {
+        self.custom(&format!("crop={}:{}:{}:{}", width, height, x, y))
+    }
+
+    /// Resize to `width`x`height` pixels (`scale=w:h`). Either dimension can
+    /// be `-1` to preserve the aspect ratio.
+    pub fn scale(self, width: i32, height: i32) -> Self {
+        self.custom(&format!("scale={}:{}", width, height))
+    }
+
+    /// Adjust contrast and brightness (`eq=contrast=c:brightness=b`). ffmpeg's
+    /// defaults are `1.0` and `0.0` respectively.
+    pub fn eq(self, contrast: f32, brightness: f32) -> Self {
+        self.custom(&format!(
+            "eq=contrast={}:brightness={}",
+            contrast, brightness
+        ))
+    }
+
+    /// Only keep frames that differ from the previous one by more than
+    /// `threshold`, from `0.0` (identical) to `1.0` (completely different)
+    /// (`select='gt(scene,threshold)'`)
+    pub fn scene_change(self, threshold: f64) -> Self {
+        self.custom(&format!("select='gt(scene,{})'", threshold))
+    }
+
+    /// Only keep the first frame at or after each of the given timestamps, in
+    /// seconds (`select='gte(t,T1)*(isnan(prev_t)+lt(prev_t,T1))+...'`)
+    pub fn select_timestamps(self, timestamps: &[f64]) -> Self {
+        let conditions: Vec<String> = timestamps
+            .iter()
+            .map(|t| format!("gte(t,{0})*(isnan(prev_t)+lt(prev_t,{0}))", t))
+            .collect();
+        self.custom(&format!("select='{}'", conditions.join("+")))
+    }
+
+    /// Log information about each frame, including its timestamp (`showinfo`)
+    pub fn showinfo(self) -> Self {
+        self.custom("showinfo")
+    }
+
+    /// Append any other ffmpeg filter verbatim, e.g. `hflip` or `format=gray`
+    pub fn custom(mut self, filter: &str) -> Self {
+        self.stages.push(filter.to_string());
+        self
+    }
+
+    /// Whether the chain includes an `fps` stage. Without one, every frame of
+    /// the input is passed to the triggers.
+    pub fn has_fps(&self) -> bool {
+        self.stages.iter().any(|stage| stage.starts_with("fps="))
+    }
+}
+
+impl Display for FilterChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.stages.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FilterChain;
+
+    #[test]
+    fn filter_chain() {
+        assert_eq!(
+            FilterChain::new().scene_change(0.3).showinfo().to_string(),
+            "select='gt(scene,0.3)',showinfo"
+        );
+
+        let filter = FilterChain::new()
+            .fps(4)
+            .crop(1280, 720, 320, 180)
+            .eq(1.5, -0.1)
+            .scale(640, -1);
+        assert_eq!(
+            filter.to_string(),
+            "fps=4,crop=1280:720:320:180,eq=contrast=1.5:brightness=-0.1,scale=640:-1"
+        );
+        assert!(filter.has_fps());
+        assert!(!FilterChain::new().scale(640, 360).has_fps());
+
+        assert_eq!(
+            FilterChain::new().select_timestamps(&[1.0, 2.5]).to_string(),
+            "select='gte(t,1)*(isnan(prev_t)+lt(prev_t,1))+gte(t,2.5)*(isnan(prev_t)+lt(prev_t,2.5))'"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+pub type InterruptCallback = Box<dyn Fn() + Send>;
+
+/// The runs currently listening for Ctrl-C, by id (along with the thread
+/// that registered them), and whether the process handler has been installed
+/// (`None` until `install_handler` is first called).
+#[derive(Default)]
+struct Interrupts {
+    installed: Option<bool>,
+    next_id: u64,
+    callbacks: HashMap<u64, (ThreadId, InterruptCallback)>,
+}
+
+lazy_static! {
+    static ref INTERRUPTS: Mutex<Interrupts> = Mutex::new(Interrupts::default());
+}
+
+/// Registration of a callback with `on_interrupt`, which stops listening for
+/// Ctrl-C once dropped (e.g. when the run ends normally).
+pub struct InterruptGuard {
+    id: u64,
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if let Ok(mut interrupts) = INTERRUPTS.lock() {
+            interrupts.callbacks.remove(&self.id);
+        }
+    }
+}
+
+/// Call `callback` on `interrupt`, until the returned guard is dropped. Used
+/// by the pipeline to tell ffmpeg to quit and finish the run cleanly, instead
+/// of the process exiting and leaving ffmpeg orphaned.
+///
+/// Nothing calls `interrupt` on Ctrl-C unless the process handler was
+/// installed with `install_handler` (see `Hypetrigger::set_handle_ctrlc`), or
+/// the application calls it from its own handler.
+pub fn on_interrupt<F>(callback: F) -> InterruptGuard
+where
+    F: Fn() + Send + 'static,
+{
+    let mut interrupts = INTERRUPTS.lock().unwrap_or_else(|e| e.into_inner());
+    let id = interrupts.next_id;
+    interrupts.next_id += 1;
+    interrupts
+        .callbacks
+        .insert(id, (thread::current().id(), Box::new(callback)));
+    InterruptGuard { id }
+}
+
+/// Install a process-wide Ctrl-C (SIGINT) handler that calls `interrupt`, if
+/// it isn't already. A Ctrl-C while no callbacks are registered exits the
+/// process, as it would without a handler. The handler can't be removed, so
+/// the application can't install its own afterwards. Returns whether it's
+/// installed, i.e. `false` if the application already has a handler.
+pub fn install_handler() -> bool {
+    let mut interrupts = INTERRUPTS.lock().unwrap_or_else(|e| e.into_inner());
+    *interrupts
+        .installed
+        .get_or_insert_with(|| ctrlc::set_handler(handle_ctrlc).is_ok())
+}
+
+/// Stop every run listening for Ctrl-C, as if it had been pressed. Returns the
+/// number of runs that were stopped.
+pub fn interrupt() -> usize {
+    let interrupts = INTERRUPTS.lock().unwrap_or_else(|e| e.into_inner());
+    for (_, callback) in interrupts.callbacks.values() {
+        callback();
+    }
+    interrupts.callbacks.len()
+}
+
+/// Like `interrupt`, but only for the callbacks registered from the given
+/// thread, so that tests can simulate Ctrl-C without stopping each other.
+#[cfg(test)]
+pub(crate) fn interrupt_thread(thread_id: ThreadId) -> usize {
+    let interrupts = INTERRUPTS.lock().unwrap_or_else(|e| e.into_inner());
+    let callbacks = interrupts
+        .callbacks
+        .values()
+        .filter(|(id, _)| *id == thread_id);
+    callbacks.map(|(_, callback)| callback()).count()
+}
+
+fn handle_ctrlc() {
+    if interrupt() == 0 {
+        std::process::exit(130); // 128 + SIGINT, like the default handler
+    }
+}
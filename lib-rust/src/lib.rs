@@ -3,6 +3,9 @@
 extern crate lazy_static;
 
 //// Image processing modules
+// Regions of an image, used by both photon and `Frame`-based triggers
+pub mod crop;
+
 // Required for tesseract/tensorflow, but can be skipped for simple or custom
 // triggers. Also required for wasm builds and provides a rich image library.
 #[cfg(feature = "photon")]
@@ -18,28 +21,84 @@ pub mod tesseract;
 #[cfg(feature = "tensorflow")]
 pub mod tensorflow;
 
+#[cfg(feature = "onnx")]
+pub mod onnx;
+
+#[cfg(all(feature = "photon", not(target_arch = "wasm32")))]
+pub mod template;
+
+#[cfg(all(feature = "photon", not(target_arch = "wasm32")))]
+pub mod regions;
+
+#[cfg(all(feature = "photon", not(target_arch = "wasm32")))]
+pub mod change_gate;
+
+#[cfg(all(feature = "photon", not(target_arch = "wasm32")))]
+pub mod color_presence;
+
+// Shared by the image classification triggers (tensorflow, onnx)
+#[cfg(any(feature = "tensorflow", feature = "onnx"))]
+pub mod prediction;
+
 //// Core functionality
 // Not WASM-safe; intended for Rust usage only. Involves spawning and attaching to ffmpeg processes.
+// Always compiled, even with only the `core` feature, so nothing here may depend on photon.
 #[cfg(not(target_arch = "wasm32"))]
 pub mod async_trigger;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod audio;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod brightness;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod debug;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dispatch;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod error;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod filter;
+
+#[cfg(all(feature = "ctrlc", not(target_arch = "wasm32")))]
+pub mod interrupt;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod pipeline;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod simple_trigger;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod stateful_trigger;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod subtitle;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tee;
+
+#[cfg(all(not(target_arch = "wasm32"), any(test, feature = "testing")))]
+pub mod test_harness;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod trigger;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod typed_trigger;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod util;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod windowed_trigger;
+
 //// Re-exports
 pub use ffmpeg_sidecar as ffmpeg;
+
+#[cfg(feature = "photon")]
+pub use threshold::{delta_e, delta_e_tuple, rgb2lab, rgb2lab_tuple, Lab};
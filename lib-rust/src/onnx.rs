@@ -0,0 +1,202 @@
+use crate::{
+    error::{NoneError, Result},
+    photon::{ensure_size, ensure_square, rgba32_to_gray8, rgba32_to_rgb24, Crop, Transform},
+    prediction::{normalize_pixels, predictions_from_output, Prediction},
+    trigger::{Frame, Trigger},
+};
+use ort::{
+    session::{builder::GraphOptimizationLevel, Session},
+    value::Tensor,
+};
+use photon_rs::PhotonImage;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Side length of the square image that the model expects
+pub const TENSOR_SIZE: u32 = 224;
+
+/// Color channels expected (RGB)
+pub const TENSOR_CHANNELS: u32 = 3;
+
+pub type OnnxTriggerCallback = Arc<dyn Fn(OnnxResult) + Send + Sync>;
+
+/// Runs an image classification model in ONNX format on each frame, as an
+/// alternative to `TensorflowTrigger` that doesn't need the TensorFlow C
+/// library. The model should take a single `[1, 224, 224, channels]` input
+/// (NHWC, normalized to `[0, 1]`) and output a confidence for each class.
+pub struct OnnxTrigger {
+    /// Rotation or mirroring applied to the whole frame, before cropping.
+    pub transform: Option<Transform>,
+    pub crop: Option<Crop>,
+    pub session: Mutex<Session>,
+    pub callback: Option<OnnxTriggerCallback>,
+
+    /// Only run the callback when the prediction's confidence is at least this
+    /// value. `None` runs the callback on every frame.
+    pub min_confidence: Option<f32>,
+
+    /// Only run the callback when the predicted class is one of these indices.
+    /// `None` runs the callback for any class.
+    pub classes: Option<Vec<usize>>,
+
+    /// Number of color channels in the model's input tensor: `3` for RGB
+    /// (the default) or `1` for grayscale.
+    pub channels: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnnxResult {
+    pub prediction: Prediction,
+    pub timestamp: f64,
+    pub frame_num: u64,
+}
+
+impl Trigger for OnnxTrigger {
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        // 1. convert raw image to photon
+        let image = frame.to_photon();
+
+        // 2. preprocess
+        let filtered = self.preprocess_image(image)?;
+
+        // 3. image classification
+        let prediction = self.predict(&filtered)?;
+
+        // 4. callback
+        if !prediction.passes(self.min_confidence, self.classes.as_deref()) {
+            return Ok(());
+        }
+        if let Some(callback) = &self.callback {
+            let result = OnnxResult {
+                prediction,
+                timestamp: frame.timestamp,
+                frame_num: frame.frame_num,
+            };
+            callback(result);
+        }
+
+        Ok(())
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.crop.iter().try_for_each(Crop::validate)
+    }
+}
+
+impl OnnxTrigger {
+    pub fn new<P>(
+        model_path: P,
+        crop: Option<Crop>,
+        callback: Option<OnnxTriggerCallback>,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let session = load_onnx_model(model_path)?;
+        Ok(Self {
+            transform: None,
+            crop,
+            session: Mutex::new(session),
+            callback,
+            min_confidence: None,
+            classes: None,
+            channels: TENSOR_CHANNELS,
+        })
+    }
+
+    /// Crop and resize the frame to the model's input size.
+    pub fn preprocess_image(&self, mut image: PhotonImage) -> Result<PhotonImage> {
+        if let Some(transform) = &self.transform {
+            image = transform.apply(image);
+        }
+
+        if let Some(crop) = &self.crop {
+            image = crop.apply(image);
+        }
+
+        image = ensure_square(image);
+        image = ensure_size(image, TENSOR_SIZE, TENSOR_SIZE);
+
+        debug_assert!(image.get_width() == TENSOR_SIZE);
+        debug_assert!(image.get_height() == TENSOR_SIZE);
+
+        Ok(image)
+    }
+
+    /// Run the model on a preprocessed image.
+    pub fn predict(&self, image: &PhotonImage) -> Result<Prediction> {
+        let rgba32 = image.get_raw_pixels();
+        let pixels = match self.channels {
+            1 => rgba32_to_gray8(rgba32),
+            _ => rgba32_to_rgb24(rgba32),
+        };
+        let tensor = buffer_to_tensor(&pixels, self.channels)?;
+
+        let mut session = self.session.lock()?;
+        let outputs = session.run(ort::inputs![tensor])?;
+        let (_shape, confidences) = outputs[0].try_extract_tensor::<f32>()?;
+        let prediction = predictions_from_output(confidences, 1)
+            .into_iter()
+            .next()
+            .ok_or(NoneError)?;
+        Ok(prediction)
+    }
+}
+
+/// Load an ONNX model from a file, ready to run with `OnnxTrigger`.
+pub fn load_onnx_model<P>(model_path: P) -> Result<Session>
+where
+    P: AsRef<Path>,
+{
+    println!("[onnx] Loading model");
+    let now = Instant::now();
+
+    let session = Session::builder()?
+        .with_optimization_level(GraphOptimizationLevel::Level3)?
+        .commit_from_file(model_path)?;
+
+    println!("[onnx] load_onnx_model {}ms.", now.elapsed().as_millis());
+    Ok(session)
+}
+
+/// Convert interleaved 8-bit pixels (`channels` bytes per pixel) to a
+/// normalized `[1, H, W, channels]` tensor. Fails if the buffer isn't the
+/// size of a `TENSOR_SIZE` square image.
+pub fn buffer_to_tensor(buf: &[u8], channels: u32) -> Result<Tensor<f32>> {
+    let normalized = normalize_pixels(buf, TENSOR_SIZE as u64, channels as u64)?;
+    let shape = [
+        1,
+        TENSOR_SIZE as usize,
+        TENSOR_SIZE as usize,
+        channels as usize,
+    ];
+    Ok(Tensor::from_array((shape, normalized))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OnnxTrigger, TENSOR_SIZE};
+    use crate::error::Result;
+    use photon_rs::PhotonImage;
+
+    /// A tiny "classifier" whose confidences are the mean of each of the R, G
+    /// and B channels of the input
+    const MEAN_RGB_MODEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/mean-rgb.onnx");
+
+    #[test]
+    fn onnx_prediction() -> Result<()> {
+        let trigger = OnnxTrigger::new(MEAN_RGB_MODEL, None, None)?;
+
+        // Solid blue image
+        let pixels = [0, 0, 255, 255].repeat((TENSOR_SIZE * TENSOR_SIZE) as usize);
+        let image = PhotonImage::new(pixels, TENSOR_SIZE, TENSOR_SIZE);
+        let prediction = trigger.predict(&trigger.preprocess_image(image)?)?;
+
+        assert_eq!(prediction.class_index, 2);
+        assert!((prediction.confidence - 1.0).abs() < 1e-6);
+        Ok(())
+    }
+}
@@ -1,61 +1,211 @@
-use image::RgbImage;
+use image::{DynamicImage, ImageOutputFormat, RgbImage, RgbaImage};
 use photon_rs::{
-    transform::{crop, resize, SamplingFilter},
-    PhotonImage, Rgb,
+    transform::{crop, padding_uniform, resize, SamplingFilter},
+    PhotonImage, Rgb, Rgba,
 };
 use std::cmp::min;
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::threshold::threshold_color_distance_rgba;
+pub use crate::crop::{Anchor, Crop};
+use crate::threshold::{
+    adaptive_threshold, threshold_color_distance_rgba_with_metric, DistanceMetric,
+};
 
-/// A threshold function based on perceptual color distance
+/// A threshold function based on perceptual color distance. New fields may be
+/// added, so fill the rest in with `..Default::default()` (or use `new`).
 #[wasm_bindgen]
-#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, Default, Hash, PartialEq, Eq, Clone, Copy)]
 pub struct ThresholdFilter {
     pub r: u8,
     pub g: u8,
     pub b: u8,
     pub threshold: u8,
+
+    /// Formula used to measure color distance. Defaults to `DeltaE`, which is
+    /// faster than `DeltaE2000`.
+    pub metric: DistanceMetric,
 }
 
 #[wasm_bindgen]
 impl ThresholdFilter {
+    /// Match colors within `threshold` of `(r, g, b)`, using the default
+    /// `DistanceMetric`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(r: u8, g: u8, b: u8, threshold: u8) -> Self {
+        Self {
+            r,
+            g,
+            b,
+            threshold,
+            ..Default::default()
+        }
+    }
+
     pub fn apply(&self, image: PhotonImage) -> PhotonImage {
         let color = Rgb::new(self.r, self.g, self.b);
-        let raw_pixels =
-            threshold_color_distance_rgba(image.get_raw_pixels(), &color, self.threshold as f64);
+        let raw_pixels = threshold_color_distance_rgba_with_metric(
+            image.get_raw_pixels(),
+            &color,
+            self.threshold as f64,
+            self.metric,
+        );
         PhotonImage::new(raw_pixels, image.get_width(), image.get_height())
     }
+
+    /// The fraction (`0.0..=1.0`) of pixels within `threshold` of the filter
+    /// color, i.e. the pixels `apply` would turn black. Cheaper than building
+    /// the full mask, e.g. for a live preview while tuning the threshold.
+    pub fn match_fraction(&self, image: &PhotonImage) -> f64 {
+        let color = (self.r, self.g, self.b);
+        let raw_pixels = image.get_raw_pixels();
+        let num_pixels = raw_pixels.len() / 4;
+        if num_pixels == 0 {
+            return 0.0;
+        }
+        let matches = raw_pixels
+            .chunks_exact(4)
+            .filter(|pixel| {
+                self.metric.distance((pixel[0], pixel[1], pixel[2]), color) < self.threshold as f64
+            })
+            .count();
+        matches as f64 / num_pixels as f64
+    }
 }
 
+/// A threshold function based on the mean brightness of each pixel's
+/// neighborhood, see `adaptive_threshold`.
+#[wasm_bindgen]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct AdaptiveThresholdFilter {
+    pub block_size: u32,
+    pub c: i32,
+}
+
+#[wasm_bindgen]
+impl AdaptiveThresholdFilter {
+    pub fn apply(&self, image: PhotonImage) -> PhotonImage {
+        adaptive_threshold(&image, self.block_size, self.c)
+    }
+}
+
+/// A linear contrast and brightness adjustment, applied to each color channel
+/// as `(value - 128) * contrast + 128 + brightness`, clamped to `0..=255`.
 #[wasm_bindgen]
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Crop {
-    pub left_percent: f64,
-    pub top_percent: f64,
-    pub width_percent: f64,
-    pub height_percent: f64,
+pub struct ContrastFilter {
+    pub contrast: f32,
+    pub brightness: i32,
+}
+
+#[wasm_bindgen]
+impl ContrastFilter {
+    pub fn apply(&self, image: PhotonImage) -> PhotonImage {
+        let mut raw_pixels = image.get_raw_pixels();
+        for pixel in raw_pixels.chunks_exact_mut(4) {
+            for channel in pixel.iter_mut().take(3) {
+                let value = (*channel as f32 - 128.0) * self.contrast + 128.0;
+                *channel = (value + self.brightness as f32).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        PhotonImage::new(raw_pixels, image.get_width(), image.get_height())
+    }
 }
 
 #[wasm_bindgen]
 impl Crop {
     pub fn apply(&self, mut image: PhotonImage) -> PhotonImage {
-        let width = image.get_width() as f64;
-        let height = image.get_height() as f64;
-        let x1 = (width * (self.left_percent / 100.0)) as u32;
-        let y1 = (height * (self.top_percent / 100.0)) as u32;
-        let x2 = min(
-            (x1 as f64 + (self.width_percent * width / 100.0)) as u32,
-            width as u32 - 1,
-        );
-        let y2 = min(
-            (y1 as f64 + (self.height_percent * height / 100.0)) as u32,
-            height as u32 - 1,
-        );
+        let (x1, y1, x2, y2) = self.bounds(image.get_width(), image.get_height());
         crop(&mut image, x1, y1, x2, y2)
     }
 }
 
+/// Crop an image and then apply a threshold filter, i.e. the preprocessing
+/// done for OCR, in a single call (e.g. for a live preview in the browser).
+#[wasm_bindgen]
+pub fn process_image(image: PhotonImage, crop: Crop, filter: ThresholdFilter) -> PhotonImage {
+    filter.apply(crop.apply(image))
+}
+
+/// A rotation or mirroring of the whole image, e.g. to correct for a rotated
+/// phone capture before cropping.
+#[wasm_bindgen]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum Transform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Transform {
+    pub fn apply(&self, image: PhotonImage) -> PhotonImage {
+        match self {
+            Transform::Rotate90 => rotate90(image),
+            Transform::Rotate180 => rotate180(image),
+            Transform::Rotate270 => rotate270(image),
+            Transform::FlipHorizontal => flip_horizontal(image),
+            Transform::FlipVertical => flip_vertical(image),
+        }
+    }
+}
+
+/// Build a new image by looking up each of its pixels in the source image.
+/// `source_coords` maps destination `(x, y)` to source `(x, y)`.
+fn remap<F>(image: &PhotonImage, width: u32, height: u32, source_coords: F) -> PhotonImage
+where
+    F: Fn(u32, u32) -> (u32, u32),
+{
+    let src = image.get_raw_pixels();
+    let src_width = image.get_width();
+    let mut raw_pixels = Vec::with_capacity(src.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (src_x, src_y) = source_coords(x, y);
+            let i = ((src_y * src_width + src_x) * 4) as usize;
+            raw_pixels.extend_from_slice(&src[i..i + 4]);
+        }
+    }
+    PhotonImage::new(raw_pixels, width, height)
+}
+
+/// Rotate 90 degrees clockwise
+#[wasm_bindgen]
+pub fn rotate90(image: PhotonImage) -> PhotonImage {
+    let (width, height) = (image.get_width(), image.get_height());
+    remap(&image, height, width, |x, y| (y, height - 1 - x))
+}
+
+/// Rotate 180 degrees
+#[wasm_bindgen]
+pub fn rotate180(image: PhotonImage) -> PhotonImage {
+    let (width, height) = (image.get_width(), image.get_height());
+    remap(&image, width, height, |x, y| {
+        (width - 1 - x, height - 1 - y)
+    })
+}
+
+/// Rotate 270 degrees clockwise (90 degrees counter-clockwise)
+#[wasm_bindgen]
+pub fn rotate270(image: PhotonImage) -> PhotonImage {
+    let (width, height) = (image.get_width(), image.get_height());
+    remap(&image, height, width, |x, y| (width - 1 - y, x))
+}
+
+/// Mirror left-to-right
+#[wasm_bindgen]
+pub fn flip_horizontal(image: PhotonImage) -> PhotonImage {
+    let (width, height) = (image.get_width(), image.get_height());
+    remap(&image, width, height, |x, y| (width - 1 - x, y))
+}
+
+/// Mirror top-to-bottom
+#[wasm_bindgen]
+pub fn flip_vertical(image: PhotonImage) -> PhotonImage {
+    let (width, height) = (image.get_width(), image.get_height());
+    remap(&image, width, height, |x, y| (x, height - 1 - y))
+}
+
 /// Resize if needed and reserve aspect ratio
 #[wasm_bindgen]
 pub fn ensure_minimum_size(image: &PhotonImage, min_size: u32) -> PhotonImage {
@@ -117,6 +267,67 @@ pub fn ensure_size(image: PhotonImage, width: u32, height: u32) -> PhotonImage {
     }
 }
 
+/// Resize to fit inside the given dimensions, preserving aspect ratio, and pad
+/// the remaining space on either side with `pad` (i.e. letterboxing). There's
+/// nothing to fit into a zero `width` or `height`, so that gives an empty image.
+#[wasm_bindgen]
+pub fn ensure_size_letterbox(
+    image: PhotonImage,
+    width: u32,
+    height: u32,
+    pad: Rgba,
+) -> PhotonImage {
+    if width == 0 || height == 0 {
+        return PhotonImage::new(Vec::new(), width, height);
+    }
+    let scale = f64::min(
+        width as f64 / image.get_width() as f64,
+        height as f64 / image.get_height() as f64,
+    );
+    let fit_width = ((image.get_width() as f64 * scale).round() as u32).clamp(1, width);
+    let fit_height = ((image.get_height() as f64 * scale).round() as u32).clamp(1, height);
+    let image = ensure_size(image, fit_width, fit_height);
+    if fit_width == width && fit_height == height {
+        return image;
+    }
+
+    // Pad every side by enough for the short dimension, then crop the long
+    // dimension back down, keeping the image centered
+    let padding = (width - fit_width).max(height - fit_height).div_ceil(2);
+    let mut padded = padding_uniform(&image, padding, pad);
+    let x1 = padding - (width - fit_width) / 2;
+    let y1 = padding - (height - fit_height) / 2;
+    crop(&mut padded, x1, y1, x1 + width, y1 + height)
+}
+
+/// How to fit an image to the fixed, square input size of a classifier
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Crop the largest centered square, then resize it. Anything outside the
+    /// square is lost.
+    #[default]
+    CenterCrop,
+
+    /// Resize to the exact size, distorting the aspect ratio
+    Stretch,
+
+    /// Resize to fit, preserving the aspect ratio, with black bars on either
+    /// side. See `ensure_size_letterbox`.
+    Letterbox,
+}
+
+impl ResizeMode {
+    pub fn apply(&self, image: PhotonImage, width: u32, height: u32) -> PhotonImage {
+        match self {
+            ResizeMode::CenterCrop => ensure_size(ensure_square(image), width, height),
+            ResizeMode::Stretch => ensure_size(image, width, height),
+            ResizeMode::Letterbox => {
+                ensure_size_letterbox(image, width, height, Rgba::new(0, 0, 0, 255))
+            }
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub fn rgb24_to_rgba32(vec: Vec<u8>) -> Vec<u8> {
     let mut new_vec = Vec::with_capacity(vec.len() * 4 / 3);
@@ -140,6 +351,18 @@ pub fn rgba32_to_rgb24(vec: Vec<u8>) -> Vec<u8> {
     new_vec
 }
 
+/// Convert RGBA pixels to single-channel 8-bit luma, using the same (Rec. 709)
+/// weights as the `image` crate. Alpha is ignored.
+#[wasm_bindgen]
+pub fn rgba32_to_gray8(vec: Vec<u8>) -> Vec<u8> {
+    let mut new_vec = Vec::with_capacity(vec.len() / 4);
+    for pixel in vec.chunks_exact(4) {
+        let luma = pixel[0] as u32 * 2126 + pixel[1] as u32 * 7152 + pixel[2] as u32 * 722;
+        new_vec.push(((luma + 5000) / 10000) as u8);
+    }
+    new_vec
+}
+
 /// Convert an `RgbImage` (`image` crate) to a `PhotonImage` (`photon-rs` crate)
 pub fn rgb_to_photon(rgb: &RgbImage) -> PhotonImage {
     let rgb24 = rgb.to_vec();
@@ -147,3 +370,520 @@ pub fn rgb_to_photon(rgb: &RgbImage) -> PhotonImage {
 
     PhotonImage::new(rgb32, rgb.width(), rgb.height())
 }
+
+/// Convert an `RgbaImage` (`image` crate) to a `PhotonImage`, preserving the
+/// alpha channel (e.g. for transparent overlays)
+pub fn rgba_to_photon(rgba: &RgbaImage) -> PhotonImage {
+    PhotonImage::new(rgba.to_vec(), rgba.width(), rgba.height())
+}
+
+/// Convert a `PhotonImage` (`photon-rs` crate) back to an `RgbImage` (`image`
+/// crate), dropping the alpha channel. The inverse of `rgb_to_photon`.
+pub fn photon_to_rgb(image: &PhotonImage) -> RgbImage {
+    let rgb24 = rgba32_to_rgb24(image.get_raw_pixels());
+    RgbImage::from_raw(image.get_width(), image.get_height(), rgb24)
+        .expect("photon image buffer matches its dimensions")
+}
+
+/// Convert a `PhotonImage` to a `DynamicImage`, e.g. to save it or run other
+/// `image` crate algorithms on it.
+pub fn photon_to_dynamic(image: &PhotonImage) -> DynamicImage {
+    DynamicImage::ImageRgb8(photon_to_rgb(image))
+}
+
+/// Encode an image as PNG, keeping the alpha channel, e.g. to send a preview
+/// over a socket or to a web view instead of saving it to disk.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn encode_photon_png(image: &PhotonImage) -> crate::error::Result<Vec<u8>> {
+    let rgba = RgbaImage::from_raw(
+        image.get_width(),
+        image.get_height(),
+        image.get_raw_pixels(),
+    )
+    .ok_or("Photon image buffer doesn't match its dimensions")?;
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgba8(rgba).write_to(&mut bytes, ImageOutputFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Encode an image as JPEG, at a `quality` from 1 to 100, dropping the alpha
+/// channel. Smaller than `encode_photon_png` for photographic frames.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn encode_photon_jpeg(image: &PhotonImage, quality: u8) -> crate::error::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    photon_to_dynamic(image)
+        .write_to(&mut bytes, ImageOutputFormat::Jpeg(quality.clamp(1, 100)))?;
+    Ok(bytes)
+}
+
+/// Per-channel histogram of an image, as pixel counts for each of the 256
+/// possible values of the red, green, and blue channels (in that order).
+/// Alpha is ignored.
+pub fn histogram(image: &PhotonImage) -> [[u32; 256]; 3] {
+    let mut histogram = [[0u32; 256]; 3];
+    for pixel in image.get_raw_pixels().chunks_exact(4) {
+        histogram[0][pixel[0] as usize] += 1;
+        histogram[1][pixel[1] as usize] += 1;
+        histogram[2][pixel[2] as usize] += 1;
+    }
+    histogram
+}
+
+/// Calculate the average brightness of an image, returned as a float between
+/// 0 (solid black) and 1 (solid white).
+#[wasm_bindgen]
+pub fn average_brightness(image: &PhotonImage) -> f64 {
+    let raw_pixels = image.get_raw_pixels();
+    let num_pixels = raw_pixels.len() / 4;
+    if num_pixels == 0 {
+        return 0.0;
+    }
+
+    let mut sum = 0.0;
+    for pixel in raw_pixels.chunks_exact(4) {
+        let r = pixel[0] as f64;
+        let g = pixel[1] as f64;
+        let b = pixel[2] as f64;
+        sum += (r / 255.0 + g / 255.0 + b / 255.0) / 3.0;
+    }
+    sum / num_pixels as f64
+}
+
+/// Lookup table for `gamma`, mapping each 8-bit value `v` to
+/// `255 * (v / 255)^(1 / gamma)`.
+pub fn gamma_lut(gamma: f32) -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    for (value, out) in lut.iter_mut().enumerate() {
+        let normalized = value as f32 / 255.0;
+        *out = (255.0 * normalized.powf(1.0 / gamma))
+            .round()
+            .clamp(0.0, 255.0) as u8;
+    }
+    lut
+}
+
+/// Gamma correction, applied to each color channel (alpha is unchanged).
+/// Values above 1 brighten the shadows of dark footage more than the
+/// highlights, unlike the linear `ContrastFilter`; 1 leaves the image as-is.
+#[wasm_bindgen]
+pub fn gamma(image: &PhotonImage, gamma: f32) -> PhotonImage {
+    let lut = gamma_lut(gamma);
+    let mut raw_pixels = image.get_raw_pixels();
+    for pixel in raw_pixels.chunks_exact_mut(4) {
+        for channel in pixel.iter_mut().take(3) {
+            *channel = lut[*channel as usize];
+        }
+    }
+    PhotonImage::new(raw_pixels, image.get_width(), image.get_height())
+}
+
+/// Reduce each color channel (alpha is unchanged) to `levels` evenly spaced
+/// values, rounding to the nearest one, e.g. `0`, `85`, `170` and `255` for 4
+/// levels. Flattens the anti-aliased edges of text and the noise of busy
+/// backgrounds before OCR. Fewer than 2 levels are treated as 2.
+#[wasm_bindgen]
+pub fn posterize(image: &PhotonImage, levels: u8) -> PhotonImage {
+    let steps = levels.max(2) as f32 - 1.0;
+    let mut lut = [0u8; 256];
+    for (value, out) in lut.iter_mut().enumerate() {
+        let level = (value as f32 * steps / 255.0).round();
+        *out = (level * 255.0 / steps).round() as u8;
+    }
+    let mut raw_pixels = image.get_raw_pixels();
+    for pixel in raw_pixels.chunks_exact_mut(4) {
+        for channel in pixel.iter_mut().take(3) {
+            *channel = lut[*channel as usize];
+        }
+    }
+    PhotonImage::new(raw_pixels, image.get_width(), image.get_height())
+}
+
+/// Find regions of an image that likely contain text of the `filter` color,
+/// e.g. to pick the `Crop` for a `TesseractTrigger` from a screenshot, sorted
+/// top to bottom, then left to right.
+///
+/// Pixels that match the filter (see `ThresholdFilter::match_fraction`) are
+/// joined into blocks, bridging the small gaps between characters and words,
+/// and each block's bounding box is kept if it looks like text: at least 4
+/// pixels tall, not covering most of the image, and neither empty nor solid
+/// (glyphs leave gaps, unlike UI panels or backgrounds).
+pub fn detect_text_regions(image: &PhotonImage, filter: &ThresholdFilter) -> Vec<Crop> {
+    let width = image.get_width() as usize;
+    let height = image.get_height() as usize;
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let color = (filter.r, filter.g, filter.b);
+    let mask: Vec<bool> = image
+        .get_raw_pixels()
+        .chunks_exact(4)
+        .map(|pixel| {
+            filter
+                .metric
+                .distance((pixel[0], pixel[1], pixel[2]), color)
+                < filter.threshold as f64
+        })
+        .collect();
+
+    // Widen each matching pixel into a small box, so that the glyphs of a
+    // line of text touch each other
+    let gap_x = (width / 50).max(2);
+    let gap_y = (height / 100).max(1);
+    let joined = dilate(&mask, width, height, gap_x, gap_y);
+
+    // Flood fill each block, measuring the bounding box of its matching pixels
+    let mut visited = vec![false; width * height];
+    let mut regions = Vec::new();
+    for start in 0..width * height {
+        if !joined[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![start];
+        let (mut x1, mut y1, mut x2, mut y2) = (width, height, 0, 0);
+        let mut matched = 0;
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            if mask[i] {
+                matched += 1;
+                (x1, y1, x2, y2) = (x1.min(x), y1.min(y), x2.max(x), y2.max(y));
+            }
+            let neighbors = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+            for j in neighbors.into_iter().flatten() {
+                if joined[j] && !visited[j] {
+                    visited[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        if matched == 0 {
+            continue;
+        }
+
+        let (region_width, region_height) = (x2 - x1 + 1, y2 - y1 + 1);
+        let fill = matched as f64 / (region_width * region_height) as f64;
+        let coverage = (region_width * region_height) as f64 / (width * height) as f64;
+        if region_height < 4 || coverage > 0.5 || !(0.05..=0.9).contains(&fill) {
+            continue;
+        }
+        regions.push(Crop {
+            left_percent: x1 as f64 * 100.0 / width as f64,
+            top_percent: y1 as f64 * 100.0 / height as f64,
+            width_percent: region_width as f64 * 100.0 / width as f64,
+            height_percent: region_height as f64 * 100.0 / height as f64,
+            anchor: Anchor::TopLeft,
+        });
+    }
+    regions.sort_by(|a, b| {
+        a.top_percent
+            .total_cmp(&b.top_percent)
+            .then(a.left_percent.total_cmp(&b.left_percent))
+    });
+    regions
+}
+
+/// Widen every `true` pixel of a `width` x `height` mask into a box reaching
+/// `gap_x` pixels left and right and `gap_y` up and down. Done one axis at a
+/// time with a running count, so it takes the same time whatever the gaps.
+fn dilate(mask: &[bool], width: usize, height: usize, gap_x: usize, gap_y: usize) -> Vec<bool> {
+    let mut rows = vec![false; width * height];
+    for y in 0..height {
+        dilate_line(mask, &mut rows, y * width, 1, width, gap_x);
+    }
+    let mut joined = vec![false; width * height];
+    for x in 0..width {
+        dilate_line(&rows, &mut joined, x, width, height, gap_y);
+    }
+    joined
+}
+
+/// Dilate the `len` pixels starting at `start`, `stride` apart, from `mask`
+/// into `out`, by `gap` pixels either side.
+fn dilate_line(
+    mask: &[bool],
+    out: &mut [bool],
+    start: usize,
+    stride: usize,
+    len: usize,
+    gap: usize,
+) {
+    let at = |i: usize| mask[start + i * stride] as usize;
+    // Matches in the window `i - gap..=i + gap`, starting with `0..gap`
+    let mut count: usize = (0..gap.min(len)).map(at).sum();
+    for i in 0..len {
+        if i + gap < len {
+            count += at(i + gap);
+        }
+        if i > gap {
+            count -= at(i - gap - 1);
+        }
+        out[start + i * stride] = count > 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        average_brightness, detect_text_regions, dilate, ensure_size_letterbox, gamma, gamma_lut,
+        histogram, photon_to_rgb, posterize, process_image, rgb_to_photon, rgba_to_photon,
+        rotate180, rotate270, rotate90, Anchor, ContrastFilter, Crop, ThresholdFilter,
+    };
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+    use photon_rs::PhotonImage;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn solid_image(value: u8, width: u32, height: u32) -> PhotonImage {
+        let mut raw_pixels = Vec::new();
+        for _ in 0..(width * height) {
+            raw_pixels.extend_from_slice(&[value, value, value, 255]);
+        }
+        PhotonImage::new(raw_pixels, width, height)
+    }
+
+    #[test]
+    fn brightness_black() {
+        let image = solid_image(0, 4, 4);
+        assert_eq!(average_brightness(&image), 0.0);
+        assert_eq!(histogram(&image)[0][0], 16);
+    }
+
+    #[test]
+    fn brightness_white() {
+        let image = solid_image(255, 4, 4);
+        assert_eq!(average_brightness(&image), 1.0);
+        assert_eq!(histogram(&image)[2][255], 16);
+    }
+
+    #[test]
+    fn letterbox() {
+        // 2:1 white image, fit into a black-padded square
+        let image = solid_image(255, 8, 4);
+        let boxed = ensure_size_letterbox(image, 8, 8, photon_rs::Rgba::new(0, 0, 0, 255));
+        assert_eq!((boxed.get_width(), boxed.get_height()), (8, 8));
+
+        let rows: Vec<u8> = boxed
+            .get_raw_pixels()
+            .chunks(8 * 4)
+            .map(|row| row[0])
+            .collect();
+        assert_eq!(rows, vec![0, 0, 255, 255, 255, 255, 0, 0]);
+
+        let empty = ensure_size_letterbox(
+            solid_image(255, 8, 4),
+            0,
+            8,
+            photon_rs::Rgba::new(0, 0, 0, 255),
+        );
+        assert_eq!((empty.get_width(), empty.get_height()), (0, 8));
+        assert!(empty.get_raw_pixels().is_empty());
+    }
+
+    #[test]
+    fn rotate() {
+        // 2x1 image: red pixel on the left, blue pixel on the right
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let image = PhotonImage::new([red, blue].concat(), 2, 1);
+
+        let rotated = rotate90(image.clone());
+        assert_eq!((rotated.get_width(), rotated.get_height()), (1, 2));
+        assert_eq!(rotated.get_raw_pixels(), [red, blue].concat());
+
+        let rotated = rotate270(image.clone());
+        assert_eq!((rotated.get_width(), rotated.get_height()), (1, 2));
+        assert_eq!(rotated.get_raw_pixels(), [blue, red].concat());
+
+        let rotated = rotate180(image);
+        assert_eq!((rotated.get_width(), rotated.get_height()), (2, 1));
+        assert_eq!(rotated.get_raw_pixels(), [blue, red].concat());
+    }
+
+    #[test]
+    fn contrast() {
+        let filter = ContrastFilter {
+            contrast: 2.0,
+            brightness: 10,
+        };
+        let image = PhotonImage::new(vec![100, 128, 250, 255], 1, 1);
+        let adjusted = filter.apply(image);
+
+        // (100 - 128) * 2 + 128 + 10 = 82
+        // (128 - 128) * 2 + 128 + 10 = 138
+        // (250 - 128) * 2 + 128 + 10 = 382, clamped to 255
+        assert_eq!(adjusted.get_raw_pixels(), vec![82, 138, 255, 255]);
+    }
+
+    #[test]
+    fn gamma_correction() {
+        // 255 * (128 / 255)^(1 / 2.2) = 186.4
+        assert_eq!(gamma_lut(2.2)[128], 186);
+        assert_eq!(gamma_lut(2.2)[0], 0);
+        assert_eq!(gamma_lut(2.2)[255], 255);
+
+        let image = PhotonImage::new(vec![0, 64, 128, 200, 255, 30, 90, 255], 2, 1);
+        assert_eq!(gamma(&image, 1.0).get_raw_pixels(), image.get_raw_pixels());
+        assert_eq!(gamma(&image, 2.2).get_raw_pixels()[2], 186);
+        // Alpha is unchanged
+        assert_eq!(gamma(&image, 2.2).get_raw_pixels()[3], 200);
+    }
+
+    #[test]
+    fn posterize_levels() {
+        let image = PhotonImage::new(vec![42, 43, 100, 200, 130, 255, 0, 255], 2, 1);
+        let posterized = posterize(&image, 4).get_raw_pixels();
+        // Nearest of 0, 85, 170 and 255, with alpha unchanged
+        assert_eq!(posterized, vec![0, 85, 85, 200, 170, 255, 0, 255]);
+        assert_eq!(posterize(&image, 2).get_raw_pixels()[..3], [0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn encode() -> crate::error::Result<()> {
+        use super::{encode_photon_jpeg, encode_photon_png};
+
+        let image = PhotonImage::new([255, 0, 0, 128].repeat(4 * 4), 4, 4);
+
+        let png = encode_photon_png(&image)?;
+        assert_eq!(
+            png[..8],
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']
+        );
+        let decoded = image::load_from_memory(&png)?.to_rgba8();
+        assert_eq!(decoded.as_raw(), &image.get_raw_pixels());
+
+        let jpeg = encode_photon_jpeg(&image, 80)?;
+        assert_eq!(jpeg[..3], [0xff, 0xd8, 0xff]);
+        Ok(())
+    }
+
+    #[test]
+    fn rgb_round_trip() {
+        let rgb = RgbImage::from_fn(4, 3, |x, y| Rgb([x as u8 * 60, y as u8 * 80, 200]));
+        let photon = rgb_to_photon(&rgb);
+        assert_eq!(photon_to_rgb(&photon), rgb);
+    }
+
+    #[test]
+    fn crop_preserves_alpha() {
+        // Opaque image with a transparent top left corner
+        let rgba = RgbaImage::from_fn(8, 8, |x, y| {
+            let alpha = if x < 2 && y < 2 { 0 } else { 255 };
+            Rgba([255, 0, 0, alpha])
+        });
+        let crop = Crop {
+            left_percent: 0.0,
+            top_percent: 0.0,
+            width_percent: 50.0,
+            height_percent: 50.0,
+            anchor: Anchor::TopLeft,
+        };
+        let cropped = crop.apply(rgba_to_photon(&rgba));
+        let alpha: Vec<u8> = cropped
+            .get_raw_pixels()
+            .chunks_exact(4)
+            .map(|px| px[3])
+            .collect();
+        assert_eq!(alpha[0], 0);
+        assert_eq!(alpha[cropped.get_width() as usize + 1], 0);
+        assert_eq!(alpha[alpha.len() - 1], 255);
+    }
+
+    #[test]
+    fn threshold_match_fraction() {
+        // White left half, black right half
+        let rgb = RgbImage::from_fn(8, 4, |x, _| {
+            if x < 4 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let filter = ThresholdFilter {
+            r: 255,
+            g: 255,
+            b: 255,
+            threshold: 42,
+            ..Default::default()
+        };
+        let fraction = filter.match_fraction(&rgb_to_photon(&rgb));
+        assert!((fraction - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dilate_box() {
+        // A single pixel becomes a box, clipped at the edges
+        let (width, height) = (6, 4);
+        let mut mask = vec![false; width * height];
+        mask[width + 1] = true;
+        let joined = dilate(&mask, width, height, 2, 1);
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(joined[y * width + x], x <= 3 && y <= 2, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn text_regions() {
+        // Two blocks of "glyphs" (2px wide bars, 2px apart) on black
+        let is_text = |x: u32, y: u32| {
+            let in_block = (20..60).contains(&x) && (10..20).contains(&y)
+                || (100..180).contains(&x) && (60..70).contains(&y);
+            in_block && x % 4 < 2
+        };
+        let image = RgbImage::from_fn(200, 100, |x, y| match is_text(x, y) {
+            true => Rgb([255, 255, 255]),
+            false => Rgb([0, 0, 0]),
+        });
+        let filter = ThresholdFilter {
+            r: 255,
+            g: 255,
+            b: 255,
+            threshold: 20,
+            ..Default::default()
+        };
+
+        let regions = detect_text_regions(&rgb_to_photon(&image), &filter);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].bounds(200, 100), (20, 10, 58, 20));
+        assert_eq!(regions[1].bounds(200, 100), (100, 60, 178, 70));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn process_image_crop_and_threshold() {
+        // White left half, black right half
+        let rgb = RgbImage::from_fn(8, 4, |x, _| {
+            if x < 4 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let crop = Crop {
+            left_percent: 0.0,
+            top_percent: 0.0,
+            width_percent: 75.0,
+            height_percent: 100.0,
+            anchor: Anchor::TopLeft,
+        };
+        let filter = ThresholdFilter {
+            r: 255,
+            g: 255,
+            b: 255,
+            threshold: 42,
+            ..Default::default()
+        };
+        let processed = photon_to_rgb(&process_image(rgb_to_photon(&rgb), crop, filter));
+        assert_eq!(processed.width(), 6);
+        // Close to the filter color becomes black, far from it becomes white
+        assert_eq!(processed.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(processed.get_pixel(5, 0), &Rgb([255, 255, 255]));
+    }
+}
@@ -1,19 +1,60 @@
+#[cfg(feature = "onnx")]
+use crate::onnx::OnnxResult;
+#[cfg(any(feature = "tensorflow", feature = "onnx"))]
+use crate::prediction::Prediction;
+#[cfg(feature = "tensorflow")]
+use crate::tensorflow::TensorflowResult;
+#[cfg(feature = "tesseract")]
+use crate::tesseract::TesseractResult;
 use crate::{
-    error::{Error, Result},
+    async_trigger::{AsyncTrigger, TriggerThread},
+    audio::{decode_s16le, AudioChunk, AudioTrigger, AUDIO_CHUNK_SAMPLES, AUDIO_SAMPLE_RATE},
+    dispatch::DispatchPool,
+    error::{Error, FfmpegFailed, Result, StopPipeline, TimedOut},
+    filter::FilterChain,
+    tee::TeeWriter,
     trigger::{Frame, Trigger},
+    util::{
+        parse_ffmpeg_dup_drop, parse_ffmpeg_fps, parse_ffmpeg_progress, parse_ffmpeg_progress_line,
+        parse_showinfo_frame, ProgressInfo,
+    },
 };
 use ffmpeg_sidecar::{
+    child::FfmpegChild,
     command::FfmpegCommand,
-    event::{FfmpegEvent, LogLevel},
+    event::{FfmpegEvent, LogLevel, OutputVideoFrame},
 };
-use image::RgbImage;
-use std::io::Write;
-use std::{process::ChildStdin, thread::JoinHandle};
+use image::{DynamicImage, GrayImage, ImageBuffer, Rgb, RgbImage, RgbaImage};
 use std::{
-    sync::Arc,
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    process::{ChildStdin, Command, Stdio},
+    str::FromStr,
+    thread::JoinHandle,
+};
+use std::{io::Write, path::PathBuf};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
     thread::{self},
+    time::{Duration, Instant},
+};
+#[cfg(feature = "tokio")]
+use tokio_util::sync::CancellationToken;
+#[cfg(feature = "ctrlc")]
+use {
+    crate::interrupt::{install_handler, on_interrupt},
+    std::sync::atomic::AtomicBool,
 };
 
+/// Cloning a Hypetrigger is shallow: the clone shares the same `triggers`
+/// (they're behind an `Arc`), along with any state they hold, such as a
+/// `TesseractTrigger`'s Tesseract instance. Running clones concurrently can
+/// interleave that state, so to process several inputs with the same
+/// configuration, use `run_each` to run them one after another.
 #[derive(Clone)]
 pub struct Hypetrigger {
     /// Path the the ffmpeg binary or command to use
@@ -22,6 +63,23 @@ pub struct Hypetrigger {
     /// Print all FFmpeg log output to stderr
     pub verbose: bool,
 
+    /// Log level for ffmpeg, e.g. `error` or `debug`. Corresponds to ffmpeg
+    /// `-loglevel` arg. When `None`, ffmpeg's default (`info`) is used.
+    /// `verbose` prints everything at the level, or info and above: quieter
+    /// levels are raised to `info` while it's enabled, and for `timestamps`
+    /// and `Sampling::SceneChange`, which read the frame timestamps from the
+    /// `info` logs (without printing them unless `verbose`).
+    ///
+    /// Note: `ffmpeg-sidecar` reads the output stream metadata from the `info`
+    /// level logs in order to decode frames, so levels quieter than `info` are
+    /// only useful for other purposes (e.g. `probe`-style runs).
+    pub ffmpeg_loglevel: Option<String>,
+
+    /// Hardware acceleration method used for decoding, e.g. `cuda`, `qsv`,
+    /// `videotoolbox`. Corresponds to ffmpeg `-hwaccel` arg. Defaults to
+    /// `auto`; `None` omits the argument and decodes in software.
+    pub hwaccel: Option<String>,
+
     /// Path to input video (or image) for ffmpeg. Corresponds to ffmpeg `-i` arg.
     pub input: String,
 
@@ -38,16 +96,148 @@ pub struct Hypetrigger {
     /// more than sufficient to capture most events.
     pub fps: u64,
 
+    /// Whether to lower `fps` to the input's native framerate when it's
+    /// higher, by probing the input before each run. Sampling faster than the
+    /// source makes ffmpeg's `fps` filter duplicate frames, so each detection
+    /// is repeated (at slightly different timestamps). When `false` (the
+    /// default), this is only warned about once ffmpeg reports the framerate.
+    /// Inputs that can only be read once (e.g. stdin) aren't probed, so they
+    /// only get the warning.
+    pub clamp_fps: bool,
+
+    /// Whether to sample the input at `fps` with ffmpeg's `fps` filter (the
+    /// default). When `false`, every frame ffmpeg decodes is passed on at the
+    /// input's native rate, and frame timestamps come from ffmpeg rather than
+    /// from `fps`. Set with `disable_fps_filter`.
+    pub fps_filter: bool,
+
+    /// How frames are selected from the input: at a fixed `fps` (the
+    /// default), or only when the scene changes.
+    pub sampling: Sampling,
+
+    /// Exact moments of the input to process, in seconds, instead of sampling
+    /// at `fps` or on scene changes. Each frame's `timestamp` is the latest
+    /// requested one at or before the frame. Set with `set_timestamps`, which
+    /// sorts them.
+    pub timestamps: Option<Vec<f64>>,
+
+    /// Number of sampled frames to discard at the start of the input (e.g.
+    /// decoder warm-up or a fade-in) before running any triggers. Skipped
+    /// frames still count towards `Frame::frame_num`. Unlike limiting the
+    /// output with `-frames:v`, this doesn't change when ffmpeg stops.
+    pub skip_frames: u64,
+
+    /// Skip frames that are identical to the previous frame, e.g. the copies
+    /// the `fps` filter inserts when sampling faster than the source, so the
+    /// triggers don't repeat a detection. Costs a copy and a comparison of
+    /// each frame. Skipped frames are counted in
+    /// `RunSummary::skipped_duplicates`.
+    pub skip_duplicate_frames: bool,
+
+    /// Index of the video stream to read (among the input's video streams,
+    /// i.e. `-map 0:v:<index>`), for files with more than one. `None` lets
+    /// ffmpeg pick, which is usually the first or the highest resolution.
+    pub video_stream: Option<usize>,
+
+    /// Resolution to downscale each frame to before it's passed to the
+    /// triggers. The frame still records the source resolution, see
+    /// `Frame::source_width`.
+    pub processing_scale: Option<(u32, u32)>,
+
+    /// Explicit video filter chain, replacing the one generated from `fps` and
+    /// `processing_scale` (which are then ignored by ffmpeg). It should include
+    /// an `fps` stage; `validate` warns if it doesn't.
+    pub filter: Option<FilterChain>,
+
+    /// Pixel format to request from ffmpeg for each frame. Defaults to 8-bit
+    /// `rgb24`; see `PixelFormat` for the alternatives.
+    pub pixel_format: PixelFormat,
+
+    /// How ffmpeg syncs the output frames to their timestamps (`-vsync`).
+    /// Defaults to `FpsMode::Drop`; see `FpsMode` for the effect on
+    /// `Frame::timestamp` with variable-framerate sources.
+    pub fps_mode: FpsMode,
+
+    /// Extra arguments passed through to ffmpeg verbatim. They're placed after
+    /// the input and the built-in output options, immediately before the
+    /// `rawvideo` output, so they apply to the output stream. For options that
+    /// apply to the input, see `input_args`.
+    pub ffmpeg_args: Vec<String>,
+
+    /// Extra arguments passed through to ffmpeg verbatim, immediately before
+    /// `-i`, so they apply to the input (e.g. `-loop 1` for a still image, or
+    /// `-r` for an image sequence).
+    pub input_args: Vec<String>,
+
+    /// Maximum time to wait for the next frame from ffmpeg before giving up,
+    /// killing ffmpeg and returning a `TimedOut` error. Guards against stalled
+    /// inputs like dead network streams. Time spent running triggers doesn't
+    /// count towards it. `None` waits indefinitely.
+    pub timeout: Option<Duration>,
+
+    /// Whether runs install a process-wide Ctrl-C handler (see
+    /// `interrupt::install_handler`), which tells ffmpeg to quit and ends the
+    /// run as if a trigger had returned `StopPipeline`. Off by default, since
+    /// the handler can't be removed; apps with their own handler can call
+    /// `interrupt::interrupt` from it instead.
+    #[cfg(feature = "ctrlc")]
+    pub handle_ctrlc: bool,
+
     /// List of all callback functions to run on each frame of the video
     pub triggers: Vec<Arc<dyn Trigger>>,
 
+    /// Triggers to run on a background `TriggerThread` instead of blocking the
+    /// pipeline. The thread is spawned at the start of each run, and stopped
+    /// (after finishing any queued frames) before the run returns. These run
+    /// after all of the `triggers` on each frame.
+    pub async_triggers: Vec<Arc<dyn Trigger>>,
+
+    /// Number of worker threads to run the `triggers` on, instead of on the
+    /// thread reading from ffmpeg. `None` (the default) runs them in turn on
+    /// each frame. Set with `set_parallel_triggers`; see `PooledTrigger` for
+    /// the ordering guarantees.
+    pub parallel_triggers: Option<usize>,
+
+    /// Triggers to run on each chunk of the input's audio. When non-empty,
+    /// ffmpeg outputs the audio stream instead of video, so it can't be
+    /// combined with (video) `triggers` in the same pipeline.
+    pub audio_triggers: Vec<Arc<dyn AudioTrigger>>,
+
+    /// A file or named pipe (FIFO) to copy the raw frames from ffmpeg to, for
+    /// another process to consume while the triggers run. Set with
+    /// `set_tee_output`; see `TeeWriter`.
+    pub tee_output: Option<PathBuf>,
+
+    /// A complete ffmpeg command to run instead of the one built from the
+    /// other settings (input, `fps`, filters, etc., which are then ignored).
+    /// Set with `with_command`.
+    pub custom_command: Option<CustomCommand>,
+
     /// Callback when the video is finished processing. Particularly useful in
     /// combination with `run_async`.
     pub on_complete_callback: Option<HypetriggerOnCompleteCallback>,
 
+    /// Callback when the video is finished processing, with statistics about
+    /// the run. Called after `on_complete_callback` if both are set.
+    pub on_complete_with_callback: Option<HypetriggerOnCompleteWithCallback>,
+
+    /// Callback when the run ends for any reason, with its summary or the
+    /// error that ended it. Unlike `on_complete_callback`, this also runs when
+    /// ffmpeg fails to start or exits with an error, e.g. to release resources.
+    pub on_finish_callback: Option<HypetriggerOnFinishCallback>,
+
     /// Callback on every item of the inner `ffmpeg_sidecar` iterator.
     /// Note: output frames are omitted for memory performance reasons.
     pub on_event_callback: Option<HypetriggerOnFfmpegEventCallback>,
+
+    /// Callback with the progress of the run, as reported by ffmpeg's
+    /// `-progress` option about twice per second. Useful for progress bars on
+    /// long inputs, where frames may arrive far apart.
+    pub on_progress_callback: Option<HypetriggerOnProgressCallback>,
+
+    /// Channel that `PipelineEvent`s are sent to, as an alternative to the
+    /// callbacks. See `with_event_channel`.
+    pub event_sender: Option<Sender<PipelineEvent>>,
 }
 
 impl Default for Hypetrigger {
@@ -62,15 +252,79 @@ impl Hypetrigger {
         Self {
             ffmpeg_exe: "ffmpeg".to_string(),
             verbose: false,
+            ffmpeg_loglevel: None,
+            hwaccel: Some("auto".to_string()),
             input: "".to_string(),
             input_format: None,
             fps: 2,
+            clamp_fps: false,
+            fps_filter: true,
+            sampling: Sampling::Fps,
+            processing_scale: None,
+            filter: None,
+            pixel_format: PixelFormat::default(),
+            fps_mode: FpsMode::default(),
+            ffmpeg_args: vec![],
+            input_args: vec![],
+            skip_frames: 0,
+            skip_duplicate_frames: false,
+            video_stream: None,
+            timestamps: None,
+            timeout: None,
+            #[cfg(feature = "ctrlc")]
+            handle_ctrlc: false,
             triggers: vec![],
+            async_triggers: vec![],
+            parallel_triggers: None,
+            audio_triggers: vec![],
+            tee_output: None,
+            custom_command: None,
             on_complete_callback: None,
+            on_complete_with_callback: None,
+            on_finish_callback: None,
             on_event_callback: None,
+            on_progress_callback: None,
+            event_sender: None,
         }
     }
 
+    /// Create a pipeline that sends a `PipelineEvent` for each frame, error
+    /// and completion to the returned `Receiver`, e.g. to consume the output
+    /// of several pipelines from one thread. Trigger results can be forwarded
+    /// to the same channel with `forward_events`:
+    ///
+    /// ```rs
+    /// let (mut hypetrigger, rx) = Hypetrigger::with_event_channel();
+    /// let on_text = forward_events(hypetrigger.event_sender.clone().unwrap());
+    /// hypetrigger.add_trigger(TesseractTrigger::builder().on_text(on_text).build(tesseract));
+    /// ```
+    ///
+    /// The channel closes once the pipeline (and any forwarding callbacks) are
+    /// dropped.
+    pub fn with_event_channel() -> (Self, Receiver<PipelineEvent>) {
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut hypetrigger = Self::new();
+        hypetrigger.event_sender = Some(event_tx);
+        (hypetrigger, event_rx)
+    }
+
+    /// Create a pipeline that runs the given ffmpeg command as-is, instead of
+    /// building one, for full control over its filters, stream mapping, etc.
+    /// The triggers are still run on each frame it outputs.
+    ///
+    /// The command must output `rawvideo` on stdout, in one of the pixel
+    /// formats in `PixelFormat` (e.g. with `FfmpegCommand::rawvideo`), or
+    /// this returns an error. Only its program and arguments are kept.
+    /// Frame timestamps are derived from the output framerate that ffmpeg
+    /// reports, since `fps` doesn't apply.
+    pub fn with_command(cmd: FfmpegCommand) -> Result<Self> {
+        let custom_command = CustomCommand::try_from(cmd)?;
+        let mut hypetrigger = Self::new();
+        hypetrigger.pixel_format = custom_command.pixel_format()?;
+        hypetrigger.custom_command = Some(custom_command);
+        Ok(hypetrigger)
+    }
+
     // --- Getters and setters ---
     /// Setter for the ffmpeg binary or command to use
     pub fn set_ffmpeg_exe(&mut self, ffmpeg_exe: String) -> &mut Self {
@@ -84,6 +338,19 @@ impl Hypetrigger {
         self
     }
 
+    /// Setter for the ffmpeg log level. Invalid levels are reported as an error
+    /// when the pipeline is run.
+    pub fn set_ffmpeg_loglevel(&mut self, level: &str) -> &mut Self {
+        self.ffmpeg_loglevel = Some(level.to_string());
+        self
+    }
+
+    /// Setter for the hardware acceleration method, or `None` to disable it
+    pub fn set_hwaccel(&mut self, hwaccel: Option<String>) -> &mut Self {
+        self.hwaccel = hwaccel;
+        self
+    }
+
     /// Setter for the input video (or image) for ffmpeg
     pub fn set_input(&mut self, input: String) -> &mut Self {
         self.input = input;
@@ -102,12 +369,173 @@ impl Hypetrigger {
             .set_input(FFMPEG_TEST_INPUT.to_string())
     }
 
+    /// Use a sequence of images as the input, via ffmpeg's `image2` demuxer.
+    /// The pattern can either be numbered (`frame%03d.png`) or a glob
+    /// (`screenshots/*.png`, not supported on Windows).
+    ///
+    /// Each image becomes one frame, spaced `1 / fps` seconds apart, so the
+    /// frame timestamps are derived from the sampling `fps`.
+    pub fn image_sequence(&mut self, pattern: &str) -> &mut Self {
+        self.set_input_format("image2")
+            .set_input(pattern.to_string())
+    }
+
     /// Setter for the framerate to sample the input video at.
     pub fn set_fps(&mut self, fps: u64) -> &mut Self {
         self.fps = fps;
         self
     }
 
+    /// Lower the sampling `fps` to the input's native framerate if it's
+    /// higher. See `clamp_fps`.
+    pub fn set_clamp_fps(&mut self, clamp_fps: bool) -> &mut Self {
+        self.clamp_fps = clamp_fps;
+        self
+    }
+
+    /// Don't add the implicit `fps` filter to the ffmpeg command, so that frames
+    /// are passed through at the input's native rate, e.g. for a single image
+    /// or a `select` stage in a custom `filter`. See `fps_filter`.
+    ///
+    /// `processing_scale` is still applied (the `-filter:v` argument is
+    /// omitted only when there's nothing left in it). An explicit `filter` is
+    /// always used as is, but no longer warns about a missing `fps` stage.
+    /// `timestamps` and `Sampling::SceneChange` don't use the `fps` filter and
+    /// are unaffected.
+    pub fn disable_fps_filter(&mut self) -> &mut Self {
+        self.fps_filter = false;
+        self
+    }
+
+    /// Setter for how frames are selected from the input, e.g.
+    /// `Sampling::SceneChange` as an alternative to a fixed `fps`.
+    pub fn set_sampling(&mut self, sampling: Sampling) -> &mut Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Only process the frames at the given timestamps (in seconds), e.g. from
+    /// chapter markers, using ffmpeg's `select` filter. Each frame is the
+    /// first one at or after its timestamp; timestamps closer together than
+    /// one frame of the input are merged, as are any at or before the start,
+    /// and those past the end yield no frame. Each frame is labelled by its
+    /// own `pts_time`, so a merged timestamp never shifts the others.
+    /// Replaces `fps` and `sampling`.
+    pub fn set_timestamps(&mut self, mut timestamps: Vec<f64>) -> &mut Self {
+        timestamps.retain(|timestamp| timestamp.is_finite());
+        timestamps.sort_by(f64::total_cmp);
+        timestamps.dedup();
+        self.timestamps = Some(timestamps);
+        self
+    }
+
+    /// Setter for the number of sampled frames to discard before running any
+    /// triggers. See `skip_frames`.
+    pub fn set_skip_frames(&mut self, skip_frames: u64) -> &mut Self {
+        self.skip_frames = skip_frames;
+        self
+    }
+
+    /// Skip frames identical to the one before. See `skip_duplicate_frames`.
+    pub fn set_skip_duplicate_frames(&mut self, skip_duplicate_frames: bool) -> &mut Self {
+        self.skip_duplicate_frames = skip_duplicate_frames;
+        self
+    }
+
+    /// Read the video stream at `index` (counting only video streams) of a
+    /// multi-stream input. Runs check the index against the input's streams
+    /// before starting, unless the input can only be read once (e.g. stdin),
+    /// in which case ffmpeg reports it instead. See `video_stream`.
+    pub fn set_video_stream(&mut self, index: usize) -> &mut Self {
+        self.video_stream = Some(index);
+        self
+    }
+
+    /// Downscale frames to the given size before running triggers on them, to
+    /// speed up both ffmpeg and the triggers.
+    ///
+    /// Percentage-based crops are unaffected, but triggers that work in
+    /// absolute pixel coordinates can map them back to the source video with
+    /// `Frame::source_width` and `Frame::source_height`.
+    pub fn set_processing_scale(&mut self, width: u32, height: u32) -> &mut Self {
+        self.processing_scale = Some((width, height));
+        self
+    }
+
+    /// Override the auto-generated video filter with a custom chain of filters.
+    /// Note that `fps` is still used for frame timestamps, so it should match
+    /// the chain's `fps` stage.
+    pub fn set_filter(&mut self, filter: FilterChain) -> &mut Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Setter for the pixel format requested from ffmpeg, e.g.
+    /// `PixelFormat::Rgb48` to keep the full bit depth of HDR footage.
+    pub fn set_pixel_format(&mut self, pixel_format: PixelFormat) -> &mut Self {
+        self.pixel_format = pixel_format;
+        self
+    }
+
+    /// Setter for how ffmpeg syncs output frames to their timestamps. See
+    /// `FpsMode`.
+    pub fn set_fps_mode(&mut self, fps_mode: FpsMode) -> &mut Self {
+        self.fps_mode = fps_mode;
+        self
+    }
+
+    /// Set the pixel format by its ffmpeg name (`rgb24`, `rgb48le`, `rgba` or
+    /// `gray`), e.g. from a config file. Unsupported formats are rejected here,
+    /// rather than when the first frame fails to decode.
+    pub fn set_pix_fmt(&mut self, pix_fmt: &str) -> Result<&mut Self> {
+        self.pixel_format = pix_fmt.parse()?;
+        Ok(self)
+    }
+
+    /// Append raw arguments to the ffmpeg command, for options that aren't
+    /// otherwise exposed by Hypetrigger. See `ffmpeg_args` for where they're
+    /// placed in the command.
+    pub fn add_ffmpeg_args(&mut self, args: &[&str]) -> &mut Self {
+        self.ffmpeg_args
+            .extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// Copy the raw frames from ffmpeg (in the `pixel_format`, at the
+    /// processing resolution) to a file or named pipe, e.g. one created with
+    /// `mkfifo`. A consumer that falls behind, or never opens the pipe, loses
+    /// frames rather than stalling the pipeline.
+    pub fn set_tee_output<P>(&mut self, path: P) -> &mut Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.tee_output = Some(path.into());
+        self
+    }
+
+    /// Append raw input options to the ffmpeg command, placed before `-i`
+    /// rather than on the output side like `add_ffmpeg_args`.
+    pub fn add_input_args(&mut self, args: &[&str]) -> &mut Self {
+        self.input_args
+            .extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// Give up if ffmpeg goes longer than `timeout` without producing a frame
+    /// (including the time before the first frame). The clock is stopped
+    /// while triggers run, so slow triggers don't trip it.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Install a Ctrl-C handler that stops runs cleanly. See `handle_ctrlc`.
+    #[cfg(feature = "ctrlc")]
+    pub fn set_handle_ctrlc(&mut self, handle_ctrlc: bool) -> &mut Self {
+        self.handle_ctrlc = handle_ctrlc;
+        self
+    }
+
     /// Add a Trigger to be run on every frame of the input
     pub fn add_trigger<T>(&mut self, trigger: T) -> &mut Self
     where
@@ -125,6 +553,41 @@ impl Hypetrigger {
         self
     }
 
+    /// Add a Trigger to be run on every frame of the input, on a background
+    /// thread managed by the pipeline. See `async_triggers`.
+    pub fn add_async_trigger<T>(&mut self, trigger: T) -> &mut Self
+    where
+        T: Trigger + 'static,
+    {
+        self.async_triggers.push(Arc::new(trigger));
+        self
+    }
+
+    /// Run the `triggers` on a pool of `workers` threads, so that a slow
+    /// trigger (e.g. OCR) doesn't stop ffmpeg's output from being read. Each
+    /// trigger queues up to `workers` frames, and the next frame is only read
+    /// once every trigger's queue has room, so memory use stays bounded.
+    ///
+    /// Each trigger still sees every frame in order, one at a time, but
+    /// different triggers can be working on different frames. Errors from a
+    /// trigger are reported on the following frame, but a `StopPipeline`
+    /// keeps every trigger from running the frames after the one that
+    /// returned it. See `PooledTrigger`.
+    pub fn set_parallel_triggers(&mut self, workers: usize) -> &mut Self {
+        self.parallel_triggers = Some(workers);
+        self
+    }
+
+    /// Add an AudioTrigger to be run on every chunk of the input's audio,
+    /// instead of on video frames. See `audio_triggers`.
+    pub fn add_audio_trigger<T>(&mut self, trigger: T) -> &mut Self
+    where
+        T: AudioTrigger + 'static,
+    {
+        self.audio_triggers.push(Arc::new(trigger));
+        self
+    }
+
     /// Call the given function when the input finishes processing
     pub fn on_complete<T>(&mut self, callback: T) -> &mut Self
     where
@@ -134,6 +597,26 @@ impl Hypetrigger {
         self
     }
 
+    /// Call the given function with a `RunSummary` when the input finishes
+    /// processing
+    pub fn on_complete_with<T>(&mut self, callback: T) -> &mut Self
+    where
+        T: Fn(RunSummary) + Send + Sync + 'static,
+    {
+        self.on_complete_with_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Call the given function when the run ends, whether it succeeded or not.
+    /// See `on_finish_callback`
+    pub fn on_finish<T>(&mut self, callback: T) -> &mut Self
+    where
+        T: Fn(&Result<RunSummary>) + Send + Sync + 'static,
+    {
+        self.on_finish_callback = Some(Arc::new(callback));
+        self
+    }
+
     /// Call the given function every time FFmpeg emits an event (like a log
     /// message or parsed metadata). Note: output frames are excluded.
     pub fn on_ffmpeg_event<T>(&mut self, callback: T) -> &mut Self
@@ -144,32 +627,323 @@ impl Hypetrigger {
         self
     }
 
+    /// Call the given function with the progress of the run, about twice per
+    /// second. See `on_progress_callback`
+    pub fn on_progress<T>(&mut self, callback: T) -> &mut Self
+    where
+        T: Fn(&ProgressInfo) + Send + Sync + 'static,
+    {
+        self.on_progress_callback = Some(Arc::new(callback));
+        self
+    }
+
     // --- Behavior ---
 
+    /// Check the configuration for errors that would otherwise only surface
+    /// once ffmpeg is running. Called automatically by `run` and `run_async`.
+    pub fn validate(&self) -> Result<()> {
+        if self.fps == 0 {
+            return Err(Error::from_display("The sampling fps must be at least 1"));
+        }
+        if let Some(level) = &self.ffmpeg_loglevel {
+            if !FFMPEG_LOG_LEVELS.contains(&level.as_str()) && level.parse::<i32>().is_err() {
+                return Err(Error::from_display(format!(
+                    "Invalid ffmpeg log level \"{}\", expected one of: {}",
+                    level,
+                    FFMPEG_LOG_LEVELS.join(", ")
+                )));
+            }
+        }
+        if self.custom_command.is_some() && !self.audio_triggers.is_empty() {
+            return Err(Error::from_display(
+                "Audio triggers can't be used with a custom ffmpeg command",
+            ));
+        }
+        let has_video_triggers = !self.triggers.is_empty() || !self.async_triggers.is_empty();
+        if has_video_triggers && !self.audio_triggers.is_empty() {
+            return Err(Error::from_display(
+                "Video triggers and audio triggers can't be combined in one pipeline",
+            ));
+        }
+        if let Some(filter) = &self.filter {
+            if self.fps_filter && !filter.has_fps() {
+                eprintln!(
+                    "[hypetrigger] Warning: filter \"{}\" has no fps stage, so every frame of the input will be processed",
+                    filter
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The sampling rate a run will use: `fps`, or with `clamp_fps`, the
+    /// input's native framerate (rounded up) if that's lower. Only probes
+    /// the input when `clamp_fps` is set and frames are sampled at `fps`, and
+    /// the input can be read more than once.
+    pub fn resolve_fps(&self) -> Result<u64> {
+        if self.clamps_fps() {
+            Ok(self.clamped_fps(self.probe_before_run()?.as_ref()))
+        } else {
+            Ok(self.fps)
+        }
+    }
+
+    /// Whether `clamp_fps` applies, i.e. frames are sampled at `fps`.
+    fn clamps_fps(&self) -> bool {
+        self.clamp_fps && self.samples_at_fps()
+    }
+
+    /// `fps`, lowered to the framerate in `media_info` with `clamp_fps`.
+    fn clamped_fps(&self, media_info: Option<&MediaInfo>) -> u64 {
+        match media_info.and_then(|media_info| media_info.fps) {
+            Some(source_fps) if self.clamps_fps() => clamp_to_source_fps(self.fps, source_fps),
+            _ => self.fps,
+        }
+    }
+
+    /// Probe the input once, if `clamp_fps` or `video_stream` need it and the
+    /// input can be read more than once.
+    fn probe_before_run(&self) -> Result<Option<MediaInfo>> {
+        let checks_stream = self.video_stream.is_some() && self.custom_command.is_none();
+        if (checks_stream || self.clamps_fps()) && !self.reads_once() {
+            self.probe().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Whether the input can only be read once, i.e. stdin or a stream that a
+    /// probe would take data (or the only connection) from.
+    fn reads_once(&self) -> bool {
+        let input = self.input.as_str();
+        let protocol = input.split_once("://").map(|(protocol, _)| protocol);
+        input == "-"
+            || input.starts_with("pipe:")
+            || matches!(
+                protocol,
+                Some("tcp" | "udp" | "rtp" | "srt" | "rtmp" | "rtsp" | "unix")
+            )
+    }
+
+    /// Whether frames are sampled with the default `fps` filter, as opposed to
+    /// `timestamps`, scene changes or an explicit `filter`.
+    fn samples_at_fps(&self) -> bool {
+        self.sampling == Sampling::Fps
+            && self.timestamps.is_none()
+            && self.fps_filter
+            && self.filter.is_none()
+            && self.custom_command.is_none()
+    }
+
     /// Spawn the inner FFmpeg command. This is a lower-level function that
     /// doesn't need to be used directly. It's equivalent to `FFmpegCommand`
     /// from `ffmpeg-sidecar` with some preset arguments and configuration.
     pub fn ffmpeg_command(&self) -> FfmpegCommand {
+        if let Some(custom_command) = &self.custom_command {
+            return custom_command.to_command();
+        }
         let mut cmd = FfmpegCommand::new_with_path(self.ffmpeg_exe.as_str());
-        cmd.hwaccel("auto");
-        if let Some(input_format) = &self.input_format {
-            cmd.format(input_format);
+        if let Some(level) = &self.ffmpeg_loglevel {
+            // `showinfo` logs the timestamps of the frames at `info`
+            let needs_info = self.verbose || self.uses_showinfo();
+            let level = if needs_info && is_quieter_than_info(level) {
+                "info"
+            } else {
+                level
+            };
+            // Keep the `[level]` prefixes that ffmpeg-sidecar parses
+            cmd.args(["-loglevel", &format!("level+{}", level)]);
+        }
+        if let Some(hwaccel) = &self.hwaccel {
+            cmd.hwaccel(hwaccel);
         }
         if cfg!(target_os = "windows") {
             cmd.create_no_window();
         }
-        cmd.input(self.input.as_str())
-            .args(["-filter:v", &format!("fps={}", self.fps)])
-            .args(["-vsync", "drop"])
+        if self.on_progress_callback.is_some() {
+            // Written to stderr alongside the logs, as `key=value` lines
+            cmd.args(["-progress", "pipe:2"]);
+        }
+        self.add_input(&mut cmd);
+        if !self.audio_triggers.is_empty() {
+            // Mono 16-bit PCM, delivered as `OutputChunk`s
+            cmd.no_video() // -vn
+                .overwrite() // -y
+                .args(&self.ffmpeg_args)
+                .args(["-ac", "1", "-ar", &AUDIO_SAMPLE_RATE.to_string()])
+                .args(["-f", "s16le"])
+                .output("-");
+            return cmd;
+        }
+        if let Some(index) = self.video_stream {
+            cmd.map(format!("0:v:{}", index));
+        }
+        let filter = self.filter.clone().unwrap_or_else(|| self.default_filter());
+        if !filter.stages.is_empty() {
+            cmd.args(["-filter:v", &filter.to_string()]);
+        }
+        cmd.args(["-vsync", self.fps_mode.ffmpeg_name()])
             .no_audio() // -an
             .overwrite() // -y
-            .rawvideo();
+            .args(&self.ffmpeg_args)
+            .args(["-f", "rawvideo"])
+            .pix_fmt(self.pixel_format.ffmpeg_name())
+            .output("-");
         cmd
     }
 
+    /// The video filter used when no explicit `filter` is set: sample according
+    /// to `timestamps` or `sampling`, then downscale to `processing_scale` (if any).
+    /// Empty if neither applies, see `disable_fps_filter`. `showinfo` logs the
+    /// timestamp of each selected frame.
+    fn default_filter(&self) -> FilterChain {
+        let filter = match (&self.timestamps, self.sampling) {
+            (Some(timestamps), _) => FilterChain::new().select_timestamps(timestamps).showinfo(),
+            (None, Sampling::Fps) if !self.fps_filter => FilterChain::new(),
+            (None, Sampling::Fps) => FilterChain::new().fps(self.fps),
+            (None, Sampling::SceneChange { threshold }) => {
+                FilterChain::new().scene_change(threshold).showinfo()
+            }
+        };
+        match self.processing_scale {
+            Some((width, height)) => filter.scale(width as i32, height as i32),
+            None => filter,
+        }
+    }
+
+    /// Add the input file, along with its input options, to an ffmpeg command.
+    fn add_input(&self, cmd: &mut FfmpegCommand) {
+        if let Some(input_format) = &self.input_format {
+            cmd.format(input_format);
+            if input_format == "image2" {
+                // Read exactly one image per sampled frame
+                cmd.args(["-framerate", &self.fps.to_string()]);
+                if self.input.contains('*') {
+                    cmd.args(["-pattern_type", "glob"]);
+                }
+            }
+        }
+        cmd.args(&self.input_args);
+        cmd.input(self.input.as_str());
+    }
+
+    /// Run a short ffmpeg process to read the resolution, duration, framerate
+    /// and codec of the input, without running any triggers.
+    pub fn probe(&self) -> Result<MediaInfo> {
+        let mut cmd = FfmpegCommand::new_with_path(self.ffmpeg_exe.as_str());
+        if cfg!(target_os = "windows") {
+            cmd.create_no_window();
+        }
+        self.add_input(&mut cmd);
+        cmd.args(["-frames:v", "1", "-f", "null", "-"]);
+
+        let mut child = cmd.spawn()?;
+        let mut media_info: Option<MediaInfo> = None;
+        let mut duration: Option<f64> = None;
+        let mut video_streams = 0;
+        let selected = self.video_stream.unwrap_or(0);
+        for event in child.iter()? {
+            match event {
+                FfmpegEvent::ParsedInputStream(stream) if stream.stream_type == "Video" => {
+                    if video_streams == selected {
+                        media_info = Some(MediaInfo {
+                            width: stream.width,
+                            height: stream.height,
+                            duration: None,
+                            fps: parse_ffmpeg_fps(&stream.raw_log_message),
+                            codec: stream.format,
+                            video_streams: 0,
+                        });
+                    }
+                    video_streams += 1;
+                }
+                FfmpegEvent::ParsedDuration(parsed) => {
+                    duration.get_or_insert(parsed.duration);
+                }
+                _ => {}
+            }
+        }
+        child.as_inner_mut().wait()?;
+
+        if video_streams == 0 {
+            return Err("No video stream found in input".into());
+        }
+        let mut media_info = media_info.ok_or_else(|| {
+            format!(
+                "Video stream {} out of range, the input has {} video stream(s)",
+                selected, video_streams
+            )
+        })?;
+        media_info.duration = duration;
+        media_info.video_streams = video_streams;
+        Ok(media_info)
+    }
+
+    /// Grab a single frame at `at` seconds into the input, e.g. to classify a
+    /// thumbnail, without running the pipeline or any triggers. The
+    /// `processing_scale` and `pixel_format` still apply.
+    pub fn snapshot(&self, at: f64) -> Result<Frame> {
+        let mut cmd = FfmpegCommand::new_with_path(self.ffmpeg_exe.as_str());
+        if let Some(hwaccel) = &self.hwaccel {
+            cmd.hwaccel(hwaccel);
+        }
+        if cfg!(target_os = "windows") {
+            cmd.create_no_window();
+        }
+        cmd.args(["-ss", &at.to_string()]); // seek before opening the input
+        self.add_input(&mut cmd);
+        if let Some((width, height)) = self.processing_scale {
+            let filter = FilterChain::new().scale(width as i32, height as i32);
+            cmd.args(["-filter:v", &filter.to_string()]);
+        }
+        cmd.args(["-frames:v", "1"])
+            .no_audio() // -an
+            .args(["-f", "rawvideo"])
+            .pix_fmt(self.pixel_format.ffmpeg_name())
+            .output("-");
+
+        let mut child = cmd.spawn()?;
+        let mut source_size = None;
+        let mut snapshot = None;
+        for event in child.iter()? {
+            match event {
+                FfmpegEvent::ParsedInputStream(stream) if stream.stream_type == "Video" => {
+                    source_size.get_or_insert((stream.width, stream.height));
+                }
+                FfmpegEvent::OutputFrame(frame) => {
+                    snapshot = Some(Frame::try_from(frame));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        child.as_inner_mut().kill().ok();
+        child.as_inner_mut().wait()?;
+
+        let mut frame = snapshot
+            .transpose()?
+            .ok_or_else(|| format!("No frame found at {}s in input", at))?;
+        frame.timestamp = at;
+        if let Some((source_width, source_height)) = source_size {
+            frame.source_width = source_width;
+            frame.source_height = source_height;
+        }
+        Ok(frame)
+    }
+
     /// A lower-level function handles both running triggers on each output
-    /// frame of FFmpeg, as well as logging when appropriate.
+    /// frame of FFmpeg, as well as logging when appropriate. Each call starts
+    /// from a fresh `RunState`, so frame numbering, sampling and the run's
+    /// summary aren't carried over between events; use `handle_event` to
+    /// feed the events of a whole ffmpeg process.
     pub fn handle_triggers(&self, event: FfmpegEvent) -> Result<()> {
+        self.handle_event(event, &mut RunState::default())
+    }
+
+    /// Same as `handle_triggers`, but keeping track of the run in `state`. The
+    /// same `state` should be passed in for every event of a single ffmpeg
+    /// process.
+    pub fn handle_event(&self, event: FfmpegEvent, state: &mut RunState) -> Result<()> {
         // Handle callbacks, if any
         if let Some(event_callback) = &self.on_event_callback {
             match &event {
@@ -180,33 +954,126 @@ impl Hypetrigger {
             }
         }
 
+        // `showinfo` logs each frame's timestamp on stderr, which is read on a
+        // separate thread from the frames themselves, so either can come first
+        if let FfmpegEvent::Log(_, msg) = &event {
+            if let Some((n, pts_time)) = parse_showinfo_frame(msg) {
+                state.pts_times.insert(n, pts_time);
+                return self.release_pending_frames(state);
+            }
+            // The final progress line (with `Lsize=`) comes through as a log.
+            // Still handled below, e.g. by the `on_progress` parser.
+            if let Some(dup_drop) = parse_ffmpeg_dup_drop(msg) {
+                (state.ffmpeg_duplicated, state.ffmpeg_dropped) = dup_drop;
+            }
+        }
+
         // Handle triggers
         match event {
+            FfmpegEvent::ParsedInputStream(stream) if stream.stream_type == "Video" => {
+                let index = match self.custom_command {
+                    Some(_) => 0,
+                    None => self.video_stream.unwrap_or(0),
+                };
+                let selected = index == state.video_streams;
+                state.video_streams += 1;
+                if !selected {
+                    return Ok(());
+                }
+                if let Some(source_fps) = parse_ffmpeg_fps(&stream.raw_log_message) {
+                    if state.source_size.is_none()
+                        && self.samples_at_fps()
+                        && clamp_to_source_fps(self.fps, source_fps) < self.fps
+                    {
+                        eprintln!(
+                            "[hypetrigger] Warning: sampling at {} fps, faster than the input's {} fps, duplicates frames (see `set_clamp_fps`)",
+                            self.fps, source_fps
+                        );
+                    }
+                }
+                state
+                    .source_size
+                    .get_or_insert((stream.width, stream.height));
+            }
+            FfmpegEvent::OutputFrame(_) if state.stopped => {}
             FfmpegEvent::OutputFrame(frame) => {
-                let image = RgbImage::from_vec(frame.width, frame.height, frame.data)
-                    .ok_or("Failed to get image from frame")?;
-                let frame = Frame {
-                    image,
-                    frame_num: frame.frame_num as u64,
-                    timestamp: frame.timestamp as f64,
+                let mut frame = Frame::try_from(frame)?;
+                // Number frames here rather than trusting ffmpeg's count, which
+                // is kept as `source_frame_num`
+                frame.frame_num = state.next_frame_num;
+                state.next_frame_num += 1;
+                if let Some((source_width, source_height)) = state.source_size {
+                    frame.source_width = source_width;
+                    frame.source_height = source_height;
+                }
+                if self.uses_showinfo() {
+                    // Wait for its `showinfo` line, if it hasn't arrived yet
+                    state.pending_frames.push_back(frame);
+                    return self.release_pending_frames(state);
+                }
+                // ffmpeg-sidecar derives the timestamp from the output stream's
+                // framerate, which it parses from the log output with limited
+                // precision. Recompute it against the source clock instead.
+                let requested = self
+                    .timestamps
+                    .as_ref()
+                    .and_then(|timestamps| timestamps.get(frame.frame_num as usize).copied());
+                frame.timestamp = match (requested, self.sampling) {
+                    _ if self.custom_command.is_some() => frame.timestamp,
+                    (None, Sampling::Fps) if !self.fps_filter => frame.timestamp,
+                    (Some(timestamp), _) => timestamp,
+                    (None, Sampling::Fps) => frame.frame_num as f64 / self.fps as f64,
+                    // Only with a custom `filter`, which has no `showinfo`
+                    (None, Sampling::SceneChange { .. }) => frame.timestamp,
                 };
-                self.triggers
-                    .iter()
-                    .map(|trigger| trigger.on_frame(&frame))
-                    .all(|r| r.is_ok())
-                    .then_some(())
-                    .ok_or(format!(
-                        "One or more triggers failed to run on frame {}",
-                        frame.frame_num
-                    ))?;
+                self.handle_frame(frame, state)?;
+            }
+            FfmpegEvent::OutputChunk(bytes) if !self.audio_triggers.is_empty() => {
+                state.audio_buffer.extend(bytes);
+                while state.audio_buffer.len() >= AUDIO_CHUNK_SAMPLES * 2 {
+                    let bytes: Vec<u8> = state
+                        .audio_buffer
+                        .drain(..AUDIO_CHUNK_SAMPLES * 2)
+                        .collect();
+                    self.handle_audio(decode_s16le(&bytes), state)?;
+                }
+            }
+            // Frames may still be waiting for their `showinfo` line
+            FfmpegEvent::Done if !state.pending_frames.is_empty() => state.done = true,
+            FfmpegEvent::Done => self.handle_done(state)?,
+            FfmpegEvent::LogEOF => {
+                state.stderr_closed = true;
+                self.release_pending_frames(state)?;
+            }
+            FfmpegEvent::Progress(progress) => {
+                if let Some(dup_drop) = parse_ffmpeg_dup_drop(&progress.raw_log_message) {
+                    (state.ffmpeg_duplicated, state.ffmpeg_dropped) = dup_drop;
+                }
+                if self.verbose {
+                    println!("[ffmpeg] {:?}", progress);
+                }
             }
-            FfmpegEvent::Done => {
-                if let Some(complete_callback) = &self.on_complete_callback {
-                    complete_callback()
+            FfmpegEvent::Log(LogLevel::Unknown, msg)
+                if self.on_progress_callback.is_some()
+                    && parse_ffmpeg_progress_line(&msg).is_some() =>
+            {
+                state.progress_block.push_str(&msg);
+                state.progress_block.push('\n');
+                if let Some(progress) = parse_ffmpeg_progress(&state.progress_block) {
+                    state.progress_block.clear();
+                    (state.ffmpeg_duplicated, state.ffmpeg_dropped) =
+                        (progress.dup_frames, progress.drop_frames);
+                    if let Some(progress_callback) = &self.on_progress_callback {
+                        progress_callback(&progress);
+                    }
                 }
             }
             FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, msg) | FfmpegEvent::Error(msg) => {
-                eprintln!("[ffmpeg] {}", msg)
+                eprintln!("[ffmpeg] {}", msg);
+                state.stderr_tail.push_back(msg);
+                while state.stderr_tail.len() > STDERR_TAIL_LINES {
+                    state.stderr_tail.pop_front();
+                }
             }
             e if self.verbose => println!("[ffmpeg] {:?}", e),
             _ => {}
@@ -214,45 +1081,2552 @@ impl Hypetrigger {
         Ok(())
     }
 
-    /// Spawn ffmpeg, call callbacks on each frame, and block until completion.
-    pub fn run(&mut self) -> Result<()> {
-        let mut child = self.ffmpeg_command().spawn()?;
-        child
-            .iter()?
-            .for_each(|event| self.handle_triggers(event).unwrap_or(()));
-        match child.as_inner_mut().wait() {
-            Ok(status) if status.success() => Ok(()),
-            Ok(status) => Err(Error::from_display(status)),
-            Err(e) => Err(Error::from_std(e)),
+    /// Flush the triggers and report the end of the input, once ffmpeg is done
+    /// and every frame has been handled.
+    fn handle_done(&self, state: &mut RunState) -> Result<()> {
+        for trigger in &self.triggers {
+            trigger.flush()?;
+        }
+        if !state.audio_buffer.is_empty() {
+            let bytes = std::mem::take(&mut state.audio_buffer);
+            self.handle_audio(decode_s16le(&bytes), state)?;
+        }
+        let summary = state.summary();
+        if self.verbose && summary.frames > 0 && !summary.is_realtime() {
+            eprintln!(
+                "[hypetrigger] Warning: triggers ran at {:.2}x real time, so a live input at {} fps would fall behind",
+                summary.realtime_factor, self.fps
+            );
+        }
+        if let Some(complete_callback) = &self.on_complete_callback {
+            complete_callback()
+        }
+        if let Some(complete_callback) = &self.on_complete_with_callback {
+            complete_callback(state.summary())
         }
+        self.send_event(PipelineEvent::Complete(state.summary()));
+        Ok(())
     }
 
-    /// Same as calling `run` on a separate thread, returning both the thread's
-    /// `JoinHandle` as well as the `ChildStdin` that can be used to stop
-    /// the pipeline early.
-    ///
-    /// See also: `stop_ffmpeg(child: ChildStdin)`
-    pub fn run_async(self) -> Result<(JoinHandle<()>, ChildStdin)> {
-        let mut child = self.ffmpeg_command().spawn()?;
-        let ffmpeg_stdin = child.take_stdin().ok_or("Failed to get stdin")?;
-        let iter = child.iter()?;
-        let join_handle = thread::spawn(move || {
-            iter.for_each(|event| self.handle_triggers(event).unwrap_or(()));
-        });
-        Ok((join_handle, ffmpeg_stdin))
+    /// Whether frames are timestamped by the `showinfo` filter that the
+    /// default filter adds for `timestamps` and `Sampling::SceneChange`.
+    fn uses_showinfo(&self) -> bool {
+        self.custom_command.is_none()
+            && self.filter.is_none()
+            && (self.timestamps.is_some() || matches!(self.sampling, Sampling::SceneChange { .. }))
     }
-}
 
-pub type HypetriggerOnCompleteCallback = Arc<dyn Fn() + Send + Sync>;
-pub type HypetriggerOnFfmpegEventCallback = Arc<dyn Fn(&FfmpegEvent) + Send + Sync>;
+    /// The timestamp of a frame at `pts_time`: the latest of the requested
+    /// `timestamps` at or before it (which selected it), if any.
+    fn frame_timestamp(&self, pts_time: f64) -> f64 {
+        // `showinfo` prints `pts_time` to 6 significant digits
+        let tolerance = pts_time.abs() * 5e-6;
+        self.timestamps
+            .iter()
+            .flatten()
+            .rev()
+            .find(|timestamp| **timestamp <= pts_time + tolerance)
+            .copied()
+            .unwrap_or(pts_time)
+    }
 
-/// Used with the ffmpeg `-i` argument, or with `.input()` in the Hypetrigger API.
-/// <https://www.bogotobogo.com/FFMpeg/ffmpeg_video_test_patterns_src.php>
-pub const FFMPEG_TEST_INPUT: &str = "testsrc=duration=10:size=1280x720:rate=30";
+    /// Pass on the frames whose `showinfo` line has arrived, in order. Once
+    /// ffmpeg's stderr is closed, the rest keep ffmpeg-sidecar's timestamp.
+    fn release_pending_frames(&self, state: &mut RunState) -> Result<()> {
+        while let Some(mut frame) = state.pending_frames.pop_front() {
+            match state.pts_times.remove(&frame.frame_num) {
+                Some(pts_time) => frame.timestamp = self.frame_timestamp(pts_time),
+                None if state.stderr_closed => {}
+                None => {
+                    state.pending_frames.push_front(frame);
+                    break;
+                }
+            }
+            if !state.stopped {
+                self.handle_frame(frame, state)?;
+            }
+        }
+        if state.done && state.pending_frames.is_empty() {
+            state.done = false;
+            self.handle_done(state)?;
+        }
+        Ok(())
+    }
 
-/// Sends a `q` to the ffmpeg process over stdin, which tells it gracefully exit.
-/// You could also call `kill()` on the `Child` process instance of ffmpeg to stop it
-/// more abruptly. You can obtain the `stdin` handle from the return value of `run_async()`.
-pub fn stop_ffmpeg(stdin: &mut ChildStdin) -> Result<()> {
-    stdin.write_all(b"q\n").map_err(Error::from)
+    /// Pass a frame to the triggers, unless it's skipped by `skip_frames`,
+    /// `sample_fps`, `skip_duplicate_frames` or the triggers' `desired_fps`.
+    fn handle_frame(&self, frame: Frame, state: &mut RunState) -> Result<()> {
+        if frame.frame_num < self.skip_frames {
+            return Ok(());
+        }
+        if !should_sample(
+            state.sample_fps,
+            frame.timestamp,
+            &mut state.last_pipeline_sample,
+        ) {
+            return Ok(());
+        }
+        if self.skip_duplicate_frames {
+            if state.previous_image.as_ref() == Some(&frame.image) {
+                state.skipped_duplicates += 1;
+                return Ok(());
+            }
+            state.previous_image = Some(frame.image.clone());
+        }
+        state.frames += 1;
+        let fps = self.fps as f64;
+        if self.samples_at_fps() && !state.external_frames {
+            state.media_time += 1.0 / state.sample_fps.map_or(fps, |sample| sample.min(fps));
+        } else {
+            // Frames aren't evenly spaced, so go by the timestamps
+            state.media_time = state.media_time.max(frame.timestamp);
+        }
+        self.send_event(PipelineEvent::Frame {
+            frame_num: frame.frame_num,
+            timestamp: frame.timestamp,
+        });
+        state.last_sample.resize(self.triggers.len(), None);
+        let sampled_triggers = self
+            .triggers
+            .iter()
+            .zip(state.last_sample.iter_mut())
+            .filter_map(|(trigger, last_sample)| {
+                should_sample(trigger.desired_fps(), frame.timestamp, last_sample)
+                    .then_some(trigger)
+            });
+        for trigger in sampled_triggers {
+            match trigger.on_frame(&frame) {
+                Ok(()) => {}
+                Err(e) if e.is::<StopPipeline>() => state.stopped = true,
+                Err(_) => {
+                    return Err(format!(
+                        "One or more triggers failed to run on frame {}",
+                        frame.frame_num
+                    )
+                    .into())
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send an event to the `event_sender`, if any. Events are dropped if the
+    /// receiver has hung up.
+    fn send_event(&self, event: PipelineEvent) {
+        if let Some(event_sender) = &self.event_sender {
+            event_sender.send(event).ok();
+        }
+    }
+
+    /// Run the audio triggers on the next chunk of samples.
+    fn handle_audio(&self, samples: Vec<i16>, state: &mut RunState) -> Result<()> {
+        let chunk = AudioChunk {
+            timestamp: state.audio_samples as f64 / AUDIO_SAMPLE_RATE as f64,
+            sample_rate: AUDIO_SAMPLE_RATE,
+            samples,
+        };
+        state.audio_samples += chunk.samples.len() as u64;
+        self.audio_triggers
+            .iter()
+            .map(|trigger| trigger.on_chunk(&chunk))
+            .all(|r| r.is_ok())
+            .then_some(())
+            .ok_or(format!(
+                "One or more audio triggers failed to run on the chunk at {}s",
+                chunk.timestamp
+            ))?;
+        Ok(())
+    }
+
+    /// Spawn ffmpeg, call callbacks on each frame, and block until completion.
+    pub fn run(&mut self) -> Result<()> {
+        self.run_with_summary().map(|_| ())
+    }
+
+    /// Same as `run`, returning statistics about the run once it completes.
+    pub fn run_with_summary(&self) -> Result<RunSummary> {
+        let result = self.validate().and_then(|_| {
+            let (pipeline, threads) = self.prepare()?;
+            let result = pipeline.start_triggers().and_then(|_| {
+                let result = pipeline
+                    .ffmpeg_command()
+                    .spawn()
+                    .map_err(Error::from)
+                    .and_then(|child| pipeline.run_child(child, None));
+                pipeline.end_triggers(result)
+            });
+            threads.stop(result)
+        });
+        self.finish(result)
+    }
+
+    /// Call `on_start` on each trigger, stopping at the first error. The
+    /// triggers that had already started are then ended, in reverse order.
+    fn start_triggers(&self) -> Result<()> {
+        for (i, trigger) in self.triggers.iter().enumerate() {
+            if let Err(e) = trigger.on_start() {
+                for trigger in self.triggers[..i].iter().rev() {
+                    trigger.on_end().ok(); // the first error is the one to report
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `on_end` on every trigger, then pass through the result of the run
+    /// (or the first error from `on_end`).
+    fn end_triggers<T>(&self, result: Result<T>) -> Result<T> {
+        let ended: Vec<Result<()>> = self
+            .triggers
+            .iter()
+            .map(|trigger| trigger.on_end())
+            .collect();
+        let value = result?;
+        ended.into_iter().collect::<Result<()>>()?;
+        Ok(value)
+    }
+
+    /// Resolve the sampling rate (see `clamp_fps`) and spawn the threads for a
+    /// run, returning the copy of the pipeline to run.
+    fn prepare(&self) -> Result<(Hypetrigger, RunThreads)> {
+        // Errors if the `video_stream` is out of range
+        let media_info = self.probe_before_run()?;
+        let fps = self.clamped_fps(media_info.as_ref());
+        let (mut pipeline, threads) = self.spawn_threads();
+        pipeline.fps = fps;
+        Ok((pipeline, threads))
+    }
+
+    /// If `parallel_triggers` is set, spawn a `DispatchPool` and wrap the
+    /// `triggers` as `PooledTrigger`s. If there are any `async_triggers`, spawn
+    /// a `TriggerThread` for them and wrap them as `AsyncTrigger`s. Returns the
+    /// copy of the pipeline with the wrapped triggers.
+    fn spawn_threads(&self) -> (Hypetrigger, RunThreads) {
+        let mut pipeline = self.clone();
+        let mut threads = RunThreads::default();
+        if let Some(workers) = self.parallel_triggers {
+            let dispatch_pool = DispatchPool::spawn(workers);
+            pipeline.triggers = pipeline
+                .triggers
+                .drain(..)
+                .map(|trigger| Arc::new(dispatch_pool.wrap(trigger)) as Arc<dyn Trigger>)
+                .collect();
+            threads.dispatch_pool = Some(dispatch_pool);
+        }
+        if !self.async_triggers.is_empty() {
+            let trigger_thread = TriggerThread::spawn();
+            for trigger in pipeline.async_triggers.drain(..) {
+                pipeline.triggers.push(Arc::new(AsyncTrigger::new(
+                    trigger,
+                    trigger_thread.tx.clone(),
+                )));
+            }
+            threads.trigger_thread = Some(trigger_thread);
+        }
+        (pipeline, threads)
+    }
+
+    /// Call the `on_finish` callback (if any) with the result of a run.
+    fn finish(&self, result: Result<RunSummary>) -> Result<RunSummary> {
+        if let Some(finish_callback) = &self.on_finish_callback {
+            finish_callback(&result);
+        }
+        result
+    }
+
+    /// Process many inputs in parallel, running at most `concurrency`
+    /// pipelines at once, and return a summary for each input (in the same
+    /// order). Each pipeline gets its own triggers from `make_triggers`, so
+    /// that stateful triggers (like a `TesseractTrigger`'s Tesseract instance)
+    /// aren't shared between threads; any `triggers` already set are ignored.
+    ///
+    /// All inputs are attempted; if any of them fail, the first failure (in
+    /// input order) is returned.
+    pub fn run_many<F>(
+        &self,
+        inputs: Vec<String>,
+        concurrency: usize,
+        make_triggers: F,
+    ) -> Result<Vec<RunSummary>>
+    where
+        F: Fn() -> Vec<Arc<dyn Trigger>> + Sync,
+    {
+        let results: Mutex<Vec<Option<Result<RunSummary>>>> =
+            Mutex::new(inputs.iter().map(|_| None).collect());
+        let next_input = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..concurrency.clamp(1, inputs.len().max(1)) {
+                scope.spawn(|| loop {
+                    let index = next_input.fetch_add(1, Ordering::SeqCst);
+                    let input = match inputs.get(index) {
+                        Some(input) => input,
+                        None => return,
+                    };
+                    let mut pipeline = self.clone();
+                    pipeline.input = input.clone();
+                    pipeline.triggers = make_triggers();
+                    pipeline.async_triggers.clear();
+                    let result = pipeline.run_with_summary();
+                    if let Ok(mut results) = results.lock() {
+                        results[index] = Some(result);
+                    }
+                });
+            }
+        });
+
+        results
+            .into_inner()?
+            .into_iter()
+            .zip(&inputs)
+            .map(|(result, input)| match result {
+                Some(Ok(summary)) => Ok(summary),
+                Some(Err(e)) => Err(Error {
+                    message: format!("Failed to process {}: {}", input, e.message),
+                    source: e.source,
+                }),
+                None => Err(Error::from_display(format!("Failed to process {}", input))),
+            })
+            .collect()
+    }
+
+    /// Run the same configuration (and triggers) on each of the given inputs in
+    /// turn, blocking until they've all completed. Stops at the first input
+    /// that fails. Note that frame numbers and timestamps restart from zero
+    /// for each input.
+    pub fn run_each<I, S>(&self, inputs: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        for input in inputs {
+            self.clone().set_input(input.into()).run()?;
+        }
+        Ok(())
+    }
+
+    /// Run until a frame satisfies `predicate`, then tell ffmpeg to quit and
+    /// return that frame, or `None` if the input ended without a match. The
+    /// predicate runs after the other triggers, so they see the matching frame
+    /// too.
+    pub fn run_until<F>(mut self, predicate: F) -> Result<Option<Frame>>
+    where
+        F: Fn(&Frame) -> bool + Send + Sync + 'static,
+    {
+        let matched = Arc::new(Mutex::new(None));
+        let matched_clone = matched.clone();
+        self.triggers.push(Arc::new(PredicateTrigger {
+            predicate,
+            matched: matched_clone,
+        }));
+        self.run_with_summary()?;
+        let matched = matched.lock()?.take();
+        Ok(matched)
+    }
+
+    /// Run the triggers over frames decoded elsewhere (e.g. by GStreamer or
+    /// OpenCV) instead of spawning ffmpeg, blocking until the iterator ends or
+    /// a trigger returns `StopPipeline`. Frames keep their own `frame_num`,
+    /// `timestamp` and source size; everything else (`skip_frames`, the
+    /// triggers' `desired_fps`, callbacks, events) works as in `run`, while
+    /// the input and ffmpeg settings are ignored.
+    pub fn run_frames<I>(&self, frames: I) -> Result<RunSummary>
+    where
+        I: IntoIterator<Item = Frame>,
+    {
+        let result = self.validate().and_then(|_| {
+            if !self.audio_triggers.is_empty() {
+                return Err("Audio triggers can't be run on external frames".into());
+            }
+            let (pipeline, threads) = self.spawn_threads();
+            let result = pipeline.start_triggers().and_then(|_| {
+                let mut state = RunState {
+                    started: Some(Instant::now()),
+                    external_frames: true,
+                    ..Default::default()
+                };
+                for frame in frames {
+                    if let Err(e) = pipeline.handle_frame(frame, &mut state) {
+                        state.errors += 1;
+                        pipeline.send_event(PipelineEvent::Error(e.message));
+                    }
+                    if state.stopped {
+                        break;
+                    }
+                }
+                pipeline.handle_event(FfmpegEvent::Done, &mut state)?;
+                Ok(state.summary())
+            });
+            threads.stop(pipeline.end_triggers(result))
+        });
+        self.finish(result)
+    }
+
+    /// Run triggers on every event of an already-spawned ffmpeg process, then
+    /// wait for it to exit.
+    fn run_child(
+        &self,
+        mut child: FfmpegChild,
+        control_rx: Option<Receiver<PipelineCommand>>,
+    ) -> Result<RunSummary> {
+        let iter = child.iter()?;
+        let child = Arc::new(Mutex::new(child));
+
+        // If enabled, the watchdog kills ffmpeg when it stops producing frames
+        let watchdog = self
+            .timeout
+            .map(|timeout| spawn_watchdog(timeout, child.clone()));
+
+        // With the `ctrlc` feature, an `interrupt` (e.g. from Ctrl-C) tells
+        // ffmpeg to quit and ends the run as if a trigger had returned
+        // `StopPipeline`
+        #[cfg(feature = "ctrlc")]
+        if self.handle_ctrlc {
+            install_handler();
+        }
+        #[cfg(feature = "ctrlc")]
+        let interrupted = Arc::new(AtomicBool::new(false));
+        #[cfg(feature = "ctrlc")]
+        let _interrupt_guard = {
+            let interrupted = interrupted.clone();
+            let child = child.clone();
+            on_interrupt(move || {
+                interrupted.store(true, Ordering::SeqCst);
+                if let Ok(mut child) = child.lock() {
+                    child.quit().ok();
+                }
+            })
+        };
+
+        let tee = self.tee_output.clone().map(TeeWriter::spawn);
+        let mut state = RunState {
+            started: Some(Instant::now()),
+            ..Default::default()
+        };
+        for event in iter {
+            #[cfg(feature = "ctrlc")]
+            if interrupted.load(Ordering::SeqCst) {
+                state.stopped = true; // ffmpeg was already told to quit
+            }
+            if let Some(control_rx) = &control_rx {
+                let heartbeat_tx = watchdog.as_ref().map(|(heartbeat_tx, _)| heartbeat_tx);
+                self.handle_commands(control_rx, &mut state, heartbeat_tx);
+            }
+            // Stop the watchdog's clock while the triggers run
+            let heartbeat_tx = match (&watchdog, &event) {
+                (
+                    Some((heartbeat_tx, _)),
+                    FfmpegEvent::OutputFrame(_) | FfmpegEvent::OutputChunk(_),
+                ) => Some(heartbeat_tx),
+                _ => None,
+            };
+            if let Some(heartbeat_tx) = heartbeat_tx {
+                heartbeat_tx.send(true).ok();
+            }
+            if let (Some(tee), FfmpegEvent::OutputFrame(frame)) = (&tee, &event) {
+                tee.write(frame.data.clone());
+            }
+            let was_stopped = state.stopped;
+            if let Err(e) = self.handle_event(event, &mut state) {
+                state.errors += 1;
+                self.send_event(PipelineEvent::Error(e.message));
+            }
+            if let Some(heartbeat_tx) = heartbeat_tx {
+                heartbeat_tx.send(false).ok();
+            }
+            if state.stopped && !was_stopped {
+                // Keep reading events afterwards, so ffmpeg isn't left blocked
+                // writing frames to a full stdout pipe while it shuts down
+                let mut child = child.lock()?;
+                if child.quit().is_err() {
+                    child.as_inner_mut().kill()?; // stdin was taken by `run_async`
+                }
+            }
+        }
+
+        if let (Some(timeout), Some((heartbeat_tx, join_handle))) = (self.timeout, watchdog) {
+            drop(heartbeat_tx);
+            let timed_out = join_handle.join().map_err(|e| format!("{:?}", e))?;
+            if timed_out {
+                child.lock()?.as_inner_mut().wait()?;
+                return Err(TimedOut { timeout }.into());
+            }
+        }
+
+        let status = child.lock()?.as_inner_mut().wait();
+        match status {
+            Ok(_) if state.stopped => Ok(state.summary()),
+            Ok(status) if status.success() => Ok(state.summary()),
+            Ok(status) => Err(FfmpegFailed {
+                exit_code: status.code(),
+                stderr: state.stderr_tail.into(),
+            }
+            .into()),
+            Err(e) => Err(Error::from_std(e)),
+        }
+    }
+
+    /// Apply any pending commands from the control channel. While paused, this
+    /// blocks (without reading any more output from ffmpeg) until the pipeline
+    /// is resumed or the control channel is dropped.
+    fn handle_commands(
+        &self,
+        control_rx: &Receiver<PipelineCommand>,
+        state: &mut RunState,
+        heartbeat_tx: Option<&Sender<bool>>,
+    ) {
+        // Pausing is intentional, so it shouldn't trip the timeout
+        let heartbeat_interval = self.timeout.map_or(Duration::from_secs(1), |t| t / 2);
+        loop {
+            let command = if state.paused {
+                match control_rx.recv_timeout(heartbeat_interval) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Some(heartbeat_tx) = heartbeat_tx {
+                            heartbeat_tx.send(false).ok();
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        state.paused = false;
+                        return;
+                    }
+                }
+            } else {
+                match control_rx.try_recv() {
+                    Ok(command) => command,
+                    Err(_) => return,
+                }
+            };
+            match command {
+                PipelineCommand::Pause => state.paused = true,
+                PipelineCommand::Resume => state.paused = false,
+                PipelineCommand::SetFps(fps) => state.sample_fps = Some(fps),
+            }
+        }
+    }
+
+    /// Same as calling `run_with_summary` on a separate thread, returning a
+    /// `PipelineHandle` to control the pipeline while it runs, stop it early,
+    /// and wait for its `RunSummary`.
+    pub fn run_async(self) -> Result<PipelineHandle> {
+        let (pipeline, threads) = match self.validate().and_then(|_| self.prepare()) {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                self.finish(Err(Error::from_display(&e))).ok();
+                return Err(e);
+            }
+        };
+        let spawned = pipeline.start_triggers().and_then(|_| {
+            pipeline
+                .ffmpeg_command()
+                .spawn()
+                .map_err(Error::from)
+                .and_then(|mut child| {
+                    let ffmpeg_stdin = child.take_stdin().ok_or("Failed to get stdin")?;
+                    Ok((child, ffmpeg_stdin))
+                })
+                .or_else(|e| pipeline.end_triggers(Err(e)))
+        });
+        let (child, ffmpeg_stdin) = match spawned {
+            Ok(spawned) => spawned,
+            Err(e) => {
+                let result = threads.stop(Err(Error::from_display(&e)));
+                self.finish(result).ok();
+                return Err(e);
+            }
+        };
+        let (control_tx, control_rx) = mpsc::channel();
+        let join_handle = thread::spawn(move || {
+            let result = pipeline.end_triggers(pipeline.run_child(child, Some(control_rx)));
+            pipeline.finish(threads.stop(result))
+        });
+        Ok(PipelineHandle {
+            join_handle,
+            stdin: ffmpeg_stdin,
+            control_tx,
+        })
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Hypetrigger {
+    /// Same as `run_with_summary`, as a future for tokio apps. ffmpeg and the
+    /// triggers run on their own thread, as with `run_async`, which is started
+    /// and awaited from blocking tasks (probing the input and the triggers'
+    /// `on_start` can block too), so the triggers stay synchronous. Cancelling
+    /// `cancellation` tells ffmpeg to quit, after which the future resolves
+    /// with the summary of the frames handled so far.
+    ///
+    /// Must be awaited from within a tokio runtime.
+    pub async fn run_tokio(self, cancellation: CancellationToken) -> Result<RunSummary> {
+        let PipelineHandle {
+            join_handle,
+            mut stdin,
+            control_tx,
+        } = tokio::task::spawn_blocking(move || self.run_async())
+            .await
+            .map_err(Error::from_std)??;
+        // Aborted once the run is over, or if this future is dropped first
+        let _stopper = AbortOnDrop(tokio::spawn(async move {
+            cancellation.cancelled().await;
+            // ffmpeg may have already exited by itself, closing its stdin
+            stop_ffmpeg(&mut stdin).ok();
+        }));
+        let joined = tokio::task::spawn_blocking(move || -> Result<RunSummary> {
+            join_handle
+                .join()
+                .map_err(|e| format!("Pipeline thread panicked: {:?}", e))?
+        })
+        .await;
+        drop(control_tx);
+        joined.map_err(Error::from_std)?
+    }
+}
+
+/// Aborts a tokio task when dropped.
+#[cfg(feature = "tokio")]
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+#[cfg(feature = "tokio")]
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// A pipeline running on its own thread, returned by `Hypetrigger::run_async`.
+pub struct PipelineHandle {
+    /// Joining the thread returns the result of the run, like
+    /// `run_with_summary`
+    pub join_handle: JoinHandle<Result<RunSummary>>,
+
+    /// ffmpeg's stdin, which can be used to stop it early (see `stop_ffmpeg`)
+    pub stdin: ChildStdin,
+
+    /// Sends `PipelineCommand`s to the pipeline while it runs
+    pub control_tx: Sender<PipelineCommand>,
+}
+
+impl PipelineHandle {
+    /// Send a command to the running pipeline, e.g. to pause it.
+    pub fn send(&self, command: PipelineCommand) -> Result<()> {
+        self.control_tx.send(command).map_err(Error::from)
+    }
+
+    /// Block until the input is finished, and return the result of the run.
+    pub fn join(self) -> Result<RunSummary> {
+        self.join_handle
+            .join()
+            .map_err(|e| format!("Pipeline thread panicked: {:?}", e))?
+    }
+
+    /// Tell ffmpeg to quit, then wait for the frames already in flight to be
+    /// handled and return the result of the run.
+    pub fn stop(mut self) -> Result<RunSummary> {
+        // ffmpeg may have already exited by itself, closing its stdin
+        stop_ffmpeg(&mut self.stdin).ok();
+        self.join()
+    }
+}
+
+/// The threads spawned for a single run of the pipeline, if any
+#[derive(Default)]
+struct RunThreads {
+    /// Runs the `triggers`, with `parallel_triggers`
+    dispatch_pool: Option<DispatchPool>,
+
+    /// Runs the `async_triggers`
+    trigger_thread: Option<TriggerThread>,
+}
+
+impl RunThreads {
+    /// Stop the threads once their queues are empty, then pass through the
+    /// result of the run (or the first error from stopping them).
+    fn stop(self, result: Result<RunSummary>) -> Result<RunSummary> {
+        let pool_stopped = self.dispatch_pool.map_or(Ok(()), DispatchPool::stop);
+        let thread_stopped = self.trigger_thread.map_or(Ok(()), TriggerThread::stop);
+        let summary = result?;
+        pool_stopped?;
+        thread_stopped?;
+        Ok(summary)
+    }
+}
+
+/// Lower `fps` to `source_fps` if it's higher. Fractional rates are rounded
+/// up, so e.g. 30 fps isn't lowered on a 29.97 fps input.
+fn clamp_to_source_fps(fps: u64, source_fps: f64) -> u64 {
+    let source_fps = source_fps.ceil();
+    if fps as f64 > source_fps {
+        (source_fps as u64).max(1)
+    } else {
+        fps
+    }
+}
+
+/// Spawn a thread that kills ffmpeg if it goes longer than `timeout` without a
+/// heartbeat. Each heartbeat says whether the pipeline is busy running
+/// triggers, which stops the clock until the next heartbeat. The thread exits
+/// when the returned `Sender` is dropped. Joining the thread returns whether
+/// ffmpeg was killed.
+fn spawn_watchdog(
+    timeout: Duration,
+    child: Arc<Mutex<FfmpegChild>>,
+) -> (Sender<bool>, JoinHandle<bool>) {
+    let (heartbeat_tx, heartbeat_rx) = mpsc::channel::<bool>();
+    let join_handle = thread::spawn(move || {
+        let mut busy = false;
+        loop {
+            let heartbeat = if busy {
+                heartbeat_rx
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                heartbeat_rx.recv_timeout(timeout)
+            };
+            match heartbeat {
+                Ok(is_busy) => busy = is_busy,
+                Err(RecvTimeoutError::Disconnected) => return false,
+                Err(RecvTimeoutError::Timeout) => {
+                    eprintln!(
+                        "[hypetrigger] No frames received in {:?}, killing ffmpeg",
+                        timeout
+                    );
+                    if let Ok(mut child) = child.lock() {
+                        child.as_inner_mut().kill().ok();
+                    }
+                    return true;
+                }
+            }
+        }
+    });
+    (heartbeat_tx, join_handle)
+}
+
+/// The program and arguments of an ffmpeg command supplied to
+/// `Hypetrigger::with_command`. Unlike `FfmpegCommand`, it can be cloned along
+/// with the rest of the pipeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomCommand {
+    pub program: OsString,
+    pub args: Vec<OsString>,
+}
+
+impl CustomCommand {
+    /// The value following `flag` in the arguments, e.g. `rawvideo` for `-f`.
+    /// The last occurrence wins, as it does for ffmpeg's output options.
+    fn arg_value(&self, flag: &str) -> Option<String> {
+        self.args
+            .windows(2)
+            .rev()
+            .find(|pair| pair[0] == flag)
+            .map(|pair| pair[1].to_string_lossy().to_string())
+    }
+
+    /// The pixel format the command outputs, which must be one that
+    /// `PixelFormat` supports.
+    fn pixel_format(&self) -> Result<PixelFormat> {
+        self.arg_value("-pix_fmt")
+            .ok_or("Custom ffmpeg command must set the output -pix_fmt")?
+            .parse()
+    }
+
+    /// Rebuild the command, with stdin, stdout and stderr piped as
+    /// `ffmpeg-sidecar` expects.
+    pub fn to_command(&self) -> FfmpegCommand {
+        let mut inner = Command::new(&self.program);
+        inner
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut cmd = FfmpegCommand::from(inner);
+        if cfg!(target_os = "windows") {
+            cmd.create_no_window();
+        }
+        cmd
+    }
+}
+
+impl TryFrom<FfmpegCommand> for CustomCommand {
+    type Error = Error;
+
+    /// Check that the command outputs raw video frames on stdout.
+    fn try_from(mut cmd: FfmpegCommand) -> Result<Self> {
+        let inner = cmd.as_inner();
+        let custom_command = Self {
+            program: inner.get_program().to_os_string(),
+            args: inner.get_args().map(|arg| arg.to_os_string()).collect(),
+        };
+        if custom_command.arg_value("-f").as_deref() != Some("rawvideo") {
+            return Err("Custom ffmpeg command must output -f rawvideo".into());
+        }
+        let output = custom_command.args.last().map(|arg| arg.to_string_lossy());
+        if !matches!(output.as_deref(), Some("-" | "pipe:" | "pipe:1")) {
+            return Err("Custom ffmpeg command must output to stdout (-)".into());
+        }
+        Ok(custom_command)
+    }
+}
+
+/// Stops the pipeline at the first frame that satisfies a predicate, keeping a
+/// copy of it, see `Hypetrigger::run_until`.
+struct PredicateTrigger<F> {
+    predicate: F,
+    matched: Arc<Mutex<Option<Frame>>>,
+}
+
+impl<F> Trigger for PredicateTrigger<F>
+where
+    F: Fn(&Frame) -> bool + Send + Sync,
+{
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        if !(self.predicate)(frame) {
+            return Ok(());
+        }
+        self.matched.lock()?.get_or_insert_with(|| frame.clone());
+        Err(StopPipeline.into())
+    }
+}
+
+/// Metadata about the input, as returned by `Hypetrigger::probe()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaInfo {
+    /// Native width of the video stream, in pixels
+    pub width: u32,
+
+    /// Native height of the video stream, in pixels
+    pub height: u32,
+
+    /// Length of the input in seconds, if known. Live streams and generated
+    /// (`lavfi`) inputs don't report a duration.
+    pub duration: Option<f64>,
+
+    /// Native framerate of the video stream, if known
+    pub fps: Option<f64>,
+
+    /// Codec (or raw format) of the video stream, e.g. `h264`
+    pub codec: String,
+
+    /// Number of video streams in the input. The other fields describe the
+    /// one selected by `Hypetrigger::video_stream` (or the first).
+    pub video_streams: usize,
+}
+
+/// Bookkeeping for a single run of the pipeline, carried across the events of
+/// one ffmpeg process.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunState {
+    /// Resolution of the input video stream, once ffmpeg has reported it
+    pub source_size: Option<(u32, u32)>,
+
+    /// For each trigger (by index), the last sampling interval it ran in, used
+    /// to decimate frames for triggers with a `desired_fps`
+    pub last_sample: Vec<Option<u64>>,
+
+    /// Whether the pipeline is paused by a `PipelineCommand`
+    pub paused: bool,
+
+    /// Sampling rate set by `PipelineCommand::SetFps`, if any
+    pub sample_fps: Option<f64>,
+
+    /// Whether the frames come from `run_frames` rather than ffmpeg, so they
+    /// weren't sampled by the `fps` filter
+    pub external_frames: bool,
+
+    /// The last sampling interval of `sample_fps` that triggers ran in
+    pub last_pipeline_sample: Option<u64>,
+
+    /// When the run started, if it's being timed
+    pub started: Option<Instant>,
+
+    /// Number of frames passed to the triggers so far
+    pub frames: u64,
+
+    /// `frame_num` of the next frame from ffmpeg, counting every frame it
+    /// outputs (including skipped ones)
+    pub next_frame_num: u64,
+
+    /// Number of events (usually frames) that failed to be handled so far
+    pub errors: u64,
+
+    /// Timestamps logged by the `showinfo` filter for frames that haven't been
+    /// received yet, by frame index (`n`), used with `timestamps` and `Sampling::SceneChange`
+    pub pts_times: HashMap<u64, f64>,
+
+    /// Frames received before their `showinfo` line, in order
+    pub pending_frames: VecDeque<Frame>,
+
+    /// Whether ffmpeg's stderr has been closed, so no more `showinfo` lines
+    /// will arrive
+    pub stderr_closed: bool,
+
+    /// Whether ffmpeg finished while frames were still waiting for their
+    /// `showinfo` line, so the end of the input is handled after them
+    pub done: bool,
+
+    /// The last `STDERR_TAIL_LINES` errors logged by ffmpeg, included in the
+    /// error returned if it exits unsuccessfully
+    pub stderr_tail: VecDeque<String>,
+
+    /// Whether a trigger returned `StopPipeline`, after which no more frames
+    /// are passed to the triggers
+    pub stopped: bool,
+
+    /// Audio output that hasn't yet filled an `AudioChunk`, as raw `s16le`
+    pub audio_buffer: Vec<u8>,
+
+    /// Number of audio samples passed to the audio triggers so far
+    pub audio_samples: u64,
+
+    /// Seconds of input covered by the frames passed to the triggers so far,
+    /// i.e. the target interval (`1 / fps`) times the number of frames (or the
+    /// latest frame's timestamp, when not sampling with the `fps` filter)
+    pub media_time: f64,
+
+    /// Frames ffmpeg reported duplicating to keep the output framerate (its
+    /// `dup=` statistic)
+    pub ffmpeg_duplicated: u64,
+
+    /// Frames ffmpeg reported dropping to keep the output framerate (its
+    /// `drop=` statistic)
+    pub ffmpeg_dropped: u64,
+
+    /// The last frame passed to the triggers, with `skip_duplicate_frames`
+    pub previous_image: Option<RgbImage>,
+
+    /// Number of frames skipped for being identical to the previous one
+    pub skipped_duplicates: u64,
+
+    /// Number of video streams ffmpeg has reported in the input so far, used to
+    /// find the one selected by `video_stream`
+    pub video_streams: usize,
+
+    /// Lines of ffmpeg's `-progress` output received since the last complete
+    /// block, used with `on_progress`
+    pub progress_block: String,
+}
+
+/// How many of ffmpeg's error messages to keep for `FfmpegFailed`
+pub const STDERR_TAIL_LINES: usize = 20;
+
+impl RunState {
+    /// Statistics about the run so far
+    pub fn summary(&self) -> RunSummary {
+        let duration = self
+            .started
+            .map_or(Duration::ZERO, |started| started.elapsed());
+        let average_fps = match duration.as_secs_f64() {
+            secs if secs > 0.0 => self.frames as f64 / secs,
+            _ => 0.0,
+        };
+        let realtime_factor = match duration.as_secs_f64() {
+            secs if secs > 0.0 => self.media_time / secs,
+            _ => 0.0,
+        };
+        RunSummary {
+            frames: self.frames,
+            errors: self.errors,
+            duration,
+            average_fps,
+            realtime_factor,
+            duplicated_frames: self.ffmpeg_duplicated,
+            dropped_frames: self.ffmpeg_dropped,
+            skipped_duplicates: self.skipped_duplicates,
+        }
+    }
+}
+
+/// Everything that happens during a run, as sent to the channel from
+/// `Hypetrigger::with_event_channel`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PipelineEvent {
+    /// A frame was passed to the triggers
+    Frame { frame_num: u64, timestamp: f64 },
+
+    /// Text recognized by a `TesseractTrigger`, see `forward_events`
+    #[cfg(feature = "tesseract")]
+    Ocr(TesseractResult),
+
+    /// A classification by a `TensorflowTrigger` or `OnnxTrigger`, see
+    /// `forward_events`
+    #[cfg(any(feature = "tensorflow", feature = "onnx"))]
+    Prediction {
+        prediction: Prediction,
+        timestamp: f64,
+        frame_num: u64,
+    },
+
+    /// An event (usually a frame) failed to be handled
+    Error(String),
+
+    /// The input finished processing
+    Complete(RunSummary),
+}
+
+#[cfg(feature = "tesseract")]
+impl From<TesseractResult> for PipelineEvent {
+    fn from(result: TesseractResult) -> Self {
+        PipelineEvent::Ocr(result)
+    }
+}
+
+#[cfg(feature = "tensorflow")]
+impl From<TensorflowResult> for PipelineEvent {
+    fn from(result: TensorflowResult) -> Self {
+        PipelineEvent::Prediction {
+            prediction: result.prediction,
+            timestamp: result.timestamp,
+            frame_num: result.frame_num,
+        }
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl From<OnnxResult> for PipelineEvent {
+    fn from(result: OnnxResult) -> Self {
+        PipelineEvent::Prediction {
+            prediction: result.prediction,
+            timestamp: result.timestamp,
+            frame_num: result.frame_num,
+        }
+    }
+}
+
+/// A trigger callback that sends each result (e.g. a `TesseractResult`) to a
+/// pipeline's event channel, see `Hypetrigger::with_event_channel`.
+pub fn forward_events<T>(event_sender: Sender<PipelineEvent>) -> impl Fn(T) + Send + Sync
+where
+    T: Into<PipelineEvent>,
+{
+    move |result| {
+        event_sender.send(result.into()).ok();
+    }
+}
+
+/// Statistics about a completed run, passed to `on_complete_with`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunSummary {
+    /// Number of frames passed to the triggers
+    pub frames: u64,
+
+    /// Number of events (usually frames) that failed to be handled, e.g.
+    /// because a trigger returned an error
+    pub errors: u64,
+
+    /// Wall-clock time from the start of the run until ffmpeg finished
+    pub duration: Duration,
+
+    /// Frames processed per second of wall-clock time
+    pub average_fps: f64,
+
+    /// Seconds of input processed per second of wall-clock time. Below `1.0`,
+    /// the triggers can't keep up with a live input at the requested `fps`.
+    pub realtime_factor: f64,
+
+    /// Frames ffmpeg duplicated to keep a constant output framerate, from its
+    /// `dup=` statistic. This only counts duplication by the `fps_mode` (e.g.
+    /// `FpsMode::Cfr`); copies made by the `fps` filter itself are only
+    /// caught by `skip_duplicate_frames`.
+    pub duplicated_frames: u64,
+
+    /// Frames ffmpeg dropped to keep a constant output framerate, from its
+    /// `drop=` statistic
+    pub dropped_frames: u64,
+
+    /// Frames skipped by `skip_duplicate_frames`, which aren't counted in
+    /// `frames`
+    pub skipped_duplicates: u64,
+}
+
+impl RunSummary {
+    /// Whether the run kept up with real time, i.e. a live input at the same
+    /// `fps` wouldn't have fallen behind.
+    pub fn is_realtime(&self) -> bool {
+        self.realtime_factor >= 1.0
+    }
+}
+
+/// How frames are selected from the input video.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Sampling {
+    /// A fixed number of frames per second, set by `Hypetrigger::fps`.
+    #[default]
+    Fps,
+
+    /// Only the frames where the scene changes by more than `threshold`, from
+    /// `0.0` (identical) to `1.0` (completely different); `0.3` is a typical
+    /// value for detecting cuts.
+    ///
+    /// The time between frames is irregular, so `Frame::timestamp` is read
+    /// from ffmpeg's `showinfo` filter instead of derived from `fps`. This
+    /// requires a log level of at least `info`.
+    SceneChange { threshold: f64 },
+}
+
+/// Pixel format of the frames requested from ffmpeg.
+///
+/// Triggers always receive an 8-bit RGB `Frame::image`. Other formats are
+/// down-converted to it, and the frame as decoded is kept in `Frame::original`
+/// for triggers that can make use of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8 bits per channel RGB (`rgb24`)
+    #[default]
+    Rgb24,
+
+    /// 16 bits per channel RGB (`rgb48le`), e.g. for HDR footage where `rgb24`
+    /// would clip highlights. `Frame::original` is a 16-bit
+    /// `DynamicImage::ImageRgb16`.
+    Rgb48,
+
+    /// 8 bits per channel RGB with alpha (`rgba`), e.g. for transparent
+    /// overlays. `Frame::original` is a `DynamicImage::ImageRgba8`, which
+    /// `Frame::to_photon` uses so that alpha survives preprocessing.
+    Rgba,
+
+    /// 8-bit grayscale (`gray`), e.g. for OCR-only pipelines, which only need
+    /// a third of the bandwidth of `rgb24`. `Frame::original` is a
+    /// `DynamicImage::ImageLuma8`.
+    Gray,
+}
+
+impl PixelFormat {
+    /// Name of the pixel format in ffmpeg, as passed to `-pix_fmt`
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            PixelFormat::Rgb24 => "rgb24",
+            PixelFormat::Rgb48 => "rgb48le",
+            PixelFormat::Rgba => "rgba",
+            PixelFormat::Gray => "gray",
+        }
+    }
+}
+
+/// How ffmpeg syncs output frames to their timestamps, passed as `-vsync`
+/// (the older name of `-fps_mode`, which is still accepted by newer ffmpeg).
+///
+/// This matters for variable-framerate sources (e.g. phone recordings or
+/// screen captures), and for custom `filter`s without an `fps` stage. With
+/// `Sampling::Fps`, `Frame::timestamp` is derived from the frame number, so it
+/// is only accurate if each output frame really is `1 / fps` seconds apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FpsMode {
+    /// Pass frames through without duplicating any, discarding their
+    /// timestamps (`drop`). Together with the default `fps` filter, which
+    /// already outputs a constant rate, timestamps stay accurate.
+    #[default]
+    Drop,
+
+    /// Duplicate or drop frames to reach a constant rate (`cfr`). Timestamps
+    /// stay aligned with the frame number, but a stalled source yields
+    /// repeated frames.
+    Cfr,
+
+    /// Drop frames with duplicate timestamps, but never duplicate any (`vfr`).
+    /// Without an `fps` filter, gaps in the source shift every later frame's
+    /// derived timestamp earlier than its real position.
+    Vfr,
+
+    /// Pass every frame through as-is, with its original timestamp
+    /// (`passthrough`). Same timestamp caveat as `Vfr`.
+    Passthrough,
+}
+
+impl FpsMode {
+    /// Value of the `-vsync` argument for this mode
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            FpsMode::Drop => "drop",
+            FpsMode::Cfr => "cfr",
+            FpsMode::Vfr => "vfr",
+            FpsMode::Passthrough => "passthrough",
+        }
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = Error;
+
+    /// Parse an ffmpeg pixel format name, as returned by `ffmpeg_name`.
+    fn from_str(pix_fmt: &str) -> Result<Self> {
+        let formats = [
+            PixelFormat::Rgb24,
+            PixelFormat::Rgb48,
+            PixelFormat::Rgba,
+            PixelFormat::Gray,
+        ];
+        formats
+            .into_iter()
+            .find(|format| format.ffmpeg_name() == pix_fmt)
+            .ok_or_else(|| {
+                let names: Vec<&str> = formats.iter().map(|f| f.ffmpeg_name()).collect();
+                Error::from_display(format!(
+                    "Unsupported pix_fmt \"{}\", expected one of: {}",
+                    pix_fmt,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// Commands to control a pipeline started with `run_async`, while it's running.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PipelineCommand {
+    /// Stop running triggers. ffmpeg's output is no longer read while paused,
+    /// so a file input is paused too; a live input may drop or buffer frames,
+    /// depending on the source.
+    Pause,
+
+    /// Continue running triggers after a `Pause`.
+    Resume,
+
+    /// Change the rate at which triggers run. ffmpeg's filter can't be changed
+    /// without restarting it, so this decimates the frames it outputs instead:
+    /// it can lower the rate below the pipeline's `fps`, but not raise it.
+    SetFps(f64),
+}
+
+/// Whether a trigger with the given `desired_fps` should run on the frame at
+/// `timestamp`. The video is divided into intervals of `1 / desired_fps`
+/// seconds, and the trigger runs on the first frame to land in each one.
+fn should_sample(desired_fps: Option<f64>, timestamp: f64, last_sample: &mut Option<u64>) -> bool {
+    let desired_fps = match desired_fps {
+        Some(desired_fps) if desired_fps > 0.0 => desired_fps,
+        _ => return true,
+    };
+    // Small epsilon so that timestamps landing exactly on an interval boundary
+    // aren't pushed back into the previous one by rounding error
+    let interval = (timestamp * desired_fps + 1e-6).floor() as u64;
+    if *last_sample == Some(interval) {
+        return false;
+    }
+    *last_sample = Some(interval);
+    true
+}
+
+/// Convert a raw frame from ffmpeg to an 8-bit RGB image, along with the
+/// original image if it was in a different format.
+fn decode_frame(frame: OutputVideoFrame) -> Result<(RgbImage, Option<DynamicImage>)> {
+    let (width, height, data_len) = (frame.width, frame.height, frame.data.len());
+    let size_error = |bytes_per_pixel: usize| {
+        format!(
+            "Failed to get image from frame {}: {}x{} {} should be {} bytes, got {}",
+            frame.frame_num,
+            width,
+            height,
+            frame.pix_fmt,
+            width as usize * height as usize * bytes_per_pixel,
+            data_len
+        )
+    };
+    match frame.pix_fmt.as_str() {
+        "rgb24" => {
+            let image =
+                RgbImage::from_vec(width, height, frame.data).ok_or_else(|| size_error(3))?;
+            Ok((image, None))
+        }
+        "rgba" => {
+            let image =
+                RgbaImage::from_vec(width, height, frame.data).ok_or_else(|| size_error(4))?;
+            let original = DynamicImage::ImageRgba8(image);
+            Ok((original.to_rgb8(), Some(original)))
+        }
+        "gray" => {
+            let image =
+                GrayImage::from_vec(width, height, frame.data).ok_or_else(|| size_error(1))?;
+            let original = DynamicImage::ImageLuma8(image);
+            Ok((original.to_rgb8(), Some(original)))
+        }
+        "rgb48le" => {
+            let samples = frame
+                .data
+                .chunks_exact(2)
+                .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+                .collect();
+            let image = ImageBuffer::<Rgb<u16>, Vec<u16>>::from_vec(width, height, samples)
+                .ok_or_else(|| size_error(6))?;
+            let original = DynamicImage::ImageRgb16(image);
+            Ok((original.to_rgb8(), Some(original)))
+        }
+        pix_fmt => Err(Error::from_display(format!(
+            "Failed to get image from frame {}: unsupported pix_fmt {}",
+            frame.frame_num, pix_fmt
+        ))),
+    }
+}
+
+impl TryFrom<OutputVideoFrame> for Frame {
+    type Error = Error;
+
+    /// Decode a raw frame from ffmpeg-sidecar, keeping its frame number and
+    /// timestamp. Useful when handling `FfmpegEvent`s in your own loop instead
+    /// of with `Hypetrigger::run`.
+    fn try_from(frame: OutputVideoFrame) -> Result<Self> {
+        let frame_num = frame.frame_num as u64;
+        let timestamp = frame.timestamp as f64;
+        let (image, original) = decode_frame(frame)?;
+        let mut frame = Frame::new(image, frame_num, timestamp);
+        frame.original = original;
+        Ok(frame)
+    }
+}
+
+pub type HypetriggerOnCompleteCallback = Arc<dyn Fn() + Send + Sync>;
+pub type HypetriggerOnCompleteWithCallback = Arc<dyn Fn(RunSummary) + Send + Sync>;
+pub type HypetriggerOnFinishCallback = Arc<dyn Fn(&Result<RunSummary>) + Send + Sync>;
+pub type HypetriggerOnFfmpegEventCallback = Arc<dyn Fn(&FfmpegEvent) + Send + Sync>;
+pub type HypetriggerOnProgressCallback = Arc<dyn Fn(&ProgressInfo) + Send + Sync>;
+
+/// Used with the ffmpeg `-i` argument, or with `.input()` in the Hypetrigger API.
+/// <https://www.bogotobogo.com/FFMpeg/ffmpeg_video_test_patterns_src.php>
+pub const FFMPEG_TEST_INPUT: &str = "testsrc=duration=10:size=1280x720:rate=30";
+
+/// Accepted values for `set_ffmpeg_loglevel` (besides a numeric level).
+pub const FFMPEG_LOG_LEVELS: [&str; 9] = [
+    "quiet", "panic", "fatal", "error", "warning", "info", "verbose", "debug", "trace",
+];
+
+/// Whether an ffmpeg log level (by name or number) hides `info` logs.
+fn is_quieter_than_info(level: &str) -> bool {
+    match level.parse::<i32>() {
+        Ok(level) => level < 32,
+        Err(_) => FFMPEG_LOG_LEVELS
+            .iter()
+            .position(|name| *name == level)
+            .is_some_and(|index| index < 5),
+    }
+}
+
+/// Sends a `q` to the ffmpeg process over stdin, which tells it gracefully exit.
+/// You could also call `kill()` on the `Child` process instance of ffmpeg to stop it
+/// more abruptly. You can obtain the `stdin` handle from the `PipelineHandle` returned by
+/// `run_async()`, or just call `PipelineHandle::stop`.
+pub fn stop_ffmpeg(stdin: &mut ChildStdin) -> Result<()> {
+    stdin.write_all(b"q\n").map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        clamp_to_source_fps, decode_frame, stop_ffmpeg, FpsMode, Hypetrigger, PipelineCommand,
+        PipelineEvent, PixelFormat, RunState, Sampling, FFMPEG_TEST_INPUT,
+    };
+    use crate::{
+        error::{Error, FfmpegFailed, Result, StopPipeline, TimedOut},
+        filter::FilterChain,
+        simple_trigger::SimpleTrigger,
+        trigger::{Frame, Trigger},
+        util::command_to_string,
+    };
+    use ffmpeg_sidecar::{
+        command::FfmpegCommand,
+        event::{FfmpegEvent, LogLevel, OutputVideoFrame},
+    };
+    use image::{ColorType, Rgb, RgbImage};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps() -> Result<()> {
+        let timestamps = Arc::new(Mutex::new(Vec::new()));
+        let timestamps_clone = timestamps.clone();
+        Hypetrigger::new()
+            .test_input()
+            .set_fps(2)
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                timestamps_clone.lock().unwrap().push(frame.timestamp);
+            }))
+            .run()?;
+
+        let timestamps = timestamps.lock()?;
+        assert!(timestamps.len() > 1);
+        assert!((timestamps[1] - 0.5).abs() < 0.001);
+        Ok(())
+    }
+
+    /// Counts the frames it receives, at a given sampling rate
+    struct RateTrigger {
+        fps: f64,
+        frames: Arc<Mutex<u32>>,
+    }
+
+    impl Trigger for RateTrigger {
+        fn on_frame(&self, _frame: &Frame) -> Result<()> {
+            *self.frames.lock()? += 1;
+            Ok(())
+        }
+
+        fn desired_fps(&self) -> Option<f64> {
+            Some(self.fps)
+        }
+    }
+
+    #[test]
+    fn desired_fps() -> Result<()> {
+        let fast_frames = Arc::new(Mutex::new(0));
+        let slow_frames = Arc::new(Mutex::new(0));
+        Hypetrigger::new()
+            .test_input() // 10 seconds
+            .set_fps(10)
+            .add_trigger(RateTrigger {
+                fps: 10.0,
+                frames: fast_frames.clone(),
+            })
+            .add_trigger(RateTrigger {
+                fps: 1.0,
+                frames: slow_frames.clone(),
+            })
+            .run()?;
+
+        let fast_frames = *fast_frames.lock()?;
+        let slow_frames = *slow_frames.lock()?;
+        assert!((95..=101).contains(&fast_frames), "{}", fast_frames);
+        assert!((9..=11).contains(&slow_frames), "{}", slow_frames);
+        Ok(())
+    }
+
+    #[test]
+    fn should_sample() {
+        let mut last_sample = None;
+        let sampled: Vec<bool> = (0..10)
+            .map(|frame_num| {
+                super::should_sample(Some(2.0), frame_num as f64 / 10.0, &mut last_sample)
+            })
+            .collect();
+        assert_eq!(
+            sampled,
+            [true, false, false, false, false, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn pause_resume() -> Result<()> {
+        // The trigger holds on to frame 1 until the pipeline has been paused
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let release_rx = Mutex::new(release_rx);
+        let resumed = Arc::new(AtomicBool::new(false));
+        let resumed_clone = resumed.clone();
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .test_input()
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                if frame.frame_num == 1 {
+                    started_tx.send(()).unwrap();
+                    release_rx.lock().unwrap().recv().unwrap();
+                }
+                let resumed = resumed_clone.load(Ordering::SeqCst);
+                frames_clone
+                    .lock()
+                    .unwrap()
+                    .push((frame.frame_num, resumed));
+            }));
+        let handle = hypetrigger.run_async()?;
+
+        started_rx.recv().map_err(Error::from_std)?;
+        handle.send(PipelineCommand::Pause)?;
+        release_tx.send(()).map_err(Error::from_std)?;
+        resumed.store(true, Ordering::SeqCst);
+        handle.send(PipelineCommand::Resume)?;
+        handle.join()?;
+
+        // No frame after the pause was handled until the pipeline resumed
+        let frames = frames.lock()?;
+        let frame_nums: Vec<u64> = frames.iter().map(|(frame_num, _)| *frame_num).collect();
+        assert_eq!(frame_nums, (0..20).collect::<Vec<u64>>());
+        assert!(frames[2..].iter().all(|(_, resumed)| *resumed));
+        Ok(())
+    }
+
+    /// Records the order of calls to the trigger, collapsing consecutive frames
+    #[derive(Default)]
+    struct LifecycleTrigger {
+        calls: Mutex<Vec<&'static str>>,
+        fail_on_start: bool,
+    }
+    impl Trigger for LifecycleTrigger {
+        fn on_start(&self) -> Result<()> {
+            self.calls.lock()?.push("start");
+            match self.fail_on_start {
+                true => Err("Failed to start".into()),
+                false => Ok(()),
+            }
+        }
+        fn on_frame(&self, _frame: &Frame) -> Result<()> {
+            let mut calls = self.calls.lock()?;
+            if calls.last() != Some(&"frame") {
+                calls.push("frame");
+            }
+            Ok(())
+        }
+        fn on_end(&self) -> Result<()> {
+            self.calls.lock()?.push("end");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn lifecycle() -> Result<()> {
+        let trigger = Arc::new(LifecycleTrigger::default());
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.test_input();
+        hypetrigger.triggers.push(trigger.clone());
+        hypetrigger.run()?;
+        assert_eq!(*trigger.calls.lock()?, vec!["start", "frame", "end"]);
+        Ok(())
+    }
+
+    #[test]
+    fn lifecycle_start_failed() -> Result<()> {
+        let trigger = Arc::new(LifecycleTrigger {
+            fail_on_start: true,
+            ..Default::default()
+        });
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.test_input();
+        hypetrigger.triggers.push(trigger.clone());
+        let error = hypetrigger.run().unwrap_err();
+        assert_eq!(error.message, "Failed to start");
+        assert_eq!(*trigger.calls.lock()?, vec!["start"]);
+        Ok(())
+    }
+
+    #[test]
+    fn lifecycle_second_start_failed() -> Result<()> {
+        let first = Arc::new(LifecycleTrigger::default());
+        let second = Arc::new(LifecycleTrigger {
+            fail_on_start: true,
+            ..Default::default()
+        });
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.test_input();
+        hypetrigger.triggers.push(first.clone());
+        hypetrigger.triggers.push(second.clone());
+        let error = hypetrigger.run().unwrap_err();
+        assert_eq!(error.message, "Failed to start");
+        assert_eq!(*first.calls.lock()?, vec!["start", "end"]);
+        assert_eq!(*second.calls.lock()?, vec!["start"]);
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_triggers() -> Result<()> {
+        // Counts frames as soon as they're read, on another worker
+        let read = Arc::new(Mutex::new(0));
+        let read_clone = read.clone();
+        let read_during_first_frame = Arc::new(Mutex::new(0));
+        let read_during_first_frame_clone = read_during_first_frame.clone();
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        Hypetrigger::new()
+            .test_input()
+            .set_parallel_triggers(4)
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                if frame.frame_num == 0 {
+                    thread::sleep(Duration::from_millis(300));
+                    *read_during_first_frame_clone.lock().unwrap() = *read_clone.lock().unwrap();
+                }
+                frames_clone.lock().unwrap().push(frame.frame_num);
+            }))
+            .add_trigger(SimpleTrigger::new(move |_| {
+                *read.lock().unwrap() += 1;
+            }))
+            .run()?;
+
+        // ffmpeg kept being read while the slow trigger was busy
+        assert!(*read_during_first_frame.lock()? > 1);
+        // ...but the slow trigger still saw every frame, in order
+        assert_eq!(*frames.lock()?, (0..20).collect::<Vec<u64>>());
+        Ok(())
+    }
+
+    #[test]
+    fn stop_async() -> Result<()> {
+        // The trigger holds on to frame 2 until ffmpeg has been told to quit
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let release_rx = Mutex::new(release_rx);
+        let frames = Arc::new(Mutex::new(0));
+        let frames_clone = frames.clone();
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .test_input()
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                *frames_clone.lock().unwrap() += 1;
+                if frame.frame_num == 2 {
+                    started_tx.send(()).unwrap();
+                    release_rx.lock().unwrap().recv().unwrap();
+                }
+            }));
+        let mut handle = hypetrigger.run_async()?;
+
+        started_rx.recv().map_err(Error::from_std)?;
+        stop_ffmpeg(&mut handle.stdin)?;
+        release_tx.send(()).map_err(Error::from_std)?;
+        let summary = handle.stop()?;
+        assert!(summary.frames >= 3);
+        assert!(summary.frames < 20); // the test input has 20 frames at 2 fps
+        assert_eq!(summary.frames, *frames.lock()?);
+        assert_eq!(summary.errors, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_frame_size() {
+        let frame = OutputVideoFrame {
+            width: 4,
+            height: 2,
+            pix_fmt: "rgb24".to_string(),
+            output_index: 0,
+            data: vec![0; 20],
+            frame_num: 3,
+            timestamp: 0.0,
+        };
+        let error = Hypetrigger::new()
+            .handle_triggers(FfmpegEvent::OutputFrame(frame))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to get image from frame 3: 4x2 rgb24 should be 24 bytes, got 20"
+        );
+    }
+
+    #[test]
+    fn frame_from_ffmpeg() -> Result<()> {
+        let frame = Frame::try_from(OutputVideoFrame {
+            width: 2,
+            height: 1,
+            pix_fmt: "rgb24".to_string(),
+            output_index: 0,
+            data: vec![255, 0, 0, 0, 0, 255],
+            frame_num: 7,
+            timestamp: 0.25,
+        })?;
+        assert_eq!(frame.frame_num, 7);
+        assert_eq!(frame.timestamp, 0.25);
+        assert_eq!(frame.image.dimensions(), (2, 1));
+        assert_eq!(frame.image.get_pixel(1, 0), &Rgb([0, 0, 255]));
+        assert!(frame.original.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn exact_timestamps() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        Hypetrigger::new()
+            .test_input()
+            .set_timestamps(vec![7.0, 1.0, 3.0])
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone.lock().unwrap().push(frame.timestamp);
+            }))
+            .run()?;
+
+        assert_eq!(*frames.lock()?, vec![1.0, 3.0, 7.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn merged_timestamps() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .set_timestamps(vec![-1.0, 0.0, 1.0, 1.01, 5.0, 100.0])
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone.lock().unwrap().push(frame.timestamp);
+            }));
+
+        // At 2 fps, the first two and the next two timestamps each select a
+        // single frame, and the last one is past the end
+        let mut state = RunState::default();
+        for (n, pts_time) in ["0", "1.5", "5"].into_iter().enumerate() {
+            let line = format!(
+                "[Parsed_showinfo_1 @ 0x5581] n:{:>4} pts:  12800 pts_time:{:<8} duration:512",
+                n, pts_time
+            );
+            hypetrigger.handle_event(FfmpegEvent::Log(LogLevel::Info, line), &mut state)?;
+            let frame = OutputVideoFrame {
+                width: 1,
+                height: 1,
+                pix_fmt: "rgb24".to_string(),
+                output_index: 0,
+                data: vec![0; 3],
+                frame_num: n as u32,
+                timestamp: 0.0,
+            };
+            hypetrigger.handle_event(FfmpegEvent::OutputFrame(frame), &mut state)?;
+        }
+
+        assert_eq!(*frames.lock()?, vec![0.0, 1.01, 5.0]);
+        assert_eq!(state.media_time, 5.0);
+        Ok(())
+    }
+
+    #[test]
+    fn skip_frames() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        Hypetrigger::new()
+            .test_input()
+            .set_skip_frames(2)
+            .add_ffmpeg_args(&["-frames:v", "4"])
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone.lock().unwrap().push(frame.frame_num);
+            }))
+            .run()?;
+
+        assert_eq!(*frames.lock()?, vec![2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn realtime_factor() -> Result<()> {
+        let slow_trigger = SimpleTrigger::new(|_| thread::sleep(Duration::from_millis(600)));
+        let summary = Hypetrigger::new()
+            .test_input()
+            .set_fps(2)
+            .add_ffmpeg_args(&["-frames:v", "3"])
+            .add_trigger(slow_trigger)
+            .run_with_summary()?;
+
+        // 1.5 seconds of input in at least 1.8 seconds
+        assert_eq!(summary.frames, 3);
+        assert!(summary.realtime_factor < 1.0);
+        assert!(!summary.is_realtime());
+        Ok(())
+    }
+
+    #[test]
+    fn run_summary() -> Result<()> {
+        let summary = Arc::new(Mutex::new(None));
+        let summary_clone = summary.clone();
+        Hypetrigger::new()
+            .test_input()
+            .add_ffmpeg_args(&["-frames:v", "3"])
+            .add_trigger(SimpleTrigger::new(|_| {}))
+            .on_complete_with(move |summary| {
+                *summary_clone.lock().unwrap() = Some(summary);
+            })
+            .run()?;
+
+        let summary = summary
+            .lock()?
+            .clone()
+            .expect("on_complete_with was called");
+        assert_eq!(summary.frames, 3);
+        assert_eq!(summary.errors, 0);
+        assert!(summary.duration > Duration::ZERO);
+        assert!(summary.average_fps > 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn on_progress() -> Result<()> {
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let progress_clone = progress.clone();
+        let mut pipeline = Hypetrigger::new();
+        pipeline
+            .test_input()
+            .add_ffmpeg_args(&["-frames:v", "3"])
+            .add_trigger(SimpleTrigger::new(|_| {}))
+            .on_progress(move |info| progress_clone.lock().unwrap().push(info.clone()));
+        let command = command_to_string(pipeline.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-progress \"pipe:2\""));
+        pipeline.run()?;
+
+        let progress = progress.lock()?;
+        let last = progress.last().expect("on_progress was called");
+        assert!(last.done);
+        assert_eq!(last.frame, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn dup_drop_log() -> Result<()> {
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let progress_clone = progress.clone();
+        let mut pipeline = Hypetrigger::new();
+        pipeline.on_progress(move |info| progress_clone.lock().unwrap().push(info.clone()));
+        let mut state = RunState::default();
+        let mut log = |line: &str| {
+            let event = FfmpegEvent::Log(LogLevel::Unknown, line.to_string());
+            pipeline.handle_event(event, &mut state)
+        };
+        for line in ["frame=20", "dup_frames=2", "drop_frames=0", "progress=end"] {
+            log(line)?;
+        }
+        // The final stats line, after the last progress block
+        log("frame=   20 fps=0.0 q=-0.0 Lsize=N/A time=00:00:01.00 bitrate=N/A dup=3 drop=1 speed=10x")?;
+
+        assert_eq!((state.ffmpeg_duplicated, state.ffmpeg_dropped), (3, 1));
+        let progress = progress.lock()?;
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].dup_frames, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rgb48() -> Result<()> {
+        let samples: [u16; 6] = [0, 256, 65535, 4096, 32768, 12345];
+        let frame = OutputVideoFrame {
+            width: 2,
+            height: 1,
+            pix_fmt: "rgb48le".to_string(),
+            output_index: 0,
+            data: samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+            frame_num: 0,
+            timestamp: 0.0,
+        };
+        let (image, original) = decode_frame(frame)?;
+        let original = original.expect("16-bit original is kept");
+        assert_eq!(original.color(), ColorType::Rgb16);
+        assert_eq!(original.as_rgb16().unwrap().as_raw(), &samples.to_vec());
+        assert_eq!(image.as_raw(), &vec![0, 1, 255, 16, 128, 48]);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rgba() -> Result<()> {
+        let frame = OutputVideoFrame {
+            width: 2,
+            height: 1,
+            pix_fmt: "rgba".to_string(),
+            output_index: 0,
+            data: vec![10, 20, 30, 0, 40, 50, 60, 255],
+            frame_num: 0,
+            timestamp: 0.0,
+        };
+        let (image, original) = decode_frame(frame)?;
+        assert_eq!(original.map(|o| o.color()), Some(ColorType::Rgba8));
+        assert_eq!(image.as_raw(), &vec![10, 20, 30, 40, 50, 60]);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_gray() -> Result<()> {
+        let frame = OutputVideoFrame {
+            width: 2,
+            height: 1,
+            pix_fmt: "gray".to_string(),
+            output_index: 0,
+            data: vec![0, 200],
+            frame_num: 0,
+            timestamp: 0.0,
+        };
+        let (image, original) = decode_frame(frame)?;
+        assert_eq!(original.map(|o| o.color()), Some(ColorType::L8));
+        assert_eq!(image.as_raw(), &vec![0, 0, 0, 200, 200, 200]);
+        Ok(())
+    }
+
+    #[test]
+    fn pix_fmt() -> Result<()> {
+        let formats = Arc::new(Mutex::new(Vec::new()));
+        for pix_fmt in ["rgb24", "gray"] {
+            let formats_clone = formats.clone();
+            Hypetrigger::new()
+                .test_input()
+                .set_pix_fmt(pix_fmt)?
+                .add_ffmpeg_args(&["-frames:v", "1"])
+                .add_trigger(SimpleTrigger::new(move |frame| {
+                    let color = frame.original.as_ref().map(|original| original.color());
+                    formats_clone.lock().unwrap().push(color);
+                }))
+                .run()?;
+        }
+        assert_eq!(*formats.lock()?, vec![None, Some(ColorType::L8)]);
+
+        let error = Hypetrigger::new().set_pix_fmt("yuv420p").err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported pix_fmt \"yuv420p\", expected one of: rgb24, rgb48le, rgba, gray"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hdr_frame() -> Result<()> {
+        let colors = Arc::new(Mutex::new(Vec::new()));
+        let colors_clone = colors.clone();
+        Hypetrigger::new()
+            .test_input()
+            .set_pixel_format(PixelFormat::Rgb48)
+            .add_ffmpeg_args(&["-frames:v", "1"])
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                let color = frame.original.as_ref().map(|original| original.color());
+                colors_clone.lock().unwrap().push(color);
+            }))
+            .run()?;
+
+        assert_eq!(*colors.lock()?, vec![Some(ColorType::Rgb16)]);
+        Ok(())
+    }
+
+    #[test]
+    fn scene_change() -> Result<()> {
+        let count_frames = |sampling| -> Result<usize> {
+            let frames = Arc::new(Mutex::new(0));
+            let frames_clone = frames.clone();
+            Hypetrigger::new()
+                .test_input()
+                .set_sampling(sampling)
+                .add_trigger(SimpleTrigger::new(move |_| {
+                    *frames_clone.lock().unwrap() += 1;
+                }))
+                .run()?;
+            let frames = *frames.lock()?;
+            Ok(frames)
+        };
+
+        let fixed = count_frames(Sampling::Fps)?;
+        let scene_change = count_frames(Sampling::SceneChange { threshold: 0.3 })?;
+        assert!(scene_change < fixed, "{} >= {}", scene_change, fixed);
+        Ok(())
+    }
+
+    #[test]
+    fn scene_change_pts_out_of_order() -> Result<()> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = events.clone();
+        let complete_clone = events.clone();
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .set_sampling(Sampling::SceneChange { threshold: 0.3 })
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                let event = format!("{} at {}", frame.frame_num, frame.timestamp);
+                events_clone.lock().unwrap().push(event);
+            }))
+            .on_complete(move || complete_clone.lock().unwrap().push("done".to_string()));
+        let frame = || {
+            FfmpegEvent::OutputFrame(OutputVideoFrame {
+                width: 1,
+                height: 1,
+                pix_fmt: "rgb24".to_string(),
+                output_index: 0,
+                data: vec![0; 3],
+                frame_num: 0,
+                timestamp: 0.0,
+            })
+        };
+        let showinfo = |n: u64, pts_time: &str| {
+            let line = format!(
+                "[Parsed_showinfo_1 @ 0x5581] n:{:>4} pts:  12800 pts_time:{:<8} duration:512",
+                n, pts_time
+            );
+            FfmpegEvent::Log(LogLevel::Info, line)
+        };
+
+        // stderr and stdout are read on separate threads, so a frame can
+        // arrive before or after its `showinfo` line
+        let mut state = RunState::default();
+        for event in [
+            showinfo(0, "1.5"),
+            frame(),
+            frame(),
+            frame(),
+            showinfo(1, "4"),
+            FfmpegEvent::Done,
+            showinfo(2, "7.25"),
+            FfmpegEvent::LogEOF,
+        ] {
+            hypetrigger.handle_event(event, &mut state)?;
+        }
+        assert_eq!(
+            *events.lock()?,
+            vec!["0 at 1.5", "1 at 4", "2 at 7.25", "done"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ffmpeg_args() {
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.add_ffmpeg_args(&["-loglevel", "quiet"]);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-loglevel \"quiet\""));
+    }
+
+    #[test]
+    fn input_args() {
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .set_input("thumbnail.png".to_string())
+            .add_input_args(&["-loop", "1"]);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        let loop_index = command.find("-loop \"1\"").unwrap();
+        let input_index = command.find("-i \"thumbnail.png\"").unwrap();
+        assert!(loop_index < input_index);
+    }
+
+    #[test]
+    fn disable_fps_filter() {
+        let mut hypetrigger = Hypetrigger::new();
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("fps="));
+
+        hypetrigger.disable_fps_filter();
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(!command.contains("fps="));
+        assert!(!command.contains("-filter:v"));
+
+        hypetrigger.set_processing_scale(320, 180);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(!command.contains("fps="));
+        assert!(command.contains("-filter:v \"scale=320:180\""));
+    }
+
+    #[test]
+    fn video_stream() {
+        let mut hypetrigger = Hypetrigger::new();
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(!command.contains("-map"));
+
+        hypetrigger.set_video_stream(1);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-map \"0:v:1\""));
+    }
+
+    #[test]
+    fn video_stream_out_of_range() {
+        let result = Hypetrigger::new()
+            .test_input()
+            .set_video_stream(1)
+            .add_trigger(SimpleTrigger::new(|_| {}))
+            .run();
+        assert_eq!(
+            result.unwrap_err().message,
+            "Video stream 1 out of range, the input has 1 video stream(s)"
+        );
+    }
+
+    #[test]
+    fn reads_once() {
+        let mut hypetrigger = Hypetrigger::new();
+        for (input, reads_once) in [
+            ("video.mp4", false),
+            ("https://example.com/video.mp4", false),
+            ("-", true),
+            ("pipe:0", true),
+            ("tcp://127.0.0.1:9000", true),
+            ("rtmp://example.com/live", true),
+        ] {
+            hypetrigger.set_input(input.to_string());
+            assert_eq!(hypetrigger.reads_once(), reads_once, "{}", input);
+        }
+    }
+
+    #[test]
+    fn fps_mode() {
+        let mut hypetrigger = Hypetrigger::new();
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-vsync \"drop\""));
+
+        hypetrigger.set_fps_mode(FpsMode::Passthrough);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-vsync \"passthrough\""));
+        assert!(!command.contains("\"drop\""));
+    }
+
+    #[test]
+    fn filter_chain() {
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.set_processing_scale(320, 180);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-filter:v \"fps=2,scale=320:180\""));
+
+        hypetrigger.set_filter(
+            FilterChain::new()
+                .fps(2)
+                .crop(640, 360, 0, 0)
+                .scale(320, 180),
+        );
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-filter:v \"fps=2,crop=640:360:0:0,scale=320:180\""));
+    }
+
+    #[test]
+    fn no_hwaccel() {
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.set_hwaccel(None);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(!command.contains("-hwaccel"));
+    }
+
+    #[test]
+    fn image_sequence() -> Result<()> {
+        let dir = std::env::temp_dir().join("hypetrigger-image-sequence");
+        std::fs::create_dir_all(&dir)?;
+        for i in 1..=3 {
+            let image = RgbImage::from_pixel(64, 32, Rgb([i * 50, 0, 0]));
+            image.save(dir.join(format!("frame{:03}.png", i)))?;
+        }
+
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        Hypetrigger::new()
+            .image_sequence(dir.join("frame%03d.png").to_str().unwrap())
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone
+                    .lock()
+                    .unwrap()
+                    .push((frame.frame_num, frame.timestamp));
+            }))
+            .run()?;
+
+        let frames = frames.lock()?;
+        assert_eq!(*frames, vec![(0, 0.0), (1, 0.5), (2, 1.0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn ffmpeg_loglevel() {
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.set_ffmpeg_loglevel("error");
+        assert!(hypetrigger.validate().is_ok());
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-loglevel \"level+error\""));
+
+        // `verbose` keeps printing info and above
+        hypetrigger.set_verbose(true);
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-loglevel \"level+info\""));
+        hypetrigger.set_ffmpeg_loglevel("debug");
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-loglevel \"level+debug\""));
+
+        // Scene changes are timestamped from `info` logs
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .set_ffmpeg_loglevel("error")
+            .set_sampling(Sampling::SceneChange { threshold: 0.3 });
+        let command = command_to_string(hypetrigger.ffmpeg_command().as_inner_mut());
+        assert!(command.contains("-loglevel \"level+info\""));
+
+        hypetrigger.set_ffmpeg_loglevel("loud");
+        assert!(hypetrigger.validate().is_err());
+    }
+
+    #[test]
+    fn zero_fps() {
+        let error = Hypetrigger::new().set_fps(0).validate().unwrap_err();
+        assert_eq!(error.message, "The sampling fps must be at least 1");
+    }
+
+    #[test]
+    fn processing_scale() -> Result<()> {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let sizes_clone = sizes.clone();
+        Hypetrigger::new()
+            .test_input()
+            .set_processing_scale(320, 180)
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                sizes_clone.lock().unwrap().push((
+                    frame.image.dimensions(),
+                    (frame.source_width, frame.source_height),
+                ));
+            }))
+            .run()?;
+
+        let sizes = sizes.lock()?;
+        assert!(!sizes.is_empty());
+        assert!(sizes.iter().all(|size| *size == ((320, 180), (1280, 720))));
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot() -> Result<()> {
+        let frame = Hypetrigger::new().test_input().snapshot(5.0)?;
+        assert_eq!(frame.image.dimensions(), (1280, 720));
+        assert_eq!(frame.timestamp, 5.0);
+        Ok(())
+    }
+
+    #[test]
+    fn probe() -> Result<()> {
+        // Render the test source to a file, since generated inputs have no duration
+        let path = std::env::temp_dir().join("hypetrigger-probe.avi");
+        let mut render = FfmpegCommand::new();
+        render
+            .format("lavfi")
+            .input(FFMPEG_TEST_INPUT)
+            .overwrite()
+            .arg(path.to_str().unwrap());
+        let mut child = render.spawn()?;
+        child.iter()?.for_each(drop);
+        child.as_inner_mut().wait()?;
+
+        let media_info = Hypetrigger::new()
+            .set_input(path.to_str().unwrap().to_string())
+            .probe()?;
+        assert_eq!(media_info.width, 1280);
+        assert_eq!(media_info.height, 720);
+        assert!((media_info.duration.unwrap() - 10.0).abs() < 0.1);
+        Ok(())
+    }
+
+    #[test]
+    fn with_command() -> Result<()> {
+        let frames = Arc::new(Mutex::new(0));
+        let frames_clone = frames.clone();
+        let mut cmd = FfmpegCommand::new();
+        cmd.testsrc() // 10 seconds
+            .args(["-vf", "fps=2"])
+            .rawvideo();
+        Hypetrigger::with_command(cmd)?
+            .add_trigger(SimpleTrigger::new(move |_| {
+                *frames_clone.lock().unwrap() += 1;
+            }))
+            .run()?;
+        assert_eq!(*frames.lock()?, 20);
+
+        let mut cmd = FfmpegCommand::new();
+        cmd.testsrc().args(["-f", "null", "-"]);
+        let error = Hypetrigger::with_command(cmd).err().unwrap();
+        assert_eq!(
+            error.message,
+            "Custom ffmpeg command must output -f rawvideo"
+        );
+
+        let mut cmd = FfmpegCommand::new();
+        cmd.testsrc()
+            .args(["-f", "rawvideo", "-pix_fmt", "yuv420p", "-"]);
+        assert!(Hypetrigger::with_command(cmd).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn run_tokio() -> Result<()> {
+        use tokio_util::sync::CancellationToken;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let frames = Arc::new(Mutex::new(0));
+        let frames_clone = frames.clone();
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .test_input()
+            .add_trigger(SimpleTrigger::new(move |_| {
+                *frames_clone.lock().unwrap() += 1;
+            }));
+        let summary = runtime.block_on(hypetrigger.run_tokio(CancellationToken::new()))?;
+        assert_eq!(summary.frames, 20);
+        assert_eq!(*frames.lock()?, 20);
+
+        // Cancelled before the first frame is handled
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .test_input()
+            .add_trigger(SimpleTrigger::new(|_| {
+                thread::sleep(Duration::from_millis(50));
+            }));
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let summary = runtime.block_on(hypetrigger.run_tokio(cancellation))?;
+        assert!(summary.frames < 20);
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_frames() -> Result<()> {
+        // A 5 fps source upsampled to 20 fps repeats each frame 4 times
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .set_input_format("lavfi")
+            .set_input("testsrc=duration=2:size=320x240:rate=5".to_string())
+            .set_fps(20)
+            .set_skip_duplicate_frames(true)
+            .add_trigger(SimpleTrigger::new(|_| {}));
+        let summary = hypetrigger.run_with_summary()?;
+        assert_eq!(summary.frames, 10);
+        assert!(summary.skipped_duplicates > 0);
+
+        // ffmpeg reports the frames it duplicates itself
+        let summary = hypetrigger
+            .set_skip_duplicate_frames(false)
+            .set_filter(FilterChain::new().custom("null"))
+            .set_fps_mode(FpsMode::Cfr)
+            .add_ffmpeg_args(&["-r", "20"])
+            .run_with_summary()?;
+        assert!(summary.duplicated_frames > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn clamp_fps() -> Result<()> {
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger.test_input().set_fps(120);
+        assert_eq!(hypetrigger.resolve_fps()?, 120);
+        hypetrigger.set_clamp_fps(true);
+        assert_eq!(hypetrigger.resolve_fps()?, 30);
+        hypetrigger.set_fps(2);
+        assert_eq!(hypetrigger.resolve_fps()?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn clamp_fractional_fps() {
+        assert_eq!(clamp_to_source_fps(30, 29.97), 30);
+        assert_eq!(clamp_to_source_fps(60, 29.97), 30);
+        assert_eq!(clamp_to_source_fps(24, 29.97), 24);
+        assert_eq!(clamp_to_source_fps(2, 0.5), 1);
+    }
+
+    #[test]
+    fn timeout() {
+        // A server that accepts the connection but never sends any data
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let input = format!("tcp://{}", listener.local_addr().unwrap());
+
+        let result = Hypetrigger::new()
+            .set_input(input)
+            .set_timeout(Duration::from_secs(1))
+            .run();
+        assert!(result.unwrap_err().is::<TimedOut>());
+    }
+
+    #[test]
+    fn timeout_excludes_triggers() -> Result<()> {
+        let summary = Hypetrigger::new()
+            .test_input()
+            .set_timeout(Duration::from_millis(500))
+            .add_trigger(SimpleTrigger::new(|frame| {
+                if frame.frame_num < 2 {
+                    thread::sleep(Duration::from_millis(800));
+                }
+            }))
+            .run_with_summary()?;
+        assert_eq!(summary.frames, 20);
+        Ok(())
+    }
+
+    #[test]
+    fn ffmpeg_failed() {
+        let error = Hypetrigger::new()
+            .set_input("does-not-exist.mp4".to_string())
+            .run()
+            .unwrap_err();
+        assert!(error.is::<FfmpegFailed>());
+        assert!(error.message.contains("does-not-exist.mp4"));
+        assert!(error.message.contains("No such file or directory"));
+    }
+
+    #[test]
+    fn stop_pipeline() -> Result<()> {
+        struct StopOnFrame2 {
+            frames: Arc<Mutex<Vec<u64>>>,
+        }
+        impl Trigger for StopOnFrame2 {
+            fn on_frame(&self, frame: &Frame) -> Result<()> {
+                self.frames.lock()?.push(frame.frame_num);
+                match frame.frame_num {
+                    2 => Err(StopPipeline.into()),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        Hypetrigger::new()
+            .test_input()
+            .add_trigger(StopOnFrame2 {
+                frames: frames.clone(),
+            })
+            .run()?;
+
+        assert_eq!(*frames.lock()?, vec![0, 1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn monotonic_frame_num() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let hypetrigger = Hypetrigger::new()
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone
+                    .lock()
+                    .unwrap()
+                    .push((frame.frame_num, frame.source_frame_num));
+            }))
+            .clone();
+
+        // ffmpeg's own numbering skips and repeats
+        let mut state = RunState::default();
+        for source_frame_num in [0, 3, 3, 4] {
+            let frame = OutputVideoFrame {
+                width: 1,
+                height: 1,
+                pix_fmt: "rgb24".to_string(),
+                output_index: 0,
+                data: vec![0; 3],
+                frame_num: source_frame_num,
+                timestamp: 0.0,
+            };
+            hypetrigger.handle_event(FfmpegEvent::OutputFrame(frame), &mut state)?;
+        }
+
+        assert_eq!(*frames.lock()?, vec![(0, 0), (1, 3), (2, 3), (3, 4)]);
+        Ok(())
+    }
+
+    #[test]
+    fn run_until() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let mut hypetrigger = Hypetrigger::new();
+        hypetrigger
+            .test_input()
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone.lock().unwrap().push(frame.frame_num);
+            }));
+
+        let frame = hypetrigger
+            .clone()
+            .run_until(|frame| frame.frame_num == 1)?;
+        assert_eq!(frame.map(|frame| frame.frame_num), Some(1));
+        assert_eq!(*frames.lock()?, vec![0, 1]);
+
+        assert!(hypetrigger.run_until(|_| false)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn run_frames() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let summary = Hypetrigger::new()
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone
+                    .lock()
+                    .unwrap()
+                    .push((frame.frame_num, frame.timestamp));
+            }))
+            .run_frames([
+                Frame::new(RgbImage::new(4, 4), 0, 0.0),
+                Frame::new(RgbImage::new(4, 4), 1, 0.5),
+            ])?;
+
+        assert_eq!(*frames.lock()?, vec![(0, 0.0), (1, 0.5)]);
+        assert_eq!(summary.frames, 2);
+        // Measured by the frames' own timestamps, not the sampling `fps`
+        let media_time = summary.realtime_factor * summary.duration.as_secs_f64();
+        assert!((media_time - 0.5).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "ctrlc")]
+    fn interrupt() -> Result<()> {
+        use crate::interrupt::interrupt_thread;
+
+        // Triggers run on the thread that called `run`, which is the one
+        // listening for Ctrl-C
+        let test_thread = thread::current().id();
+        let completed = Arc::new(Mutex::new(false));
+        let completed_clone = completed.clone();
+        let summary = Hypetrigger::new()
+            .test_input()
+            .on_complete(move || *completed_clone.lock().unwrap() = true)
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                if frame.frame_num == 1 {
+                    // Same as pressing Ctrl-C
+                    assert_eq!(interrupt_thread(test_thread), 1);
+                }
+            }))
+            .run_with_summary()?;
+
+        assert!(summary.frames < 20);
+        assert!(*completed.lock()?);
+        assert_eq!(interrupt_thread(test_thread), 0); // no longer listening
+        Ok(())
+    }
+
+    #[test]
+    fn run_each() -> Result<()> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let mut config = Hypetrigger::new();
+        config
+            .set_input_format("lavfi")
+            .add_trigger(SimpleTrigger::new(move |frame| {
+                frames_clone.lock().unwrap().push(frame.frame_num);
+            }));
+
+        config.clone().run_each([
+            "testsrc=duration=1:size=320x240:rate=30",
+            "testsrc=duration=2:size=320x240:rate=30",
+        ])?;
+
+        assert_eq!(*frames.lock()?, vec![0, 1, 0, 1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn run_many() -> Result<()> {
+        let mut config = Hypetrigger::new();
+        config.set_input_format("lavfi");
+        let inputs = vec![
+            "testsrc=duration=1:size=320x240:rate=30".to_string(),
+            "testsrc=duration=3:size=320x240:rate=30".to_string(),
+        ];
+
+        let triggers_made = Arc::new(Mutex::new(0));
+        let summaries = config.run_many(inputs, 2, || {
+            *triggers_made.lock().unwrap() += 1;
+            let trigger: Arc<dyn Trigger> = Arc::new(SimpleTrigger::new(|_frame| {}));
+            vec![trigger]
+        })?;
+
+        assert_eq!(*triggers_made.lock()?, 2);
+        let frames: Vec<u64> = summaries.iter().map(|summary| summary.frames).collect();
+        assert_eq!(frames, vec![2, 6]);
+        Ok(())
+    }
+
+    #[test]
+    fn event_channel() -> Result<()> {
+        let (mut hypetrigger, event_rx) = Hypetrigger::with_event_channel();
+        hypetrigger.test_input().run()?;
+        drop(hypetrigger);
+
+        let events: Vec<PipelineEvent> = event_rx.iter().collect();
+        let frames = events
+            .iter()
+            .filter(|event| matches!(event, PipelineEvent::Frame { .. }))
+            .count();
+        assert_eq!(frames, 20);
+        assert!(matches!(
+            events.last(),
+            Some(PipelineEvent::Complete(summary)) if summary.frames == 20
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn on_finish() {
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let finished_clone = finished.clone();
+        let result = Hypetrigger::new()
+            .set_input("does-not-exist.mp4".to_string())
+            .on_finish(move |result| {
+                let message = result.as_ref().map_err(|e| e.message.clone());
+                finished_clone.lock().unwrap().push(message.err());
+            })
+            .run();
+
+        assert!(result.is_err());
+        let finished = finished.lock().unwrap();
+        assert_eq!(finished.len(), 1);
+        assert!(finished[0].as_ref().unwrap().contains("does-not-exist.mp4"));
+    }
 }
@@ -0,0 +1,71 @@
+use crate::error::{Error, Result};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction {
+    /// The index of the class with the highest confidence.
+    pub class_index: usize,
+
+    // pub label: String, // TODO
+    /// Confidence interval in the prediction, in the range [0, 1].
+    pub confidence: f32,
+}
+
+impl Prediction {
+    /// Whether this prediction meets an (optional) confidence threshold and
+    /// (optional) list of accepted classes.
+    pub fn passes(&self, min_confidence: Option<f32>, classes: Option<&[usize]>) -> bool {
+        let confident = match min_confidence {
+            Some(min) => self.confidence >= min,
+            None => true,
+        };
+        let accepted = match classes {
+            Some(classes) => classes.contains(&self.class_index),
+            None => true,
+        };
+        confident && accepted
+    }
+}
+
+/// Normalize a square image's interleaved 8-bit pixels to `[0, 1]`, failing
+/// if the buffer isn't exactly `size * size * channels` bytes.
+pub fn normalize_pixels(buf: &[u8], size: u64, channels: u64) -> Result<Vec<f32>> {
+    let bytes = (size * size * channels) as usize;
+    if buf.len() != bytes {
+        return Err(Error::from_display(format!(
+            "Expected a {}x{}x{} image buffer of {} bytes, got {}",
+            size,
+            size,
+            channels,
+            bytes,
+            buf.len()
+        )));
+    }
+    Ok(buf.iter().map(|pixel| *pixel as f32 / 255.0).collect())
+}
+
+/// Split the model's confidences for a whole batch into one (argmax)
+/// prediction per image.
+pub fn predictions_from_output(output: &[f32], batch_size: usize) -> Vec<Prediction> {
+    let classes = output.len() / batch_size.max(1);
+    output
+        .chunks(classes.max(1))
+        .take(batch_size)
+        .map(|confidences| {
+            // Calculate argmax of the output
+            let (max_idx, max_val) = confidences.iter().enumerate().fold(
+                (0, confidences[0]),
+                |(idx_max, val_max), (idx, val)| {
+                    if &val_max > val {
+                        (idx_max, val_max)
+                    } else {
+                        (idx, *val)
+                    }
+                },
+            );
+            Prediction {
+                class_index: max_idx,
+                confidence: max_val,
+            }
+        })
+        .collect()
+}
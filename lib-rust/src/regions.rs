@@ -0,0 +1,100 @@
+use crate::error::{Error, Result};
+use crate::photon::Crop;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A registry of named regions of the screen (e.g. `"score"` or
+/// `"kill_feed"`), so that the layout of a particular game UI can be defined
+/// in one place (or one JSON file) instead of as `Crop` literals throughout
+/// the code. In JSON, it's an object mapping each name to a `Crop`:
+///
+/// ```json
+/// {
+///   "score": { "left_percent": 80, "top_percent": 5, "width_percent": 10, "height_percent": 5 }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RegionSet {
+    pub regions: HashMap<String, Crop>,
+}
+
+impl RegionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a region set from a JSON string
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Read a region set from a JSON file
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Serialize the region set to pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Add or replace a named region
+    pub fn insert(&mut self, name: &str, crop: Crop) -> &mut Self {
+        self.regions.insert(name.to_string(), crop);
+        self
+    }
+
+    /// Look up a region by name, failing with the list of known names if it
+    /// isn't defined
+    pub fn get(&self, name: &str) -> Result<Crop> {
+        self.regions.get(name).copied().ok_or_else(|| {
+            let mut names: Vec<&str> = self.regions.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            Error::from_display(format!(
+                "No region named \"{}\", expected one of: {}",
+                name,
+                names.join(", ")
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RegionSet;
+    use crate::{
+        error::Result,
+        photon::{Anchor, Crop},
+    };
+
+    #[test]
+    fn region_set() -> Result<()> {
+        let regions = RegionSet::from_json(
+            r#"{
+                "score": { "left_percent": 80, "top_percent": 5, "width_percent": 10, "height_percent": 5 },
+                "kill_feed": { "left_percent": 70, "top_percent": 20, "width_percent": 25, "height_percent": 15 }
+            }"#,
+        )?;
+
+        assert_eq!(
+            regions.get("score")?,
+            Crop {
+                left_percent: 80.0,
+                top_percent: 5.0,
+                width_percent: 10.0,
+                height_percent: 5.0,
+                anchor: Anchor::TopLeft,
+            }
+        );
+        let error = regions.get("health").unwrap_err();
+        assert!(error.message.contains("kill_feed, score"));
+        assert_eq!(RegionSet::from_json(&regions.to_json()?)?, regions);
+        Ok(())
+    }
+}
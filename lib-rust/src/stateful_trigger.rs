@@ -0,0 +1,75 @@
+use crate::error::Result;
+use crate::trigger::{Frame, Trigger};
+use std::sync::{Arc, Mutex};
+
+pub type StatefulTriggerCallback<S> = Arc<dyn Fn(&mut S, &Frame) + Send + Sync>;
+
+/// A Trigger that keeps mutable state across frames (e.g. a counter, a streak
+/// detector, or the last few OCR results for smoothing), and passes it to a
+/// callback on each frame along with the frame itself.
+///
+/// The state is behind an `Arc<Mutex>`, so it can still be read with `state()`
+/// after the trigger has been added to a pipeline.
+pub struct StatefulTrigger<S> {
+    pub state: Arc<Mutex<S>>,
+    pub callback: StatefulTriggerCallback<S>,
+}
+
+impl<S> Trigger for StatefulTrigger<S>
+where
+    S: Send,
+{
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        let mut state = self.state.lock()?;
+        (self.callback)(&mut state, frame);
+        Ok(())
+    }
+}
+
+impl<S> StatefulTrigger<S> {
+    pub fn new<T>(initial_state: S, on_frame: T) -> Self
+    where
+        T: Fn(&mut S, &Frame) + Send + Sync + 'static,
+    {
+        Self {
+            state: Arc::new(Mutex::new(initial_state)),
+            callback: Arc::new(on_frame),
+        }
+    }
+
+    /// A handle to the state, which stays valid after the trigger is moved
+    /// into a pipeline
+    pub fn state(&self) -> Arc<Mutex<S>> {
+        self.state.clone()
+    }
+}
+
+impl<S> Clone for StatefulTrigger<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            callback: self.callback.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatefulTrigger;
+    use crate::{error::Result, pipeline::Hypetrigger};
+
+    #[test]
+    fn stateful_trigger() -> Result<()> {
+        let trigger = StatefulTrigger::new(0u64, |count, _frame| *count += 1);
+        let count = trigger.state();
+
+        Hypetrigger::new()
+            .test_input() // 10 seconds
+            .set_fps(2)
+            .add_trigger(trigger)
+            .run()?;
+
+        assert_eq!(*count.lock()?, 20);
+        Ok(())
+    }
+}
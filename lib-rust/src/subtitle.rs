@@ -0,0 +1,227 @@
+use crate::error::Result;
+use crate::trigger::{Frame, Trigger};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// File format written by a `SubtitleTrigger`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    /// SubRip (`.srt`)
+    #[default]
+    Srt,
+
+    /// WebVTT (`.vtt`), e.g. for a `<track>` in the browser
+    Vtt,
+}
+
+impl SubtitleFormat {
+    /// Guess the format from a file extension, defaulting to `Srt`
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("vtt") => SubtitleFormat::Vtt,
+            _ => SubtitleFormat::Srt,
+        }
+    }
+}
+
+/// A single subtitle, shown from `start` until `end` (in seconds)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// Collects detections (e.g. OCR text) with their timestamps, and writes them
+/// to a subtitle file when the pipeline completes, to review them over the
+/// video in a player.
+///
+/// Detections are recorded from another trigger's callback with `record`, on
+/// a clone of this trigger. The file is written when the pipeline ends, after
+/// every trigger (including asynchronous ones) has been flushed.
+#[derive(Clone)]
+pub struct SubtitleTrigger {
+    pub path: PathBuf,
+    pub format: SubtitleFormat,
+
+    /// How long each detection stays on screen, in seconds, unless the next
+    /// cue starts first. Repeated detections of the same text within this
+    /// time extend a single cue.
+    pub cue_duration: f64,
+
+    /// Every `(timestamp, text)` recorded so far, shared between clones
+    detections: Arc<Mutex<Vec<(f64, String)>>>,
+}
+
+impl Trigger for SubtitleTrigger {
+    fn on_frame(&self, _frame: &Frame) -> Result<()> {
+        Ok(())
+    }
+
+    /// Write the subtitle file.
+    fn on_end(&self) -> Result<()> {
+        self.write()
+    }
+}
+
+impl SubtitleTrigger {
+    /// Write subtitles to the given path, in the format of its extension
+    /// (`.srt` or `.vtt`).
+    pub fn new<P>(path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        let path = path.into();
+        Self {
+            format: SubtitleFormat::from_path(&path),
+            path,
+            cue_duration: 2.0,
+            detections: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Record a detection at the given timestamp. Empty (or whitespace) text
+    /// is ignored.
+    pub fn record<S>(&self, timestamp: f64, text: S)
+    where
+        S: AsRef<str>,
+    {
+        let text = text.as_ref().trim();
+        if text.is_empty() {
+            return;
+        }
+        if let Ok(mut detections) = self.detections.lock() {
+            detections.push((timestamp, text.to_string()));
+        }
+    }
+
+    /// Group the detections recorded so far into cues.
+    pub fn cues(&self) -> Result<Vec<Cue>> {
+        let mut detections = self.detections.lock()?.clone();
+        detections.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Merge repeated detections of the same text, tracking when each cue's
+        // text was last seen
+        let mut cues: Vec<(Cue, f64)> = Vec::new();
+        for (timestamp, text) in detections {
+            match cues.last_mut() {
+                Some((cue, last_seen))
+                    if cue.text == text && timestamp - *last_seen <= self.cue_duration =>
+                {
+                    *last_seen = timestamp;
+                }
+                _ => cues.push((
+                    Cue {
+                        start: timestamp,
+                        end: timestamp,
+                        text,
+                    },
+                    timestamp,
+                )),
+            }
+        }
+
+        let next_starts: Vec<Option<f64>> = cues
+            .iter()
+            .skip(1)
+            .map(|(cue, _)| Some(cue.start))
+            .chain([None])
+            .collect();
+        Ok(cues
+            .into_iter()
+            .zip(next_starts)
+            .map(|((mut cue, last_seen), next_start)| {
+                let end = last_seen + self.cue_duration;
+                cue.end = next_start.map_or(end, |next_start| end.min(next_start));
+                cue
+            })
+            .collect())
+    }
+
+    /// Render the detections recorded so far in the trigger's `format`.
+    pub fn render(&self) -> Result<String> {
+        let cues = self.cues()?;
+        let mut string = String::new();
+        if self.format == SubtitleFormat::Vtt {
+            string += "WEBVTT\n\n";
+        }
+        for (i, cue) in cues.iter().enumerate() {
+            if self.format == SubtitleFormat::Srt {
+                string += &format!("{}\n", i + 1);
+            }
+            string += &format!(
+                "{} --> {}\n{}\n\n",
+                format_timecode(cue.start, self.format),
+                format_timecode(cue.end, self.format),
+                cue.text
+            );
+        }
+        Ok(string)
+    }
+
+    /// Write the detections recorded so far to `path`.
+    pub fn write(&self) -> Result<()> {
+        Ok(fs::write(&self.path, self.render()?)?)
+    }
+}
+
+/// Formats a timestamp as a subtitle timecode, e.g. `"01:02:03,456"` for SRT
+/// or `"01:02:03.456"` for WebVTT. Negative timestamps are clamped to zero.
+pub fn format_timecode(seconds: f64, format: SubtitleFormat) -> String {
+    let milliseconds = (seconds.max(0.0) * 1000.0).round() as u64;
+    let separator = match format {
+        SubtitleFormat::Srt => ',',
+        SubtitleFormat::Vtt => '.',
+    };
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        milliseconds / 3_600_000,
+        milliseconds / 60_000 % 60,
+        milliseconds / 1000 % 60,
+        separator,
+        milliseconds % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timecode, SubtitleFormat, SubtitleTrigger};
+    use crate::{error::Result, trigger::Trigger};
+    use std::fs;
+
+    #[test]
+    fn srt() -> Result<()> {
+        let path = std::env::temp_dir().join("hypetrigger-subtitles.srt");
+        let subtitles = SubtitleTrigger {
+            cue_duration: 2.5,
+            ..SubtitleTrigger::new(&path)
+        };
+        let recorder = subtitles.clone();
+        recorder.record(1.0, "Hello");
+        recorder.record(2.0, "Hello\n");
+        recorder.record(2.5, "");
+        recorder.record(3.0, "World");
+
+        subtitles.on_end()?;
+
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            "1\n00:00:01,000 --> 00:00:03,000\nHello\n\n\
+             2\n00:00:03,000 --> 00:00:05,500\nWorld\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn timecodes() {
+        assert_eq!(format_timecode(0.0, SubtitleFormat::Srt), "00:00:00,000");
+        assert_eq!(
+            format_timecode(3723.456, SubtitleFormat::Vtt),
+            "01:02:03.456"
+        );
+        assert_eq!(SubtitleTrigger::new("out.vtt").format, SubtitleFormat::Vtt);
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Number of frames a `TeeWriter` buffers before dropping new ones
+pub const TEE_CAPACITY: usize = 8;
+
+/// How often the writer thread checks whether it should give up on a FIFO that
+/// nothing is reading (or that is full)
+const TEE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a dropped `TeeWriter` keeps waiting for a stalled consumer before
+/// discarding the frames that are still queued
+pub const TEE_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Copies the raw frames from ffmpeg to a file or named pipe (FIFO), so that
+/// another process can consume them while the pipeline runs its triggers. See
+/// `Hypetrigger::set_tee_output`.
+///
+/// Writing happens on a separate thread, so a slow consumer never stalls the
+/// pipeline: once `TEE_CAPACITY` frames are waiting, new frames are dropped
+/// until it catches up. Until the other end of a FIFO is opened for reading,
+/// only the first `TEE_CAPACITY` frames are kept.
+///
+/// Dropping it waits for the queued frames to be written, unless the consumer
+/// makes no progress for `TEE_CLOSE_TIMEOUT` (or never opened the FIFO).
+pub struct TeeWriter {
+    tx: Option<SyncSender<Vec<u8>>>,
+    closed: Arc<AtomicBool>,
+    join_handle: Option<JoinHandle<()>>,
+
+    /// Number of frames dropped so far. See `dropped_frames`.
+    pub dropped: Arc<AtomicU64>,
+}
+
+impl TeeWriter {
+    /// Spawn the writer thread for the given path. Regular files are created
+    /// (or truncated) when the thread starts.
+    pub fn spawn(path: PathBuf) -> Self {
+        let (tx, rx) = sync_channel::<Vec<u8>>(TEE_CAPACITY);
+        let closed = Arc::new(AtomicBool::new(false));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let closed_clone = closed.clone();
+        let dropped_clone = dropped.clone();
+        let join_handle = thread::spawn(move || {
+            if let Err(e) = write_frames(&path, &rx, &closed_clone, &dropped_clone) {
+                // e.g. the consumer closed its end of the pipe
+                eprintln!("[tee] Failed to write to {}: {}", path.display(), e);
+            }
+            // Whatever is left was never written
+            dropped_clone.fetch_add(rx.try_iter().count() as u64, Ordering::Relaxed);
+        });
+        Self {
+            tx: Some(tx),
+            closed,
+            join_handle: Some(join_handle),
+            dropped,
+        }
+    }
+
+    /// Queue a raw frame to be written, or drop it if the queue is full.
+    pub fn write(&self, data: Vec<u8>) {
+        match self.tx.as_ref().map(|tx| tx.try_send(data)) {
+            Some(Ok(())) | None => {}
+            Some(Err(TrySendError::Full(_) | TrySendError::Disconnected(_))) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Number of frames that were never written, because the consumer wasn't
+    /// keeping up (or had gone away).
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for TeeWriter {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        drop(self.tx.take());
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle.join().ok();
+        }
+    }
+}
+
+/// The body of the writer thread: open `path`, then write each frame from
+/// `rx` until the `TeeWriter` is dropped.
+fn write_frames(
+    path: &Path,
+    rx: &Receiver<Vec<u8>>,
+    closed: &AtomicBool,
+    dropped: &AtomicU64,
+) -> io::Result<()> {
+    let mut file = match open(path, closed)? {
+        Some(file) => file,
+        None => return Ok(()), // nothing ever opened the FIFO
+    };
+    while let Ok(data) = rx.recv() {
+        if let Err(e) = write_all(&mut file, &data, closed) {
+            dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Open `path` for writing without blocking on a FIFO that has no reader yet,
+/// retrying until it gets one. `None` if the `TeeWriter` was dropped first.
+fn open(path: &Path, closed: &AtomicBool) -> io::Result<Option<File>> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NONBLOCK);
+    }
+    loop {
+        match options.open(path) {
+            Ok(file) => return Ok(Some(file)),
+            Err(e) if is_unread_fifo(&e) && closed.load(Ordering::Relaxed) => return Ok(None),
+            Err(e) if is_unread_fifo(&e) => thread::sleep(TEE_POLL_INTERVAL),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Like `Write::write_all`, but waits for a (non-blocking) FIFO to have room,
+/// giving up once the `TeeWriter` is dropped and the consumer has made no
+/// progress for `TEE_CLOSE_TIMEOUT`.
+fn write_all(file: &mut File, mut data: &[u8], closed: &AtomicBool) -> io::Result<()> {
+    let mut last_progress = Instant::now();
+    while !data.is_empty() {
+        match file.write(data) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                data = &data[n..];
+                last_progress = Instant::now();
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if closed.load(Ordering::Relaxed) && last_progress.elapsed() >= TEE_CLOSE_TIMEOUT {
+                    let message = "the consumer stopped reading";
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, message));
+                }
+                wait_writable(file, TEE_POLL_INTERVAL);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Whether opening a FIFO failed only because nothing has opened it for
+/// reading yet
+#[cfg(unix)]
+fn is_unread_fifo(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENXIO)
+}
+
+#[cfg(not(unix))]
+fn is_unread_fifo(_e: &io::Error) -> bool {
+    false
+}
+
+/// Block until `file` can be written to, or `timeout` passes.
+#[cfg(unix)]
+fn wait_writable(file: &File, timeout: Duration) {
+    use std::os::unix::io::AsRawFd;
+    let mut pollfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    // SAFETY: `pollfd` is a single valid entry and `file` outlives the call
+    unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+}
+
+#[cfg(not(unix))]
+fn wait_writable(_file: &File, timeout: Duration) {
+    thread::sleep(timeout);
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{TeeWriter, TEE_CAPACITY, TEE_CLOSE_TIMEOUT};
+    use crate::error::Result;
+    use std::{
+        fs::File,
+        io::Read,
+        path::PathBuf,
+        process::Command,
+        sync::atomic::Ordering,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    fn make_fifo(name: &str) -> Result<PathBuf> {
+        let path = std::env::temp_dir().join(name);
+        std::fs::remove_file(&path).ok();
+        let status = Command::new("mkfifo").arg(&path).status()?;
+        assert!(status.success());
+        Ok(path)
+    }
+
+    #[test]
+    fn tee_to_fifo() -> Result<()> {
+        let path = make_fifo("hypetrigger-tee.fifo")?;
+        let tee = TeeWriter::spawn(path.clone());
+
+        // Opening the read end lets the writer thread open the write end
+        let mut reader = File::open(&path)?;
+        tee.write(vec![1, 2, 3]);
+        tee.write(vec![4, 5, 6]);
+        let mut bytes = [0; 6];
+        reader.read_exact(&mut bytes)?;
+        assert_eq!(bytes, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(tee.dropped_frames(), 0);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn unread_fifo() -> Result<()> {
+        let path = make_fifo("hypetrigger-tee-unread.fifo")?;
+        let tee = TeeWriter::spawn(path.clone());
+
+        // Nothing ever opens the read end, but writing never blocks
+        let start = Instant::now();
+        for _ in 0..100 {
+            tee.write(vec![0; 1024]);
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(tee.dropped_frames(), 100 - TEE_CAPACITY as u64);
+
+        // Nor does dropping it, which discards the queued frames
+        let dropped = tee.dropped.clone();
+        let start = Instant::now();
+        drop(tee);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(dropped.load(Ordering::Relaxed), 100);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn stalled_fifo() -> Result<()> {
+        let path = make_fifo("hypetrigger-tee-stalled.fifo")?;
+        let tee = TeeWriter::spawn(path.clone());
+
+        // The consumer opens the pipe but never reads more than the pipe buffer
+        let _reader = File::open(&path)?;
+        tee.write(vec![0; 4 << 20]);
+        tee.write(vec![0; 4 << 20]);
+        thread::sleep(Duration::from_millis(100));
+
+        // Dropping it gives up on the stalled frames instead of hanging
+        let dropped = tee.dropped.clone();
+        let start = Instant::now();
+        drop(tee);
+        assert!(start.elapsed() < TEE_CLOSE_TIMEOUT + Duration::from_millis(500));
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
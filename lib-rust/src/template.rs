@@ -0,0 +1,230 @@
+use crate::error::Result;
+use crate::photon::Crop;
+use crate::trigger::{Frame, Trigger};
+use image::{imageops, GrayImage, RgbImage};
+use std::sync::Arc;
+
+pub type TemplateTriggerCallback = Arc<dyn Fn(TemplateMatch) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateMatch {
+    /// Left edge of the best match, in pixels from the left of the frame
+    pub x: u32,
+
+    /// Top edge of the best match, in pixels from the top of the frame
+    pub y: u32,
+
+    /// Normalized cross-correlation of the template and the match, from `-1.0`
+    /// to `1.0` (identical, up to brightness and contrast)
+    pub score: f32,
+
+    pub timestamp: f64,
+    pub frame_num: u64,
+}
+
+/// Detects a fixed sprite or icon (e.g. a kill feed icon) by sliding the
+/// template over the frame and scoring each position with normalized
+/// cross-correlation, on grayscale versions of both images.
+///
+/// Matching costs `O(W * H * w * h)` for a `W`x`H` search area and a `w`x`h`
+/// template, so keep the `search_region` as small as possible (or downscale
+/// with `Hypetrigger::set_processing_scale`, scaling the template to match).
+#[derive(Clone)]
+pub struct TemplateTrigger {
+    pub template: RgbImage,
+
+    /// Minimum score for the callback to run, from `-1.0` to `1.0`. Around
+    /// `0.9` is a reasonable starting point.
+    pub threshold: f32,
+
+    /// The region of the frame to search in. `None` searches the whole frame.
+    pub search_region: Option<Crop>,
+
+    pub callback: Option<TemplateTriggerCallback>,
+}
+
+impl Trigger for TemplateTrigger {
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        // 1. crop to the search region
+        let (x1, y1, x2, y2) = match &self.search_region {
+            Some(crop) => crop.bounds(frame.image.width(), frame.image.height()),
+            None => (0, 0, frame.image.width(), frame.image.height()),
+        };
+        let (width, height) = (x2.saturating_sub(x1), y2.saturating_sub(y1));
+        if width == 0 || height == 0 {
+            return Ok(()); // the search region is outside the frame
+        }
+        let region = imageops::crop_imm(&frame.image, x1, y1, width, height).to_image();
+
+        // 2. find the best match
+        let best = match best_match(
+            &imageops::grayscale(&region),
+            &imageops::grayscale(&self.template),
+        ) {
+            Some(best) => best,
+            None => return Ok(()), // template is larger than the search region
+        };
+
+        // 3. callback
+        let (x, y, score) = best;
+        if score < self.threshold {
+            return Ok(());
+        }
+        if let Some(callback) = &self.callback {
+            callback(TemplateMatch {
+                x: x1 + x,
+                y: y1 + y,
+                score,
+                timestamp: frame.timestamp,
+                frame_num: frame.frame_num,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl TemplateTrigger {
+    pub fn new<T>(template: RgbImage, threshold: f32, on_match: T) -> Self
+    where
+        T: Fn(TemplateMatch) + Send + Sync + 'static,
+    {
+        Self {
+            template,
+            threshold,
+            search_region: None,
+            callback: Some(Arc::new(on_match)),
+        }
+    }
+}
+
+/// Find the position `(x, y, score)` in `image` where `template` has the
+/// highest normalized cross-correlation. Returns `None` if the template
+/// doesn't fit inside the image.
+///
+/// Flat (single-color) windows have no defined correlation, and score `0.0`.
+pub fn best_match(image: &GrayImage, template: &GrayImage) -> Option<(u32, u32, f32)> {
+    let (width, height) = image.dimensions();
+    let (t_width, t_height) = template.dimensions();
+    if t_width == 0 || t_height == 0 || t_width > width || t_height > height {
+        return None;
+    }
+
+    // Zero-mean template, so that the numerator doesn't depend on the mean of
+    // each window
+    let t_count = (t_width * t_height) as f64;
+    let t_mean = template.pixels().map(|p| p[0] as f64).sum::<f64>() / t_count;
+    let t_diff: Vec<f64> = template.pixels().map(|p| p[0] as f64 - t_mean).collect();
+    let t_norm = t_diff.iter().map(|d| d * d).sum::<f64>().sqrt();
+
+    // Summed-area tables of the image and its square, for the variance of
+    // each window in constant time
+    let stride = width as usize + 1;
+    let mut sum = vec![0.0; stride * (height as usize + 1)];
+    let mut sum_sq = sum.clone();
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let value = image.get_pixel(x as u32, y as u32)[0] as f64;
+            let i = (y + 1) * stride + x + 1;
+            sum[i] = value + sum[i - 1] + sum[i - stride] - sum[i - stride - 1];
+            sum_sq[i] = value * value + sum_sq[i - 1] + sum_sq[i - stride] - sum_sq[i - stride - 1];
+        }
+    }
+    let window_sum = |table: &[f64], x: usize, y: usize| {
+        let (x2, y2) = (x + t_width as usize, y + t_height as usize);
+        table[y2 * stride + x2] - table[y * stride + x2] - table[y2 * stride + x]
+            + table[y * stride + x]
+    };
+
+    let mut best = (0, 0, f32::MIN);
+    for y in 0..=(height - t_height) {
+        for x in 0..=(width - t_width) {
+            let (xu, yu) = (x as usize, y as usize);
+            let w_sum = window_sum(&sum, xu, yu);
+            let w_var = window_sum(&sum_sq, xu, yu) - w_sum * w_sum / t_count;
+            let denominator = w_var.max(0.0).sqrt() * t_norm;
+
+            let score = if denominator < f64::EPSILON {
+                0.0
+            } else {
+                let mut numerator = 0.0;
+                for ty in 0..t_height {
+                    for tx in 0..t_width {
+                        let value = image.get_pixel(x + tx, y + ty)[0] as f64;
+                        numerator += value * t_diff[(ty * t_width + tx) as usize];
+                    }
+                }
+                (numerator / denominator) as f32
+            };
+
+            if score > best.2 {
+                best = (x, y, score);
+            }
+        }
+    }
+    Some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TemplateTrigger;
+    use crate::{
+        error::Result,
+        photon::{Anchor, Crop},
+        trigger::{Frame, Trigger},
+    };
+    use image::{imageops, Rgb, RgbImage};
+    use std::sync::{Arc, Mutex};
+
+    /// Deterministic noise, so that every position of the frame is distinct
+    fn noise(width: u32, height: u32, seed: u32) -> RgbImage {
+        let mut state = seed;
+        RgbImage::from_fn(width, height, |_, _| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let value = (state >> 16) as u8;
+            Rgb([value, value / 2, 255 - value])
+        })
+    }
+
+    #[test]
+    fn template_match() -> Result<()> {
+        let template = noise(12, 8, 1);
+        let mut image = noise(160, 90, 2);
+        imageops::replace(&mut image, &template, 100, 50);
+
+        let matches = Arc::new(Mutex::new(Vec::new()));
+        let matches_clone = matches.clone();
+        let mut trigger = TemplateTrigger::new(template, 0.9, move |result| {
+            matches_clone.lock().unwrap().push(result)
+        });
+        trigger.search_region = Some(Crop {
+            left_percent: 50.0,
+            top_percent: 50.0,
+            width_percent: 50.0,
+            height_percent: 50.0,
+            anchor: Anchor::TopLeft,
+        });
+
+        trigger.run_on_image(image)?;
+
+        let matches = matches.lock()?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].x, matches[0].y), (100, 50));
+        assert!(matches[0].score > 0.99);
+        Ok(())
+    }
+
+    #[test]
+    fn empty_search_region() -> Result<()> {
+        let template = noise(12, 8, 1);
+        let mut trigger = TemplateTrigger::new(template, 0.0, |_| panic!("no match expected"));
+        trigger.search_region = Some(Crop {
+            left_percent: 100.0,
+            top_percent: 0.0,
+            width_percent: 10.0,
+            height_percent: 10.0,
+            anchor: Anchor::TopLeft,
+        });
+        trigger.on_frame(&Frame::new(noise(160, 90, 2), 0, 0.0))
+    }
+}
@@ -1,7 +1,9 @@
+pub use crate::prediction::Prediction;
 use crate::{
     debug::debug_photon_image,
-    error::Result,
-    photon::{ensure_size, ensure_square, rgb_to_photon, rgba32_to_rgb24, Crop},
+    error::{NoneError, Result},
+    photon::{rgba32_to_gray8, rgba32_to_rgb24, Crop, ResizeMode, Transform},
+    prediction::{normalize_pixels, predictions_from_output},
     trigger::{Frame, Trigger},
 };
 use photon_rs::PhotonImage;
@@ -14,26 +16,87 @@ pub const TENSOR_SIZE: u64 = 224;
 /// Color channels expected (RGB)
 pub const TENSOR_CHANNELS: u64 = 3;
 
+/// Color channels expected by grayscale models
+pub const TENSOR_CHANNELS_GRAY: u64 = 1;
+
 /// The key in the hashmap of Runners, used to map Triggers to their Runners
 pub const TENSORFLOW_RUNNER: &str = "tensorflow";
 
+/// Which device TensorFlow should run inference on.
+///
+/// If the model is loaded with `Device::Auto`, TensorFlow's own defaults
+/// apply, which can still be overridden with the `CUDA_VISIBLE_DEVICES`
+/// environment variable (e.g. `CUDA_VISIBLE_DEVICES=-1` to force the CPU, or
+/// `CUDA_VISIBLE_DEVICES=1` for the second GPU).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Device {
+    /// Let TensorFlow choose, preferring a GPU when one is available
+    #[default]
+    Auto,
+
+    /// Only use the CPU, even if a GPU is available
+    Cpu,
+
+    /// Only use the GPU with the given index
+    Gpu(u32),
+}
+
+impl Device {
+    /// The serialized `ConfigProto` that restricts a session to this device,
+    /// for `SessionOptions::set_config`. Empty for `Device::Auto`.
+    pub fn config_proto(&self) -> Vec<u8> {
+        match self {
+            Device::Auto => vec![],
+            // device_count { key: "GPU" value: 0 }
+            Device::Cpu => vec![0x0a, 0x07, 0x0a, 0x03, b'G', b'P', b'U', 0x10, 0x00],
+            // gpu_options { visible_device_list: "<index>" }
+            Device::Gpu(index) => {
+                let index = index.to_string().into_bytes();
+                let mut gpu_options = vec![0x2a, index.len() as u8];
+                gpu_options.extend(index);
+                let mut config = vec![0x32, gpu_options.len() as u8];
+                config.extend(gpu_options);
+                config
+            }
+        }
+    }
+
+    /// Session options that place the session on this device
+    pub fn session_options(&self) -> Result<SessionOptions> {
+        let mut options = SessionOptions::new();
+        if *self != Device::Auto {
+            options.set_config(&self.config_proto())?;
+        }
+        Ok(options)
+    }
+}
+
 pub type TensorflowTriggerCallback = Arc<dyn Fn(TensorflowResult) + Send + Sync>;
 
 pub struct TensorflowTrigger {
+    /// Rotation or mirroring applied to the whole frame, before cropping.
+    pub transform: Option<Transform>,
     pub crop: Option<Crop>,
     pub bundle: SavedModelBundle,
     pub graph: Graph,
     pub callback: Option<TensorflowTriggerCallback>,
-}
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Prediction {
-    /// The index of the class with the highest confidence.
-    pub class_index: usize,
+    /// Only run the callback when the prediction's confidence is at least this
+    /// value. `None` runs the callback on every frame.
+    pub min_confidence: Option<f32>,
+
+    /// Only run the callback when the predicted class is one of these indices.
+    /// `None` runs the callback for any class.
+    pub classes: Option<Vec<usize>>,
 
-    // pub label: String, // TODO
-    /// Confidence interval in the prediction, in the range [0, 1].
-    pub confidence: f32,
+    /// Number of color channels in the model's input tensor: `3` for RGB
+    /// (the default) or `1` for grayscale. Must match the channel count used
+    /// to load the model.
+    pub channels: u64,
+
+    /// How the (cropped) frame is fit to the model's square input. Defaults
+    /// to a center crop; `ResizeMode::Letterbox` keeps the whole frame.
+    pub resize_mode: ResizeMode,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -46,19 +109,24 @@ pub struct TensorflowResult {
 impl Trigger for TensorflowTrigger {
     fn on_frame(&self, frame: &Frame) -> Result<()> {
         // 1. convert raw image to photon
-        let image = rgb_to_photon(&frame.image);
+        let image = frame.to_photon();
 
         // 2. preprocess
         let filtered = self.preprocess_image(image)?;
 
         // 3. image classification
         let rgba32 = filtered.get_raw_pixels();
-        let rgb24 = rgba32_to_rgb24(rgba32);
-        let buf = rgb24.as_slice();
-        let tensor = buffer_to_tensor(buf);
+        let pixels = match self.channels {
+            TENSOR_CHANNELS_GRAY => rgba32_to_gray8(rgba32),
+            _ => rgba32_to_rgb24(rgba32),
+        };
+        let tensor = buffer_to_tensor(&pixels, self.channels)?;
         let prediction = predict(&self.bundle, &self.graph, &tensor)?;
 
         // 4. callback
+        if !prediction.passes(self.min_confidence, self.classes.as_deref()) {
+            return Ok(());
+        }
         if let Some(callback) = &self.callback {
             let result = TensorflowResult {
                 prediction,
@@ -70,6 +138,10 @@ impl Trigger for TensorflowTrigger {
 
         Ok(())
     }
+
+    fn on_start(&self) -> Result<()> {
+        self.crop.iter().try_for_each(Crop::validate)
+    }
 }
 
 impl TensorflowTrigger {
@@ -81,12 +153,46 @@ impl TensorflowTrigger {
     where
         P: AsRef<Path>,
     {
-        let (bundle, graph) = load_tensorflow_model(model_dir)?;
+        Self::with_channels(model_dir, TENSOR_CHANNELS, crop, callback)
+    }
+
+    /// Load a model whose input has a different number of color channels,
+    /// e.g. [`TENSOR_CHANNELS_GRAY`] for a grayscale classifier.
+    pub fn with_channels<P>(
+        model_dir: P,
+        channels: u64,
+        crop: Option<Crop>,
+        callback: Option<TensorflowTriggerCallback>,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::with_device(model_dir, channels, Device::Auto, crop, callback)
+    }
+
+    /// Load a model to run on a specific `Device`, e.g. `Device::Cpu` when
+    /// the GPU is busy with something else.
+    pub fn with_device<P>(
+        model_dir: P,
+        channels: u64,
+        device: Device,
+        crop: Option<Crop>,
+        callback: Option<TensorflowTriggerCallback>,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let (bundle, graph) = load_tensorflow_model_on_device(model_dir, channels, device)?;
         Ok(Self {
             bundle,
             graph,
+            transform: None,
             crop,
             callback,
+            min_confidence: None,
+            classes: None,
+            channels,
+            resize_mode: ResizeMode::default(),
         })
     }
 
@@ -98,16 +204,19 @@ impl TensorflowTrigger {
             debug_photon_image(&image)?;
         }
 
+        if let Some(transform) = &self.transform {
+            image = transform.apply(image);
+        }
+
         if let Some(crop) = &self.crop {
             image = crop.apply(image);
         }
 
         let size = TENSOR_SIZE as u32;
-        image = ensure_square(image);
-        image = ensure_size(image, size, size);
+        image = self.resize_mode.apply(image, size, size);
 
         if DEBUG {
-            println!("[tensorflow] center square crop and resize to 224x224 px");
+            println!("[tensorflow] {:?} to 224x224 px", self.resize_mode);
             debug_photon_image(&image)?;
         }
 
@@ -122,11 +231,36 @@ pub fn load_tensorflow_model<P>(model_dir: P) -> Result<(SavedModelBundle, Graph
 where
     P: AsRef<Path>,
 {
-    println!("[tensorflow] Loading saved model");
+    load_tensorflow_model_with_channels(model_dir, TENSOR_CHANNELS)
+}
+
+/// Load a model and warm it up with a dummy input of the given channel count.
+pub fn load_tensorflow_model_with_channels<P>(
+    model_dir: P,
+    channels: u64,
+) -> Result<(SavedModelBundle, Graph)>
+where
+    P: AsRef<Path>,
+{
+    load_tensorflow_model_on_device(model_dir, channels, Device::Auto)
+}
+
+/// Load a model into a session placed on the given `Device`, and warm it up
+/// with a dummy input of the given channel count.
+pub fn load_tensorflow_model_on_device<P>(
+    model_dir: P,
+    channels: u64,
+    device: Device,
+) -> Result<(SavedModelBundle, Graph)>
+where
+    P: AsRef<Path>,
+{
+    println!("[tensorflow] Loading saved model on {:?}", device);
     let now = Instant::now();
 
     let mut graph = Graph::new();
-    let bundle = SavedModelBundle::load(&SessionOptions::new(), ["serve"], &mut graph, model_dir)?;
+    let options = device.session_options()?;
+    let bundle = SavedModelBundle::load(&options, ["serve"], &mut graph, model_dir)?;
 
     println!(
         "[tensorflow] load_tensorflow_model {}ms.",
@@ -134,7 +268,7 @@ where
     );
 
     // Initialize the session by running a dummy input through the graph.
-    let dummy = dummy_tensor();
+    let dummy = dummy_tensor(channels)?;
     predict(&bundle, &graph, &dummy)?;
     println!("[tensorflow] finished test run");
 
@@ -142,13 +276,11 @@ where
 }
 
 /// Creates a tensor of zeros (all black image) used to initialize a session for fast prediction.
-pub fn dummy_tensor() -> Tensor<f32> {
-    let bytes = TENSOR_SIZE * TENSOR_SIZE * TENSOR_CHANNELS;
+pub fn dummy_tensor(channels: u64) -> Result<Tensor<f32>> {
+    let bytes = TENSOR_SIZE * TENSOR_SIZE * channels;
     let zero_vec: Vec<f32> = vec![0 as f32; bytes as usize];
 
-    Tensor::new(&[1, TENSOR_SIZE, TENSOR_SIZE, TENSOR_CHANNELS])
-        .with_values(&zero_vec)
-        .expect("creating dummy tensor")
+    Ok(Tensor::new(&[1, TENSOR_SIZE, TENSOR_SIZE, channels]).with_values(&zero_vec)?)
 }
 
 pub fn predict(
@@ -156,6 +288,33 @@ pub fn predict(
     graph: &Graph,
     tensor: &Tensor<f32>,
 ) -> Result<Prediction> {
+    let predictions = predict_batch_tensor(bundle, graph, tensor)?;
+    predictions
+        .into_iter()
+        .next()
+        .ok_or_else(|| NoneError.into())
+}
+
+/// Run several images (each a `[1, H, W, channels]` tensor, e.g. from
+/// `buffer_to_tensor`) through the model in a single session run, returning
+/// a prediction for each one, in order. Amortizes the per-run overhead when
+/// classifying many regions or frames at once.
+pub fn predict_batch(
+    bundle: &SavedModelBundle,
+    graph: &Graph,
+    tensors: &[Tensor<f32>],
+) -> Result<Vec<Prediction>> {
+    let batch = stack_tensors(tensors)?;
+    predict_batch_tensor(bundle, graph, &batch)
+}
+
+/// Run a pre-stacked `[N, H, W, channels]` tensor (e.g. from
+/// `buffer_to_batch_tensor`) through the model, returning `N` predictions.
+pub fn predict_batch_tensor(
+    bundle: &SavedModelBundle,
+    graph: &Graph,
+    batch: &Tensor<f32>,
+) -> Result<Vec<Prediction>> {
     let mut args = SessionRunArgs::new();
 
     // get in/out operations
@@ -166,8 +325,8 @@ pub fn predict(
     let output_info = signature.get_output("Confidences")?;
     let op_output = &graph.operation_by_name_required(&output_info.name().name)?;
 
-    // Load our input image
-    args.add_feed(op_x, 0, tensor);
+    // Load our input image(s)
+    args.add_feed(op_x, 0, batch);
     let token_output = args.request_fetch(op_output, 0);
 
     // Run prediction
@@ -176,37 +335,48 @@ pub fn predict(
 
     // Check the output.
     let output: Tensor<f32> = args.fetch(token_output)?;
+    let batch_size = batch.dims().first().copied().unwrap_or(1) as usize;
+    Ok(predictions_from_output(&output, batch_size))
+}
 
-    // Calculate argmax of the output)
-    let (max_idx, max_val) =
-        output
-            .iter()
-            .enumerate()
-            .fold((0, output[0]), |(idx_max, val_max), (idx, val)| {
-                if &val_max > val {
-                    (idx_max, val_max)
-                } else {
-                    (idx, *val)
-                }
-            });
-
-    Ok(Prediction {
-        class_index: max_idx,
-        confidence: max_val,
-    })
-}
-
-pub fn buffer_to_tensor(buf: &[u8]) -> Tensor<f32> {
-    let mut flattened: Vec<f32> = Vec::new();
-    let bytes = TENSOR_SIZE * TENSOR_SIZE * TENSOR_CHANNELS;
-
-    for pixel in buf.iter().take(bytes as usize) {
-        flattened.push(*pixel as f32 / 255.0);
+/// Concatenate `[1, H, W, channels]` tensors into a single
+/// `[N, H, W, channels]` batch.
+fn stack_tensors(tensors: &[Tensor<f32>]) -> Result<Tensor<f32>> {
+    let first = tensors.first().ok_or("Cannot predict an empty batch")?;
+    let mut dims = first.dims().to_vec();
+    if tensors.iter().any(|tensor| tensor.dims() != first.dims()) {
+        return Err("All tensors in a batch must have the same dimensions".into());
     }
+    dims[0] *= tensors.len() as u64;
+    let values: Vec<f32> = tensors
+        .iter()
+        .flat_map(|tensor| tensor.iter().copied())
+        .collect();
+    Ok(Tensor::new(&dims).with_values(&values)?)
+}
 
-    Tensor::new(&[1, TENSOR_SIZE, TENSOR_SIZE, TENSOR_CHANNELS])
-        .with_values(&flattened)
-        .expect("creating tensor from buffer")
+/// Convert interleaved 8-bit pixels (`channels` bytes per pixel) to a
+/// normalized `[1, H, W, channels]` tensor. Fails if the buffer isn't the
+/// size of a `TENSOR_SIZE` square image.
+pub fn buffer_to_tensor(buf: &[u8], channels: u64) -> Result<Tensor<f32>> {
+    let flattened = normalize_pixels(buf, TENSOR_SIZE, channels)?;
+    Ok(Tensor::new(&[1, TENSOR_SIZE, TENSOR_SIZE, channels]).with_values(&flattened)?)
+}
+
+/// Convert several images' interleaved 8-bit pixels into a single normalized
+/// `[N, H, W, channels]` tensor, for `predict_batch_tensor`.
+pub fn buffer_to_batch_tensor(bufs: &[&[u8]], channels: u64) -> Result<Tensor<f32>> {
+    let bytes = (TENSOR_SIZE * TENSOR_SIZE * channels) as usize;
+    let mut flattened: Vec<f32> = Vec::with_capacity(bytes * bufs.len());
+
+    for buf in bufs {
+        flattened.extend(normalize_pixels(buf, TENSOR_SIZE, channels)?);
+    }
+
+    Ok(
+        Tensor::new(&[bufs.len() as u64, TENSOR_SIZE, TENSOR_SIZE, channels])
+            .with_values(&flattened)?,
+    )
 }
 
 // /// 1. (if needed) Center crop if not square
@@ -223,3 +393,105 @@ pub fn buffer_to_tensor(buf: &[u8]) -> Tensor<f32> {
 
 //     new_image
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        buffer_to_batch_tensor, buffer_to_tensor, dummy_tensor, predictions_from_output,
+        stack_tensors, Device, Prediction, TENSOR_CHANNELS, TENSOR_CHANNELS_GRAY, TENSOR_SIZE,
+    };
+    use crate::{error::Result, photon::rgba32_to_gray8};
+
+    #[test]
+    fn prediction_threshold() {
+        let prediction = Prediction {
+            class_index: 1,
+            confidence: 0.4,
+        };
+        assert!(prediction.passes(None, None));
+        assert!(prediction.passes(Some(0.4), None));
+        assert!(!prediction.passes(Some(0.5), None));
+        assert!(prediction.passes(None, Some(&[0, 1])));
+        assert!(!prediction.passes(Some(0.2), Some(&[0, 2])));
+    }
+
+    #[test]
+    fn grayscale_tensor() -> Result<()> {
+        let dummy = dummy_tensor(TENSOR_CHANNELS_GRAY)?;
+        assert_eq!(dummy.dims(), &[1, TENSOR_SIZE, TENSOR_SIZE, 1]);
+
+        let pixels = (TENSOR_SIZE * TENSOR_SIZE) as usize;
+        let rgba32 = [255u8, 255, 255, 255].repeat(pixels);
+        let gray8 = rgba32_to_gray8(rgba32);
+        assert_eq!(gray8.len(), pixels);
+
+        let tensor = buffer_to_tensor(&gray8, TENSOR_CHANNELS_GRAY)?;
+        assert_eq!(tensor.dims(), dummy.dims());
+        assert!(tensor.iter().all(|value| *value == 1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn mis_sized_buffer() {
+        let short = vec![0u8; 100];
+        let error = buffer_to_tensor(&short, TENSOR_CHANNELS).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected a 224x224x3 image buffer of 150528 bytes, got 100"
+        );
+        assert!(buffer_to_batch_tensor(&[&short], TENSOR_CHANNELS).is_err());
+    }
+
+    #[test]
+    fn batch() -> Result<()> {
+        let batch = stack_tensors(&[
+            dummy_tensor(TENSOR_CHANNELS)?,
+            dummy_tensor(TENSOR_CHANNELS)?,
+        ])?;
+        assert_eq!(
+            batch.dims(),
+            &[2, TENSOR_SIZE, TENSOR_SIZE, TENSOR_CHANNELS]
+        );
+
+        let pixels = vec![255u8; (TENSOR_SIZE * TENSOR_SIZE * TENSOR_CHANNELS) as usize];
+        let batch = buffer_to_batch_tensor(&[&pixels, &pixels], TENSOR_CHANNELS)?;
+        assert_eq!(
+            batch.dims(),
+            &[2, TENSOR_SIZE, TENSOR_SIZE, TENSOR_CHANNELS]
+        );
+        assert!(batch.iter().all(|value| *value == 1.0));
+
+        // Confidences for two images, over three classes
+        let output = [0.1, 0.7, 0.2, 0.6, 0.3, 0.1];
+        let predictions = predictions_from_output(&output, 2);
+        assert_eq!(
+            predictions,
+            vec![
+                Prediction {
+                    class_index: 1,
+                    confidence: 0.7
+                },
+                Prediction {
+                    class_index: 0,
+                    confidence: 0.6
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn device_config() -> Result<()> {
+        assert!(Device::Auto.config_proto().is_empty());
+        assert_eq!(
+            Device::Cpu.config_proto(),
+            b"\x0a\x07\x0a\x03GPU\x10\x00".to_vec()
+        );
+        assert_eq!(
+            Device::Gpu(12).config_proto(),
+            b"\x32\x04\x2a\x0212".to_vec()
+        );
+        Device::Cpu.session_options()?;
+        Ok(())
+    }
+}
@@ -1,25 +1,39 @@
-use crate::debug::debug_photon_image;
-use crate::error::{NoneError, Result};
-use crate::photon::{ensure_minimum_size, rgb_to_photon, Crop, ThresholdFilter};
+use crate::debug::{debug_photon_image, save_photon_image};
+use crate::error::{Error, NoneError, Result};
+use crate::photon::{
+    ensure_minimum_size, gamma, posterize, AdaptiveThresholdFilter, ContrastFilter, Crop,
+    ThresholdFilter, Transform,
+};
+use crate::regions::RegionSet;
 use crate::trigger::{Frame, Trigger};
 use photon_rs::transform::padding_uniform;
 use photon_rs::{PhotonImage, Rgba};
+use std::collections::{hash_map::DefaultHasher, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::sync::Arc;
 use std::{
     fs::{self, File},
-    path::Path,
+    path::{Path, PathBuf},
     sync::Mutex,
+    thread,
+    time::Duration,
 };
 use tesseract::Tesseract;
 
 pub type TesseractTriggerCallback = Arc<dyn Fn(TesseractResult) + Send + Sync>;
+pub type TesseractTriggerRegionsCallback = Arc<dyn Fn(Vec<TesseractResult>) + Send + Sync>;
+pub type TesseractTriggerHocrCallback = Arc<dyn Fn(TesseractResult) + Send + Sync>;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct TesseractResult {
     pub text: String,
     pub timestamp: f64,
     pub frame_num: u64,
+
+    /// Index of the region in `TesseractTrigger::crops` that this text was
+    /// read from. Always `0` when using the single `crop` field.
+    pub crop_index: usize,
 }
 
 #[derive(Clone)]
@@ -27,29 +41,93 @@ pub struct TesseractTrigger {
     /// The initialized instance of Tesseract that will be used to run this trigger
     pub tesseract: TesseractRef,
 
+    /// Rotation or mirroring applied to the whole frame, before cropping.
+    pub transform: Option<Transform>,
+
     /// The region to crop to before running OCR.
     pub crop: Option<Crop>,
 
+    /// Multiple regions to run OCR on separately, sharing the same Tesseract
+    /// instance (e.g. separate fields of a scoreboard). When non-empty, this
+    /// is used instead of `crop`.
+    pub crops: Vec<Crop>,
+
+    /// Contrast and brightness adjustment to apply before thresholding.
+    pub contrast_filter: Option<ContrastFilter>,
+
+    /// Gamma correction to apply after `contrast_filter` and before
+    /// thresholding, e.g. `2.2` to bring out text in dark footage. See
+    /// `photon::gamma`.
+    pub gamma: Option<f32>,
+
+    /// Number of levels to reduce each color channel to after `gamma` and
+    /// before thresholding, which helps with anti-aliased text over busy
+    /// backgrounds. See `photon::posterize`.
+    pub posterize: Option<u8>,
+
     /// The threshold filter to apply before running OCR.
     pub threshold_filter: Option<ThresholdFilter>,
 
-    /// The callback to run after OCR is complete.
+    /// Adaptive (local) threshold to apply before running OCR, which handles
+    /// gradient backgrounds better than `threshold_filter`. If both are set,
+    /// this is applied second.
+    pub adaptive_threshold: Option<AdaptiveThresholdFilter>,
+
+    /// The callback to run after OCR is complete. When using `crops`, it's
+    /// called once for each region.
     pub callback: Option<TesseractTriggerCallback>,
 
+    /// The callback to run after OCR is complete on all of the `crops` of a
+    /// frame, receiving the results for every region at once.
+    pub regions_callback: Option<TesseractTriggerRegionsCallback>,
+
+    /// The callback to run with the layout of the recognized text as an hOCR
+    /// (HTML) document in `TesseractResult::text`, with a bounding box for each
+    /// page, paragraph, line (`ocr_line`) and word. Like `callback`, it's
+    /// called once for each of the `crops`. The text for `callback` comes from
+    /// the same recognition, and both are kept in the `ocr_cache`.
+    pub hocr_callback: Option<TesseractTriggerHocrCallback>,
+
+    /// Resolution (DPI) of the source image reported to Tesseract. Defaults to
+    /// 96; the wrong value degrades accuracy, especially on small text.
+    pub source_resolution: i32,
+
+    /// Scale `source_resolution` by how much a small crop was enlarged before
+    /// OCR, so that the reported DPI matches the upscaled text.
+    pub auto_source_resolution: bool,
+
     /// Pause execution after each step of image pre-processing.
     pub enable_debug_breakpoints: bool,
+
+    /// Write the image after each step of pre-processing (`crop`, `resized`,
+    /// `filtered`, `padded`) to this directory as numbered PNGs, e.g.
+    /// `frame000012-2-resized.png`, without pausing execution.
+    pub debug_output_dir: Option<PathBuf>,
+
+    /// Reuse the text recognized in identical preprocessed images, e.g. a
+    /// static menu that's on screen for hundreds of frames. Shared between
+    /// clones of the trigger.
+    pub ocr_cache: Option<Arc<OcrCache>>,
 }
 
 impl Trigger for TesseractTrigger {
     fn on_frame(&self, frame: &Frame) -> Result<()> {
+        if !self.crops.is_empty() {
+            return self.on_frame_regions(frame);
+        }
+
         // 1. convert raw image to photon
-        let image = rgb_to_photon(&frame.image);
+        let image = frame.to_photon();
 
         // 2. preprocess
-        let filtered = self.preprocess_image(image)?;
+        let image = self.transform_frame(image)?;
+        let debug_name = format!("frame{:06}", frame.frame_num);
+        let (filtered, scale) =
+            self.preprocess_region_named(image, self.crop.as_ref(), &debug_name)?;
 
         // 3. run ocr
-        let text = self.ocr(filtered)?;
+        let source_resolution = self.source_resolution_for(scale);
+        let text = self.ocr_region(filtered, source_resolution, frame, 0)?;
 
         // 4. callback
         if let Some(callback) = &self.callback {
@@ -57,47 +135,207 @@ impl Trigger for TesseractTrigger {
                 text,
                 timestamp: frame.timestamp,
                 frame_num: frame.frame_num,
+                crop_index: 0,
             };
             callback(result);
         }
 
         Ok(())
     }
+
+    /// Check the `crop` and `crops` before the first frame, so a typo in one
+    /// fails the run instead of reading the wrong region.
+    fn on_start(&self) -> Result<()> {
+        self.crop
+            .iter()
+            .chain(&self.crops)
+            .try_for_each(Crop::validate)
+    }
 }
 
 impl TesseractTrigger {
     pub fn new() -> Self {
         Self {
             tesseract: Arc::new(Mutex::new(None)),
+            transform: None,
             crop: None,
+            crops: vec![],
+            contrast_filter: None,
+            gamma: None,
+            posterize: None,
             threshold_filter: None,
+            adaptive_threshold: None,
             callback: None,
+            regions_callback: None,
+            hocr_callback: None,
+            source_resolution: 96,
+            auto_source_resolution: false,
             enable_debug_breakpoints: false,
+            debug_output_dir: None,
+            ocr_cache: None,
+        }
+    }
+
+    /// Start building a trigger with a fluent API, as an alternative to a
+    /// struct literal.
+    pub fn builder() -> TesseractTriggerBuilder {
+        TesseractTriggerBuilder::new()
+    }
+
+    /// Run OCR on each of the `crops` of a frame.
+    fn on_frame_regions(&self, frame: &Frame) -> Result<()> {
+        let image = self.transform_frame(frame.to_photon())?;
+
+        let mut results = Vec::with_capacity(self.crops.len());
+        for (crop_index, crop) in self.crops.iter().enumerate() {
+            let debug_name = format!("frame{:06}-region{}", frame.frame_num, crop_index);
+            let (filtered, scale) =
+                self.preprocess_region_named(image.clone(), Some(crop), &debug_name)?;
+            let source_resolution = self.source_resolution_for(scale);
+            let text = self.ocr_region(filtered, source_resolution, frame, crop_index)?;
+            let result = TesseractResult {
+                text,
+                timestamp: frame.timestamp,
+                frame_num: frame.frame_num,
+                crop_index,
+            };
+            if let Some(callback) = &self.callback {
+                callback(result.clone());
+            }
+            results.push(result);
         }
+
+        if let Some(regions_callback) = &self.regions_callback {
+            regions_callback(results);
+        }
+
+        Ok(())
+    }
+
+    /// Run OCR on one preprocessed region of a frame, passing its hOCR to the
+    /// `hocr_callback` (if any) on the way.
+    fn ocr_region(
+        &self,
+        filtered: PhotonImage,
+        source_resolution: i32,
+        frame: &Frame,
+        crop_index: usize,
+    ) -> Result<String> {
+        let hocr_callback = match &self.hocr_callback {
+            Some(hocr_callback) => hocr_callback,
+            None => return self.ocr_at_resolution(filtered, source_resolution),
+        };
+        let (text, hocr) = self.ocr_with_hocr_at_resolution(filtered, source_resolution)?;
+        hocr_callback(TesseractResult {
+            text: hocr,
+            timestamp: frame.timestamp,
+            frame_num: frame.frame_num,
+            crop_index,
+        });
+        Ok(text)
+    }
+
+    /// Run all preprocessing steps on a full frame, using the single `crop`.
+    pub fn preprocess_image(&self, image: PhotonImage) -> Result<PhotonImage> {
+        let image = self.transform_frame(image)?;
+        self.preprocess_region(image, self.crop.as_ref())
     }
 
-    pub fn preprocess_image(&self, mut image: PhotonImage) -> Result<PhotonImage> {
+    /// Preprocessing steps that apply to the whole frame, before cropping.
+    pub fn transform_frame(&self, mut image: PhotonImage) -> Result<PhotonImage> {
         if self.enable_debug_breakpoints {
             println!("[tesseract] received frame");
             debug_photon_image(&image)?;
         }
 
+        // Rotate/flip
+        if let Some(transform) = &self.transform {
+            image = transform.apply(image);
+            if self.enable_debug_breakpoints {
+                println!("[tesseract] transform: {:?}", transform);
+                debug_photon_image(&image)?;
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Preprocessing steps that crop a single region out of the (transformed)
+    /// frame and prepare it for OCR.
+    pub fn preprocess_region(
+        &self,
+        image: PhotonImage,
+        crop: Option<&Crop>,
+    ) -> Result<PhotonImage> {
+        self.preprocess_region_scaled(image, crop)
+            .map(|(image, _)| image)
+    }
+
+    /// Same as `preprocess_region`, also returning the factor that the region
+    /// was enlarged by to reach Tesseract's minimum size (`1.0` if it wasn't).
+    pub fn preprocess_region_scaled(
+        &self,
+        image: PhotonImage,
+        crop: Option<&Crop>,
+    ) -> Result<(PhotonImage, f64)> {
+        self.preprocess_region_named(image, crop, "image")
+    }
+
+    /// Same as `preprocess_region_scaled`, using `debug_name` as the filename
+    /// prefix for images written to `debug_output_dir`.
+    fn preprocess_region_named(
+        &self,
+        mut image: PhotonImage,
+        crop: Option<&Crop>,
+        debug_name: &str,
+    ) -> Result<(PhotonImage, f64)> {
         // Crop
-        if let Some(crop) = &self.crop {
+        if let Some(crop) = crop {
             image = crop.apply(image);
             if self.enable_debug_breakpoints {
-                println!("[tesseract] crop: {:?}", self.crop);
+                println!("[tesseract] crop: {:?}", crop);
                 debug_photon_image(&image)?;
             }
         }
+        self.save_debug_image(&image, debug_name, 1, "crop")?;
 
         // Minimum size
         const MIN_TESSERACT_IMAGE_SIZE: u32 = 32;
+        let cropped_width = image.get_width();
         image = ensure_minimum_size(&image, MIN_TESSERACT_IMAGE_SIZE);
+        let scale = image.get_width() as f64 / cropped_width as f64;
         if self.enable_debug_breakpoints {
             println!("[tesseract] resized");
             debug_photon_image(&image)?;
         }
+        self.save_debug_image(&image, debug_name, 2, "resized")?;
+
+        // Contrast/brightness
+        if let Some(filter) = &self.contrast_filter {
+            image = filter.apply(image);
+            if self.enable_debug_breakpoints {
+                println!("[tesseract] contrast: {:?}", filter);
+                debug_photon_image(&image)?;
+            }
+        }
+
+        // Gamma
+        if let Some(gamma_value) = self.gamma {
+            image = gamma(&image, gamma_value);
+            if self.enable_debug_breakpoints {
+                println!("[tesseract] gamma: {}", gamma_value);
+                debug_photon_image(&image)?;
+            }
+        }
+
+        // Posterize
+        if let Some(levels) = self.posterize {
+            image = posterize(&image, levels);
+            if self.enable_debug_breakpoints {
+                println!("[tesseract] posterize: {}", levels);
+                debug_photon_image(&image)?;
+            }
+        }
 
         // Threshold filter
         if let Some(filter) = &self.threshold_filter {
@@ -108,6 +346,17 @@ impl TesseractTrigger {
             }
         }
 
+        // Adaptive threshold
+        if let Some(filter) = &self.adaptive_threshold {
+            image = filter.apply(image);
+            if self.enable_debug_breakpoints {
+                println!("[tesseract] adaptive threshold: {:?}", filter);
+                debug_photon_image(&image)?;
+            }
+        }
+
+        self.save_debug_image(&image, debug_name, 3, "filtered")?;
+
         // Padding
         let padding_bg: Rgba = Rgba::new(255, 255, 255, 255);
         image = padding_uniform(&image, MIN_TESSERACT_IMAGE_SIZE, padding_bg);
@@ -115,11 +364,120 @@ impl TesseractTrigger {
             println!("[tesseract] padded (done)");
             debug_photon_image(&image)?;
         }
+        self.save_debug_image(&image, debug_name, 4, "padded")?;
 
-        Ok(image)
+        Ok((image, scale))
+    }
+
+    /// Write one step of pre-processing to `debug_output_dir`, if it's set.
+    fn save_debug_image(
+        &self,
+        image: &PhotonImage,
+        debug_name: &str,
+        step: u32,
+        stage: &str,
+    ) -> Result<()> {
+        match &self.debug_output_dir {
+            Some(dir) => {
+                let path = dir.join(format!("{}-{}-{}.png", debug_name, step, stage));
+                save_photon_image(image, &path)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The DPI to report to Tesseract for a region enlarged by `scale`.
+    pub fn source_resolution_for(&self, scale: f64) -> i32 {
+        if self.auto_source_resolution {
+            (self.source_resolution as f64 * scale).round() as i32
+        } else {
+            self.source_resolution
+        }
     }
 
+    /// Run OCR on a preprocessed image, at the configured `source_resolution`.
     pub fn ocr(&self, image: PhotonImage) -> Result<String> {
+        self.ocr_at_resolution(image, self.source_resolution)
+    }
+
+    /// Run OCR on a preprocessed image, reporting the given DPI to Tesseract.
+    pub fn ocr_at_resolution(&self, image: PhotonImage, source_resolution: i32) -> Result<String> {
+        match &self.ocr_cache {
+            Some(cache) => cache
+                .get_or_insert_with(OcrCache::key(&image, source_resolution), || {
+                    self.run_tesseract(image, source_resolution)
+                }),
+            None => self.run_tesseract(image, source_resolution),
+        }
+    }
+
+    /// Run OCR on a preprocessed image, returning the text and its layout as an
+    /// hOCR (HTML) document instead of plain text, e.g. for archival. Needs the
+    /// `tesseract` feature, and a Tesseract build with the hOCR renderer (the
+    /// default). Doesn't use the `ocr_cache`.
+    pub fn ocr_hocr(&self, image: PhotonImage) -> Result<String> {
+        self.ocr_hocr_at_resolution(image, self.source_resolution)
+    }
+
+    /// Same as `ocr_hocr`, reporting the given DPI to Tesseract.
+    pub fn ocr_hocr_at_resolution(
+        &self,
+        image: PhotonImage,
+        source_resolution: i32,
+    ) -> Result<String> {
+        self.with_frame(image, source_resolution, |tesseract| {
+            Ok(tesseract.get_hocr_text(0)?)
+        })
+    }
+
+    /// Run OCR on a preprocessed image once, returning both the plain text and
+    /// the hOCR document (see `ocr_hocr`). Both are kept in the `ocr_cache`.
+    pub fn ocr_with_hocr_at_resolution(
+        &self,
+        image: PhotonImage,
+        source_resolution: i32,
+    ) -> Result<(String, String)> {
+        let cache = match &self.ocr_cache {
+            Some(cache) => cache,
+            None => return self.run_tesseract_with_hocr(image, source_resolution),
+        };
+        let key = OcrCache::key(&image, source_resolution);
+        let hocr_key = OcrCache::hocr_key(key);
+        if let (Some(text), Some(hocr)) = (cache.get(key)?, cache.get(hocr_key)?) {
+            return Ok((text, hocr));
+        }
+        let (text, hocr) = self.run_tesseract_with_hocr(image, source_resolution)?;
+        cache.insert(key, text.clone())?;
+        cache.insert(hocr_key, hocr.clone())?;
+        Ok((text, hocr))
+    }
+
+    /// Run OCR without checking the `ocr_cache`.
+    fn run_tesseract(&self, image: PhotonImage, source_resolution: i32) -> Result<String> {
+        self.with_frame(image, source_resolution, |tesseract| {
+            Ok(tesseract.get_text()?)
+        })
+    }
+
+    /// Same as `run_tesseract`, also returning the hOCR document.
+    fn run_tesseract_with_hocr(
+        &self,
+        image: PhotonImage,
+        source_resolution: i32,
+    ) -> Result<(String, String)> {
+        self.with_frame(image, source_resolution, |tesseract| {
+            // The image is recognized once, for the hOCR, and the text reuses it
+            let hocr = tesseract.get_hocr_text(0)?;
+            Ok((tesseract.get_text()?, hocr))
+        })
+    }
+
+    /// Load an image into the Tesseract instance, then read the results with
+    /// `read`.
+    fn with_frame<T, F>(&self, image: PhotonImage, source_resolution: i32, read: F) -> Result<T>
+    where
+        F: FnOnce(&mut Tesseract) -> Result<T>,
+    {
         let rgba32 = image.get_raw_pixels();
         let buf = rgba32.as_slice();
         let channels = 4;
@@ -134,10 +492,10 @@ impl TesseractTrigger {
                 channels,
                 image.get_width() as i32 * channels,
             )?
-            .set_source_resolution(96);
-        let result = tesseract.get_text()?;
+            .set_source_resolution(source_resolution);
+        let result = read(&mut tesseract);
         let _tesseract = mutex_guard.insert(tesseract);
-        Ok(result)
+        result
     }
 }
 
@@ -147,32 +505,520 @@ impl Default for TesseractTrigger {
     }
 }
 
+/// Fluent builder for a `TesseractTrigger`, e.g.
+/// `TesseractTrigger::builder().crop(crop).on_text(|result| ...).build(tesseract)`.
+#[derive(Clone, Default)]
+pub struct TesseractTriggerBuilder {
+    trigger: TesseractTrigger,
+}
+
+impl TesseractTriggerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// See `TesseractTrigger::transform`
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.trigger.transform = Some(transform);
+        self
+    }
+
+    /// See `TesseractTrigger::crop`
+    pub fn crop(mut self, crop: Crop) -> Self {
+        self.trigger.crop = Some(crop);
+        self
+    }
+
+    /// Add one of multiple regions to OCR. See `TesseractTrigger::crops`
+    pub fn add_crop(mut self, crop: Crop) -> Self {
+        self.trigger.crops.push(crop);
+        self
+    }
+
+    /// Crop to a named region, failing if `regions` doesn't define it. See
+    /// `TesseractTrigger::crop`
+    pub fn region(self, regions: &RegionSet, name: &str) -> Result<Self> {
+        Ok(self.crop(regions.get(name)?))
+    }
+
+    /// Add a named region as one of multiple regions to OCR, failing if
+    /// `regions` doesn't define it. See `TesseractTrigger::crops`
+    pub fn add_region(self, regions: &RegionSet, name: &str) -> Result<Self> {
+        Ok(self.add_crop(regions.get(name)?))
+    }
+
+    /// See `TesseractTrigger::contrast_filter`
+    pub fn contrast(mut self, contrast_filter: ContrastFilter) -> Self {
+        self.trigger.contrast_filter = Some(contrast_filter);
+        self
+    }
+
+    /// See `TesseractTrigger::gamma`
+    pub fn gamma(mut self, gamma: f32) -> Self {
+        self.trigger.gamma = Some(gamma);
+        self
+    }
+
+    /// See `TesseractTrigger::posterize`
+    pub fn posterize(mut self, levels: u8) -> Self {
+        self.trigger.posterize = Some(levels);
+        self
+    }
+
+    /// See `TesseractTrigger::threshold_filter`
+    pub fn threshold(mut self, threshold_filter: ThresholdFilter) -> Self {
+        self.trigger.threshold_filter = Some(threshold_filter);
+        self
+    }
+
+    /// See `TesseractTrigger::adaptive_threshold`
+    pub fn adaptive_threshold(mut self, adaptive_threshold: AdaptiveThresholdFilter) -> Self {
+        self.trigger.adaptive_threshold = Some(adaptive_threshold);
+        self
+    }
+
+    /// Call the given function with the text recognized in each frame (or
+    /// each region). See `TesseractTrigger::callback`
+    pub fn on_text<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(TesseractResult) + Send + Sync + 'static,
+    {
+        self.trigger.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Call the given function with the hOCR layout of each frame (or each
+    /// region). See `TesseractTrigger::hocr_callback`
+    pub fn on_hocr<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(TesseractResult) + Send + Sync + 'static,
+    {
+        self.trigger.hocr_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Call the given function only once the recognized text has been the
+    /// same in `min_votes` of the last `window` frames. See `OcrStabilizer`
+    pub fn on_stable_text<F>(mut self, window: usize, min_votes: usize, callback: F) -> Self
+    where
+        F: Fn(TesseractResult) + Send + Sync + 'static,
+    {
+        self.trigger.callback = Some(OcrStabilizer::new(window, min_votes).callback(callback));
+        self
+    }
+
+    /// Call the given function with the text recognized in all regions of
+    /// each frame. See `TesseractTrigger::regions_callback`
+    pub fn on_regions<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(Vec<TesseractResult>) + Send + Sync + 'static,
+    {
+        self.trigger.regions_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// See `TesseractTrigger::source_resolution`
+    pub fn source_resolution(mut self, source_resolution: i32) -> Self {
+        self.trigger.source_resolution = source_resolution;
+        self
+    }
+
+    /// See `TesseractTrigger::auto_source_resolution`
+    pub fn auto_source_resolution(mut self, enable: bool) -> Self {
+        self.trigger.auto_source_resolution = enable;
+        self
+    }
+
+    /// See `TesseractTrigger::enable_debug_breakpoints`
+    pub fn debug_breakpoints(mut self, enable: bool) -> Self {
+        self.trigger.enable_debug_breakpoints = enable;
+        self
+    }
+
+    /// See `TesseractTrigger::debug_output_dir`
+    pub fn debug_output_dir<P>(mut self, dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.trigger.debug_output_dir = Some(dir.into());
+        self
+    }
+
+    /// Cache the text of up to `capacity` distinct preprocessed images. See
+    /// `TesseractTrigger::ocr_cache`
+    pub fn ocr_cache(mut self, capacity: usize) -> Self {
+        self.trigger.ocr_cache = Some(Arc::new(OcrCache::new(capacity)));
+        self
+    }
+
+    /// Finish the trigger, using the given (initialized) Tesseract instance
+    pub fn build(mut self, tesseract: TesseractRef) -> TesseractTrigger {
+        self.trigger.tesseract = tesseract;
+        self.trigger
+    }
+}
+
+/// A bounded, least-recently-used cache of OCR results, keyed by a hash of the
+/// preprocessed image. See `TesseractTrigger::ocr_cache`.
+#[derive(Debug)]
+pub struct OcrCache {
+    /// Maximum number of entries, after which the least recently used one is
+    /// evicted
+    pub capacity: usize,
+
+    entries: Mutex<OcrCacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct OcrCacheEntries {
+    text: HashMap<u64, String>,
+
+    /// Keys from least to most recently used
+    order: VecDeque<u64>,
+}
+
+impl OcrCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(OcrCacheEntries::default()),
+        }
+    }
+
+    /// Hash of everything that affects the OCR result of a preprocessed image
+    pub fn key(image: &PhotonImage, source_resolution: i32) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        image.get_width().hash(&mut hasher);
+        image.get_height().hash(&mut hasher);
+        source_resolution.hash(&mut hasher);
+        image.get_raw_pixels().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Key for the hOCR document of the image with the given `key`, which is
+    /// cached separately from its text (and counts towards the `capacity`)
+    pub fn hocr_key(key: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        "hocr".hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Return the cached text for `key`, or run `ocr` and cache its result.
+    /// The lock isn't held while `ocr` runs, so concurrent misses on the same
+    /// key may both run it.
+    pub fn get_or_insert_with<F>(&self, key: u64, ocr: F) -> Result<String>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        if let Some(text) = self.get(key)? {
+            return Ok(text);
+        }
+        let text = ocr()?;
+        self.insert(key, text.clone())?;
+        Ok(text)
+    }
+
+    /// The cached text for `key`, marking it as the most recently used
+    pub fn get(&self, key: u64) -> Result<Option<String>> {
+        let mut entries = self.entries.lock()?;
+        let text = entries.text.get(&key).cloned();
+        if text.is_some() {
+            entries.order.retain(|k| *k != key);
+            entries.order.push_back(key);
+        }
+        Ok(text)
+    }
+
+    /// Cache `text` for `key`, evicting the least recently used entries
+    /// beyond the `capacity`.
+    pub fn insert(&self, key: u64, text: String) -> Result<()> {
+        let mut entries = self.entries.lock()?;
+        if self.capacity > 0 && entries.text.insert(key, text).is_none() {
+            entries.order.push_back(key);
+            while entries.order.len() > self.capacity {
+                if let Some(evicted) = entries.order.pop_front() {
+                    entries.text.remove(&evicted);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of cached results
+    pub fn len(&self) -> usize {
+        self.entries.lock().map_or(0, |entries| entries.text.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Smooths out single-frame OCR noise by voting over the last `window`
+/// results, only emitting a value once it appears in at least `min_votes` of
+/// them. Results are grouped by `crop_index`, so regions vote separately.
+///
+/// The emitted result carries the timestamp and frame number at which the
+/// value first became stable, e.g.
+/// `.on_text(OcrStabilizer::new(5, 3).callback(|result| ...))`.
+#[derive(Clone, Debug)]
+pub struct OcrStabilizer {
+    /// Number of recent results to vote over
+    pub window: usize,
+
+    /// Number of results in the window that must agree before a value is
+    /// emitted
+    pub min_votes: usize,
+
+    history: HashMap<usize, VecDeque<TesseractResult>>,
+    stable: HashMap<usize, TesseractResult>,
+}
+
+impl OcrStabilizer {
+    pub fn new(window: usize, min_votes: usize) -> Self {
+        Self {
+            window,
+            min_votes,
+            history: HashMap::new(),
+            stable: HashMap::new(),
+        }
+    }
+
+    /// Add a result to the window, returning it if its text just became
+    /// stable (and differs from the previously confirmed value).
+    pub fn push(&mut self, result: TesseractResult) -> Option<TesseractResult> {
+        let history = self.history.entry(result.crop_index).or_default();
+        history.push_back(result.clone());
+        while history.len() > self.window {
+            history.pop_front();
+        }
+
+        let text = result.text.trim();
+        let votes = history.iter().filter(|r| r.text.trim() == text).count();
+        if votes < self.min_votes {
+            return None;
+        }
+        if let Some(stable) = self.stable.get(&result.crop_index) {
+            if stable.text.trim() == text {
+                return None;
+            }
+        }
+
+        self.stable.insert(result.crop_index, result.clone());
+        Some(result)
+    }
+
+    /// The last confirmed value for the given region, if any
+    pub fn stable(&self, crop_index: usize) -> Option<&TesseractResult> {
+        self.stable.get(&crop_index)
+    }
+
+    /// Wrap a callback so that it's only called with stable results, for use
+    /// as a `TesseractTrigger::callback`.
+    pub fn callback<F>(self, callback: F) -> TesseractTriggerCallback
+    where
+        F: Fn(TesseractResult) + Send + Sync + 'static,
+    {
+        let stabilizer = Mutex::new(self);
+        Arc::new(move |result| {
+            let stable = stabilizer.lock().unwrap().push(result);
+            if let Some(stable) = stable {
+                callback(stable);
+            }
+        })
+    }
+}
+
+/// Where to download traineddata files from when they're missing.
+/// See <https://tesseract-ocr.github.io/tessdoc/Data-Files.html>.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TessdataSource {
+    /// The `tessdata` repo at the `4.00` tag, which includes the legacy
+    /// engine's data
+    #[default]
+    Standard,
+
+    /// `tessdata_fast`: smaller, faster integer models
+    Fast,
+
+    /// `tessdata_best`: the most accurate (and slowest) models
+    Best,
+
+    /// Any URL, with `{filename}` in place of e.g. `eng.traineddata`. Use a
+    /// commit hash instead of a branch name to pin a version.
+    Custom(String),
+}
+
+impl TessdataSource {
+    /// The URL to download the given traineddata file from
+    pub fn url(&self, filename: &str) -> String {
+        let template = match self {
+            TessdataSource::Standard => {
+                "https://github.com/tesseract-ocr/tessdata/raw/4.00/{filename}"
+            }
+            TessdataSource::Fast => {
+                "https://github.com/tesseract-ocr/tessdata_fast/raw/main/{filename}"
+            }
+            TessdataSource::Best => {
+                "https://github.com/tesseract-ocr/tessdata_best/raw/main/{filename}"
+            }
+            TessdataSource::Custom(template) => template,
+        };
+        template.replace("{filename}", filename)
+    }
+}
+
 /// Attempts to download the latest traineddata file from Github
 pub fn download_tesseract_traineddata(download_path: &Path) -> Result<()> {
-    // Download latest from Github
+    download_tesseract_traineddata_from(download_path, &TessdataSource::default())
+}
+
+/// Download a traineddata file from the given source, failing if the server
+/// doesn't return it (e.g. a 404 for an unknown language). Retries with the
+/// default `DownloadOptions`.
+pub fn download_tesseract_traineddata_from(
+    download_path: &Path,
+    source: &TessdataSource,
+) -> Result<()> {
+    download_tesseract_traineddata_with(download_path, source, &DownloadOptions::default())
+}
+
+/// How to retry and verify a traineddata download, see
+/// `download_tesseract_traineddata_with`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DownloadOptions {
+    /// Total number of attempts, including the first one
+    pub attempts: u32,
+
+    /// Wait before the second attempt, doubling before each one after that
+    pub backoff: Duration,
+
+    /// Reject a download that isn't exactly this many bytes, e.g. for a
+    /// pinned `TessdataSource::Custom` version. The size is always checked
+    /// against the server's `Content-Length`, when it sends one.
+    pub expected_size: Option<u64>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_secs(1),
+            expected_size: None,
+        }
+    }
+}
+
+/// Same as `download_tesseract_traineddata_from`, retrying failed attempts
+/// with backoff. The file is written next to `download_path` and only moved
+/// into place once it's complete, so a failed attempt never leaves a partial
+/// file behind. Client errors (like a 404) fail straight away.
+pub fn download_tesseract_traineddata_with(
+    download_path: &Path,
+    source: &TessdataSource,
+    options: &DownloadOptions,
+) -> Result<()> {
     let filename = download_path
         .file_name()
         .ok_or(NoneError)?
         .to_str()
         .ok_or(NoneError)?;
-    let url = format!(
-        "https://github.com/tesseract-ocr/tessdata/raw/4.00/{}",
-        filename
-    );
-    let body = reqwest::blocking::get(url)?.bytes()?;
+    let url = source.url(filename);
+    let partial_path = download_path.with_extension("traineddata.part");
 
     // Automatically create needed directories
-    fs::create_dir_all(download_path.parent().unwrap())?;
+    fs::create_dir_all(download_path.parent().ok_or(NoneError)?)?;
 
-    // Write to file
-    let mut file = File::create(download_path)?;
-    Ok(file.write_all(body.as_ref())?)
+    let mut backoff = options.backoff;
+    let mut attempt = 1;
+    loop {
+        let error = match download_attempt(&url, &partial_path, options.expected_size) {
+            Ok(()) => return Ok(fs::rename(&partial_path, download_path)?),
+            Err(e) => e,
+        };
+        fs::remove_file(&partial_path).ok();
+        if attempt >= options.attempts || error.is::<DownloadRejected>() {
+            return Err(Error::from_display(format!(
+                "Failed to download traineddata from {} after {} attempt(s): {}",
+                url, attempt, error
+            )));
+        }
+        println!(
+            "[tesseract] download attempt {} failed ({}), retrying in {:?}",
+            attempt, error, backoff
+        );
+        thread::sleep(backoff);
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// A download that won't succeed by retrying, e.g. a 404
+#[derive(Debug)]
+struct DownloadRejected(String);
+
+impl std::fmt::Display for DownloadRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for DownloadRejected {}
+
+/// Download `url` to `path` once, checking that the whole file arrived.
+fn download_attempt(url: &str, path: &Path, expected_size: Option<u64>) -> Result<()> {
+    let mut response = reqwest::blocking::get(url)?;
+    let status = response.status();
+    if status.is_client_error() {
+        return Err(Error::from_std(DownloadRejected(format!(
+            "HTTP {}",
+            status
+        ))));
+    }
+    if !status.is_success() {
+        return Err(Error::from_display(format!("HTTP {}", status)));
+    }
+    let content_length = response.content_length();
+    let mut file = File::create(path)?;
+    let size = response.copy_to(&mut file)?;
+    file.flush()?;
+    if size == 0 {
+        return Err("empty response".into());
+    }
+    if let Some(content_length) = content_length.filter(|&length| length != size) {
+        return Err(Error::from_display(format!(
+            "got {} of {} bytes",
+            size, content_length
+        )));
+    }
+    if let Some(expected_size) = expected_size.filter(|&expected| expected != size) {
+        return Err(Error::from_std(DownloadRejected(format!(
+            "got {} bytes, expected {}",
+            size, expected_size
+        ))));
+    }
+    Ok(())
 }
 
 pub type TesseractRef = Arc<Mutex<Option<Tesseract>>>;
 
 /// Initialize a Tesseract instance, automatically downloading traineddata if needed
 pub fn init_tesseract<'a, X, Y>(datapath: X, language: Y) -> Result<TesseractRef>
+where
+    X: Into<Option<&'a str>>,
+    Y: Into<Option<&'a str>>,
+{
+    init_tesseract_from(datapath, language, &TessdataSource::default())
+}
+
+/// Same as `init_tesseract`, but downloads missing traineddata from the given
+/// source, e.g. `TessdataSource::Best`.
+pub fn init_tesseract_from<'a, X, Y>(
+    datapath: X,
+    language: Y,
+    source: &TessdataSource,
+) -> Result<TesseractRef>
 where
     X: Into<Option<&'a str>>,
     Y: Into<Option<&'a str>>,
@@ -197,7 +1043,7 @@ where
             traineddata_path.display()
         );
         println!("[tesseract] downloading traineddata...");
-        download_tesseract_traineddata(traineddata_path)?;
+        download_tesseract_traineddata_from(traineddata_path, source)?;
         println!("[tesseract] traineddata downloaded!");
     } else {
         println!("[tesseract] found traineddata")
@@ -209,11 +1055,22 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{init_tesseract, TesseractTrigger};
+    use super::{
+        download_tesseract_traineddata_with, init_tesseract, DownloadOptions, OcrCache,
+        OcrStabilizer, TessdataSource, TesseractResult, TesseractTrigger,
+    };
     use crate::async_trigger::{AsyncTrigger, TriggerThread};
     use crate::error::{Error, Result};
-    use crate::photon::Crop;
+    use crate::photon::{rgb_to_photon, Anchor, Crop, ThresholdFilter};
     use crate::pipeline::Hypetrigger;
+    use crate::trigger::Trigger;
+    use photon_rs::PhotonImage;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
 
     #[test]
     fn tesseract() -> Result<()> {
@@ -225,10 +1082,9 @@ mod tests {
                 top_percent: 25.0,
                 width_percent: 10.0,
                 height_percent: 10.0,
+                anchor: Anchor::TopLeft,
             }),
-            threshold_filter: None,
-            callback: None,
-            enable_debug_breakpoints: false,
+            ..Default::default()
         };
 
         Hypetrigger::new()
@@ -239,6 +1095,166 @@ mod tests {
             .map_err(Error::from_display)
     }
 
+    #[test]
+    fn builder() -> Result<()> {
+        let tesseract = init_tesseract(None, None)?;
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let results_clone = results.clone();
+        let trigger = TesseractTrigger::builder()
+            .crop(Crop {
+                left_percent: 25.0,
+                top_percent: 25.0,
+                width_percent: 10.0,
+                height_percent: 10.0,
+                anchor: Anchor::TopLeft,
+            })
+            .threshold(ThresholdFilter {
+                r: 255,
+                g: 255,
+                b: 255,
+                threshold: 42,
+                ..Default::default()
+            })
+            .on_text(move |result| results_clone.lock().unwrap().push(result))
+            .build(tesseract);
+        assert!(trigger.crop.is_some());
+        assert!(trigger.threshold_filter.is_some());
+
+        Hypetrigger::new()
+            .test_input()
+            .add_trigger(trigger)
+            .run()
+            .map_err(Error::from_display)?;
+
+        assert!(!results.lock()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn source_resolution() -> Result<()> {
+        let tesseract = init_tesseract(None, None)?;
+        let texts = Arc::new(Mutex::new(Vec::new()));
+        let texts_clone = texts.clone();
+        let trigger = TesseractTrigger::builder()
+            .crop(Crop {
+                left_percent: 25.0,
+                top_percent: 25.0,
+                width_percent: 10.0,
+                height_percent: 10.0,
+                anchor: Anchor::TopLeft,
+            })
+            .source_resolution(300)
+            .on_text(move |result| texts_clone.lock().unwrap().push(result.text))
+            .build(tesseract);
+        assert_eq!(trigger.source_resolution_for(2.0), 300);
+
+        Hypetrigger::new()
+            .test_input()
+            .add_trigger(trigger)
+            .run()
+            .map_err(Error::from_display)?;
+
+        assert!(!texts.lock()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn auto_source_resolution() {
+        let trigger = TesseractTrigger::builder()
+            .auto_source_resolution(true)
+            .build(TesseractTrigger::new().tesseract);
+        assert_eq!(trigger.source_resolution_for(1.0), 96);
+        assert_eq!(trigger.source_resolution_for(2.5), 240);
+    }
+
+    #[test]
+    fn still_image() -> Result<()> {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../examples/js-canvas/public/cod-warzone-kills-1.png"
+        );
+        let image = image::open(path).map_err(Error::from_std)?.to_rgb8();
+        let tesseract = init_tesseract(None, None)?;
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let results_clone = results.clone();
+        let trigger = TesseractTrigger::builder()
+            .on_text(move |result| results_clone.lock().unwrap().push(result))
+            .build(tesseract);
+
+        trigger.run_on_image(image)?;
+
+        let results = results.lock()?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].frame_num, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn hocr() -> Result<()> {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../examples/js-canvas/public/cod-warzone-kills-1.png"
+        );
+        let image = image::open(path).map_err(Error::from_std)?.to_rgb8();
+        let tesseract = init_tesseract(None, None)?;
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let results_clone = results.clone();
+        let trigger = TesseractTrigger::builder()
+            .on_hocr(move |result| results_clone.lock().unwrap().push(result))
+            .build(tesseract);
+
+        let hocr = trigger.ocr_hocr(rgb_to_photon(&image))?;
+        assert!(hocr.contains("ocr_line"));
+        let (text, hocr) = trigger.ocr_with_hocr_at_resolution(rgb_to_photon(&image), 96)?;
+        assert!(hocr.contains("ocr_line"));
+        assert_eq!(text, trigger.ocr(rgb_to_photon(&image))?);
+
+        trigger.run_on_image(image)?;
+        let results = results.lock()?;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].text.contains("ocr_line"));
+        Ok(())
+    }
+
+    #[test]
+    fn debug_output_dir() -> Result<()> {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../examples/js-canvas/public/cod-warzone-kills-1.png"
+        );
+        let image = image::open(path).map_err(Error::from_std)?.to_rgb8();
+        let debug_output_dir = std::env::temp_dir().join("hypetrigger-debug-output-dir");
+        let _ = fs::remove_dir_all(&debug_output_dir);
+        let tesseract = init_tesseract(None, None)?;
+        let trigger = TesseractTrigger::builder()
+            .crop(Crop {
+                left_percent: 25.0,
+                top_percent: 25.0,
+                width_percent: 10.0,
+                height_percent: 10.0,
+                anchor: Anchor::TopLeft,
+            })
+            .debug_output_dir(&debug_output_dir)
+            .build(tesseract);
+
+        trigger.run_on_image(image)?;
+
+        let mut filenames: Vec<String> = fs::read_dir(&debug_output_dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_>>()?;
+        filenames.sort();
+        assert_eq!(
+            filenames,
+            vec![
+                "frame000000-1-crop.png",
+                "frame000000-2-resized.png",
+                "frame000000-3-filtered.png",
+                "frame000000-4-padded.png",
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn async_trigger() -> Result<()> {
         let runner_thread = TriggerThread::spawn();
@@ -250,10 +1266,9 @@ mod tests {
                 top_percent: 25.0,
                 width_percent: 10.0,
                 height_percent: 10.0,
+                anchor: Anchor::TopLeft,
             }),
-            threshold_filter: None,
-            callback: None,
-            enable_debug_breakpoints: false,
+            ..Default::default()
         };
         let trigger = AsyncTrigger::from_trigger(base_trigger, runner_thread.tx.clone());
 
@@ -267,4 +1282,176 @@ mod tests {
         runner_thread.stop()?;
         Ok(())
     }
+
+    #[test]
+    fn add_async_trigger() -> Result<()> {
+        let tesseract = init_tesseract(None, None)?;
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        let trigger = TesseractTrigger::builder()
+            .crop(Crop {
+                left_percent: 25.0,
+                top_percent: 25.0,
+                width_percent: 10.0,
+                height_percent: 10.0,
+                anchor: Anchor::TopLeft,
+            })
+            .on_text(move |result| frames_clone.lock().unwrap().push(result.frame_num))
+            .build(tesseract);
+
+        Hypetrigger::new()
+            .test_input()
+            .add_async_trigger(trigger)
+            .run()
+            .map_err(Error::from_display)?;
+
+        // The trigger thread is stopped only after it finishes every frame
+        assert_eq!(frames.lock()?.len(), 20);
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_crops() -> Result<()> {
+        let tesseract = init_tesseract(None, None)?;
+        let region_counts = Arc::new(Mutex::new(Vec::new()));
+        let region_counts_clone = region_counts.clone();
+        let crop = |left_percent| Crop {
+            left_percent,
+            top_percent: 25.0,
+            width_percent: 10.0,
+            height_percent: 10.0,
+            anchor: Anchor::TopLeft,
+        };
+        let trigger = TesseractTrigger {
+            tesseract,
+            crops: vec![crop(25.0), crop(50.0)],
+            regions_callback: Some(Arc::new(move |results| {
+                let crop_indices: Vec<usize> = results.iter().map(|r| r.crop_index).collect();
+                region_counts_clone.lock().unwrap().push(crop_indices);
+            })),
+            ..Default::default()
+        };
+
+        Hypetrigger::new()
+            .test_input()
+            .add_trigger(trigger)
+            .run()
+            .map_err(Error::from_display)?;
+
+        let region_counts = region_counts.lock()?;
+        assert!(!region_counts.is_empty());
+        assert!(region_counts.iter().all(|indices| indices == &vec![0, 1]));
+        Ok(())
+    }
+
+    #[test]
+    fn ocr_stabilizer() {
+        let emitted = Arc::new(Mutex::new(Vec::new()));
+        let emitted_clone = emitted.clone();
+        let callback = OcrStabilizer::new(4, 3)
+            .callback(move |result| emitted_clone.lock().unwrap().push(result));
+
+        for (frame_num, text) in ["12", "12", "13", "12"].iter().enumerate() {
+            callback(TesseractResult {
+                text: text.to_string(),
+                timestamp: frame_num as f64,
+                frame_num: frame_num as u64,
+                crop_index: 0,
+            });
+        }
+
+        let emitted = emitted.lock().unwrap();
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].text, "12");
+        assert_eq!(emitted[0].timestamp, 3.0);
+    }
+
+    #[test]
+    fn download_retry() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let server = thread::spawn(move || {
+            let responses = [
+                // Cut off partway through the body
+                "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\nhello",
+                "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\nhelloworld",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear(); // skip the request up to the blank line
+                }
+                drop(reader);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!("hypetrigger-download-{}", port));
+        let path = dir.join("eng.traineddata");
+        let source = TessdataSource::Custom(format!("http://127.0.0.1:{}/{{filename}}", port));
+        let options = DownloadOptions {
+            attempts: 2,
+            backoff: Duration::from_millis(10),
+            expected_size: None,
+        };
+        download_tesseract_traineddata_with(&path, &source, &options)?;
+        server.join().unwrap();
+
+        assert_eq!(fs::read_to_string(&path)?, "helloworld");
+        assert!(!path.with_extension("traineddata.part").exists());
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn tessdata_source() {
+        assert_eq!(
+            TessdataSource::Best.url("eng.traineddata"),
+            "https://github.com/tesseract-ocr/tessdata_best/raw/main/eng.traineddata"
+        );
+        assert_eq!(
+            TessdataSource::default().url("eng.traineddata"),
+            "https://github.com/tesseract-ocr/tessdata/raw/4.00/eng.traineddata"
+        );
+        let pinned = TessdataSource::Custom(
+            "https://github.com/tesseract-ocr/tessdata_fast/raw/87416418/{filename}".to_string(),
+        );
+        assert_eq!(
+            pinned.url("deu.traineddata"),
+            "https://github.com/tesseract-ocr/tessdata_fast/raw/87416418/deu.traineddata"
+        );
+    }
+
+    #[test]
+    fn ocr_cache() -> Result<()> {
+        let cache = OcrCache::new(2);
+        let calls = std::cell::Cell::new(0);
+        let ocr = || {
+            calls.set(calls.get() + 1);
+            Ok("1,000".to_string())
+        };
+
+        // Two identical preprocessed images, e.g. from consecutive frames
+        let first = PhotonImage::new(vec![255; 32 * 32 * 4], 32, 32);
+        let second = PhotonImage::new(vec![255; 32 * 32 * 4], 32, 32);
+        assert_eq!(
+            cache.get_or_insert_with(OcrCache::key(&first, 96), ocr)?,
+            "1,000"
+        );
+        assert_eq!(
+            cache.get_or_insert_with(OcrCache::key(&second, 96), ocr)?,
+            "1,000"
+        );
+        assert_eq!(calls.get(), 1);
+
+        // Bounded to the two most recently used images
+        let black = PhotonImage::new(vec![0; 32 * 32 * 4], 32, 32);
+        cache.get_or_insert_with(OcrCache::key(&black, 96), ocr)?;
+        cache.get_or_insert_with(OcrCache::key(&first, 300), ocr)?;
+        assert_eq!(calls.get(), 3);
+        assert_eq!(cache.len(), 2);
+        Ok(())
+    }
 }
@@ -0,0 +1,164 @@
+//! Helpers to unit test triggers on synthetic frames, without spawning ffmpeg.
+//! Available in the crate's own tests, or elsewhere with the `testing`
+//! feature.
+
+use crate::error::{Result, StopPipeline};
+use crate::simple_trigger::SimpleTrigger;
+use crate::trigger::{Frame, Trigger};
+use image::{Rgb, RgbImage};
+use std::sync::{Arc, Mutex};
+
+/// A frame of a single color.
+pub fn solid_frame(color: [u8; 3], width: u32, height: u32) -> Frame {
+    Frame::new(RgbImage::from_pixel(width, height, Rgb(color)), 0, 0.0)
+}
+
+/// A frame split into a checkerboard of `cell` x `cell` pixel squares,
+/// alternating between colors `a` (in the top left corner) and `b`.
+pub fn checkerboard_frame(a: [u8; 3], b: [u8; 3], cell: u32, width: u32, height: u32) -> Frame {
+    let cell = cell.max(1);
+    let image = RgbImage::from_fn(width, height, |x, y| match (x / cell + y / cell) % 2 {
+        0 => Rgb(a),
+        _ => Rgb(b),
+    });
+    Frame::new(image, 0, 0.0)
+}
+
+/// A frame that fades horizontally from `from` (left edge) to `to` (right
+/// edge).
+pub fn gradient_frame(from: [u8; 3], to: [u8; 3], width: u32, height: u32) -> Frame {
+    let span = width.saturating_sub(1).max(1) as f64;
+    let image = RgbImage::from_fn(width, height, |x, _| {
+        let t = x as f64 / span;
+        let mix = |c: usize| (from[c] as f64 + (to[c] as f64 - from[c] as f64) * t).round() as u8;
+        Rgb([mix(0), mix(1), mix(2)])
+    });
+    Frame::new(image, 0, 0.0)
+}
+
+/// Number the frames in order and timestamp them as if sampled at `fps`, the
+/// way the pipeline would.
+pub fn sequence<I>(frames: I, fps: f64) -> Vec<Frame>
+where
+    I: IntoIterator<Item = Frame>,
+{
+    frames
+        .into_iter()
+        .enumerate()
+        .map(|(frame_num, mut frame)| {
+            frame.frame_num = frame_num as u64;
+            frame.timestamp = frame_num as f64 / fps;
+            frame
+        })
+        .collect()
+}
+
+/// Run a trigger over a scripted sequence of frames, the way a run of the
+/// pipeline would: `on_start`, `on_frame` for each frame until one returns
+/// `StopPipeline`, then `flush` and `on_end`. Returns the result of each
+/// `on_frame` call, or the first error from the other calls.
+///
+/// Unlike the pipeline, every frame is passed on, regardless of the trigger's
+/// `desired_fps`.
+pub fn run_trigger<T>(trigger: &T, frames: &[Frame]) -> Result<Vec<Result<()>>>
+where
+    T: Trigger + ?Sized,
+{
+    trigger.on_start()?;
+    let mut results = Vec::new();
+    for frame in frames {
+        let result = trigger.on_frame(frame);
+        let stopped = matches!(&result, Err(e) if e.is::<StopPipeline>());
+        results.push(result);
+        if stopped {
+            break;
+        }
+    }
+    let flushed = trigger.flush();
+    let ended = trigger.on_end();
+    flushed.and(ended)?;
+    Ok(results)
+}
+
+/// A trigger that records the `frame_num` of every frame it sees, along with
+/// the list it records them to.
+pub fn recorder() -> (SimpleTrigger, Arc<Mutex<Vec<u64>>>) {
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let frames_clone = frames.clone();
+    let trigger = SimpleTrigger::new(move |frame| {
+        if let Ok(mut frames) = frames_clone.lock() {
+            frames.push(frame.frame_num);
+        }
+    });
+    (trigger, frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checkerboard_frame, gradient_frame, recorder, run_trigger, sequence, solid_frame};
+    use crate::{
+        error::{Result, StopPipeline},
+        simple_trigger::SimpleTrigger,
+        trigger::{Frame, Trigger},
+    };
+    use image::Rgb;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn patterns() {
+        let frame = solid_frame([255, 0, 0], 4, 2);
+        assert_eq!(frame.image.dimensions(), (4, 2));
+        assert!(frame.image.pixels().all(|p| *p == Rgb([255, 0, 0])));
+
+        let frame = checkerboard_frame([0, 0, 0], [255, 255, 255], 2, 4, 4);
+        assert_eq!(*frame.image.get_pixel(0, 0), Rgb([0, 0, 0]));
+        assert_eq!(*frame.image.get_pixel(2, 0), Rgb([255, 255, 255]));
+        assert_eq!(*frame.image.get_pixel(2, 2), Rgb([0, 0, 0]));
+
+        let frame = gradient_frame([0, 0, 0], [200, 100, 0], 3, 1);
+        assert_eq!(*frame.image.get_pixel(1, 0), Rgb([100, 50, 0]));
+        assert_eq!(*frame.image.get_pixel(2, 0), Rgb([200, 100, 0]));
+    }
+
+    #[test]
+    fn simple_trigger() -> Result<()> {
+        let frames = sequence((0..3).map(|_| solid_frame([0, 0, 0], 2, 2)), 2.0);
+        assert_eq!(frames[2].timestamp, 1.0);
+
+        let (trigger, seen) = recorder();
+        let results = run_trigger(&trigger, &frames)?;
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(*seen.lock()?, vec![0, 1, 2]);
+
+        let dimensions = Arc::new(Mutex::new(Vec::new()));
+        let dimensions_clone = dimensions.clone();
+        let trigger = SimpleTrigger::new(move |frame| {
+            dimensions_clone
+                .lock()
+                .unwrap()
+                .push(frame.image.dimensions());
+        });
+        run_trigger(&trigger, &frames)?;
+        assert_eq!(*dimensions.lock()?, vec![(2, 2); 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn stops_early() -> Result<()> {
+        struct StopOnFrame1;
+        impl Trigger for StopOnFrame1 {
+            fn on_frame(&self, frame: &Frame) -> Result<()> {
+                match frame.frame_num {
+                    1 => Err(StopPipeline.into()),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let frames = sequence((0..5).map(|_| solid_frame([0, 0, 0], 1, 1)), 1.0);
+        let results = run_trigger(&StopOnFrame1, &frames)?;
+        assert_eq!(results.len(), 2);
+        assert!(results[1].as_ref().unwrap_err().is::<StopPipeline>());
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use image::{GrayImage, Luma};
 use photon_rs::{PhotonImage, Rgb};
 use wasm_bindgen::prelude::wasm_bindgen;
 
@@ -5,9 +6,15 @@ pub type Lab = (f64, f64, f64);
 
 /// https://en.wikipedia.org/wiki/CIELAB_color_space
 pub fn rgb2lab(rgb: &Rgb) -> Lab {
-    let mut r = rgb.get_red() as f64 / 255_f64;
-    let mut g = rgb.get_green() as f64 / 255_f64;
-    let mut b = rgb.get_blue() as f64 / 255_f64;
+    rgb2lab_tuple((rgb.get_red(), rgb.get_green(), rgb.get_blue()))
+}
+
+/// Same as `rgb2lab`, for an `(r, g, b)` tuple (e.g. from the pixels of a
+/// `Frame`) instead of a `photon_rs::Rgb`.
+pub fn rgb2lab_tuple(rgb: (u8, u8, u8)) -> Lab {
+    let mut r = rgb.0 as f64 / 255_f64;
+    let mut g = rgb.1 as f64 / 255_f64;
+    let mut b = rgb.2 as f64 / 255_f64;
     r = if r > 0.04045 {
         f64::powf((r + 0.055) / 1.055, 2.4)
     } else {
@@ -47,8 +54,16 @@ pub fn rgb2lab(rgb: &Rgb) -> Lab {
 #[wasm_bindgen]
 /// <https://en.wikipedia.org/wiki/Color_difference>
 pub fn delta_e(color_a: &Rgb, color_b: &Rgb) -> f64 {
-    let lab_a = rgb2lab(color_a);
-    let lab_b = rgb2lab(color_b);
+    delta_e_tuple(
+        (color_a.get_red(), color_a.get_green(), color_a.get_blue()),
+        (color_b.get_red(), color_b.get_green(), color_b.get_blue()),
+    )
+}
+
+/// Same as `delta_e`, for `(r, g, b)` tuples instead of `photon_rs::Rgb`.
+pub fn delta_e_tuple(color_a: (u8, u8, u8), color_b: (u8, u8, u8)) -> f64 {
+    let lab_a = rgb2lab_tuple(color_a);
+    let lab_b = rgb2lab_tuple(color_b);
     let delta_l = lab_a.0 - lab_b.0;
     let delta_a = lab_a.1 - lab_b.1;
     let delta_b = lab_a.2 - lab_b.2;
@@ -70,6 +85,112 @@ pub fn delta_e(color_a: &Rgb, color_b: &Rgb) -> f64 {
     }
 }
 
+/// CIEDE2000 color difference, which is more perceptually accurate than
+/// `delta_e` for saturated colors, at the cost of more computation.
+///
+/// <https://en.wikipedia.org/wiki/Color_difference#CIEDE2000>
+#[wasm_bindgen]
+pub fn delta_e_2000(color_a: &Rgb, color_b: &Rgb) -> f64 {
+    delta_e_2000_tuple(
+        (color_a.get_red(), color_a.get_green(), color_a.get_blue()),
+        (color_b.get_red(), color_b.get_green(), color_b.get_blue()),
+    )
+}
+
+/// Same as `delta_e_2000`, for `(r, g, b)` tuples instead of `photon_rs::Rgb`.
+pub fn delta_e_2000_tuple(color_a: (u8, u8, u8), color_b: (u8, u8, u8)) -> f64 {
+    delta_e_2000_lab(rgb2lab_tuple(color_a), rgb2lab_tuple(color_b))
+}
+
+/// CIEDE2000 color difference between two colors in the CIELAB color space,
+/// following Sharma, Wu & Dalal (2005).
+pub fn delta_e_2000_lab(lab_a: Lab, lab_b: Lab) -> f64 {
+    let (l1, a1, b1) = lab_a;
+    let (l2, a2, b2) = lab_b;
+    let pow25_7 = 25_f64.powi(7);
+
+    // Adjust a* to compensate for the non-uniformity of neutral colors
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+    let a1 = (1.0 + g) * a1;
+    let a2 = (1.0 + g) * a2;
+    let c1 = a1.hypot(b1);
+    let c2 = a2.hypot(b2);
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let h1 = hue(b1, a1);
+    let h2 = hue(b2, a2);
+
+    // Differences in lightness, chroma and hue
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h.to_radians() / 2.0).sin();
+
+    // Weighting functions
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let l = delta_l / s_l;
+    let c = delta_c / s_c;
+    let h = delta_h / s_h;
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+/// Formula used to measure the distance between two colors when thresholding
+#[wasm_bindgen]
+#[derive(Debug, Default, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum DistanceMetric {
+    /// `delta_e`: fast, but less accurate for saturated colors
+    #[default]
+    DeltaE,
+
+    /// `delta_e_2000`: CIEDE2000, more accurate but slower
+    DeltaE2000,
+}
+
+impl DistanceMetric {
+    /// Distance between two `(r, g, b)` colors using this metric
+    pub fn distance(&self, color_a: (u8, u8, u8), color_b: (u8, u8, u8)) -> f64 {
+        match self {
+            DistanceMetric::DeltaE => delta_e_tuple(color_a, color_b),
+            DistanceMetric::DeltaE2000 => delta_e_2000_tuple(color_a, color_b),
+        }
+    }
+}
+
 /// Custom thresholding function which uses the color distance from a given color
 #[wasm_bindgen]
 pub fn threshold_color_distance(image: PhotonImage, color: &Rgb, threshold: f64) -> PhotonImage {
@@ -83,24 +204,370 @@ pub fn threshold_color_distance(image: PhotonImage, color: &Rgb, threshold: f64)
 /// Custom thresholding function which uses the color distance from a given color
 #[wasm_bindgen]
 pub fn threshold_color_distance_rgba(vector: Vec<u8>, color: &Rgb, threshold: f64) -> Vec<u8> {
-    let mut new_vector = Vec::new();
+    threshold_color_distance_rgba_with_metric(vector, color, threshold, DistanceMetric::DeltaE)
+}
 
-    for i in (0..vector.len()).step_by(4) {
-        let r = vector[i];
-        let g = vector[i + 1];
-        let b = vector[i + 2];
+/// Same as `threshold_color_distance_rgba`, using the given color distance
+/// formula
+#[wasm_bindgen]
+pub fn threshold_color_distance_rgba_with_metric(
+    vector: Vec<u8>,
+    color: &Rgb,
+    threshold: f64,
+    metric: DistanceMetric,
+) -> Vec<u8> {
+    let color = (color.get_red(), color.get_green(), color.get_blue());
 
-        let px_color: Rgb = Rgb::new(r, g, b);
-        let v = if delta_e(&px_color, color) >= threshold {
-            255u8
-        } else {
-            0u8
-        };
-        new_vector.push(v);
-        new_vector.push(v);
-        new_vector.push(v);
-        new_vector.push(255u8); // alpha
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    return threshold_pixels_parallel(&vector, color, threshold, metric);
+
+    #[cfg(not(all(feature = "rayon", not(target_arch = "wasm32"))))]
+    threshold_pixels_serial(&vector, color, threshold, metric)
+}
+
+/// Threshold a single RGBA pixel: white if it's at least `threshold` away from
+/// `color`, otherwise black. Alpha is unchanged.
+fn threshold_pixel(
+    pixel: &[u8],
+    color: (u8, u8, u8),
+    threshold: f64,
+    metric: DistanceMetric,
+) -> [u8; 4] {
+    let v = if metric.distance((pixel[0], pixel[1], pixel[2]), color) >= threshold {
+        255u8
+    } else {
+        0u8
+    };
+    [v, v, v, pixel[3]]
+}
+
+#[cfg_attr(all(feature = "rayon", not(target_arch = "wasm32")), allow(dead_code))]
+fn threshold_pixels_serial(
+    vector: &[u8],
+    color: (u8, u8, u8),
+    threshold: f64,
+    metric: DistanceMetric,
+) -> Vec<u8> {
+    vector
+        .chunks_exact(4)
+        .flat_map(|pixel| threshold_pixel(pixel, color, threshold, metric))
+        .collect()
+}
+
+/// Same as `threshold_pixels_serial`, processing blocks of pixels in parallel.
+/// The output is identical.
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+fn threshold_pixels_parallel(
+    vector: &[u8],
+    color: (u8, u8, u8),
+    threshold: f64,
+    metric: DistanceMetric,
+) -> Vec<u8> {
+    use rayon::prelude::*;
+
+    /// Pixels per parallel task, to amortize the scheduling overhead
+    const CHUNK_PIXELS: usize = 4096;
+
+    vector
+        .par_chunks(CHUNK_PIXELS * 4)
+        .flat_map_iter(|chunk| threshold_pixels_serial(chunk, color, threshold, metric))
+        .collect()
+}
+
+/// A binary image, one `bool` per pixel in row-major order, e.g. from
+/// `threshold_to_mask`. More compact than a thresholded RGBA image, where each
+/// pixel repeats the same 0 or 255 in all three channels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mask {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<bool>,
+}
+
+impl Mask {
+    /// Whether the pixel at `(x, y)` is set. Out of bounds pixels aren't.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Number of set pixels
+    pub fn count(&self) -> usize {
+        self.pixels.iter().filter(|&&set| set).count()
+    }
+
+    /// The `(x, y)` coordinates of each set pixel, row by row
+    pub fn iter_set(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let width = self.width.max(1);
+        self.pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, &set)| set)
+            .map(move |(i, _)| (i as u32 % width, i as u32 / width))
+    }
+
+    /// Convert to a single-channel image, with set pixels white (255) and the
+    /// rest black (0), the same as the thresholded RGBA image.
+    pub fn to_gray_image(&self) -> GrayImage {
+        GrayImage::from_fn(self.width, self.height, |x, y| match self.get(x, y) {
+            true => Luma([255]),
+            false => Luma([0]),
+        })
+    }
+}
+
+/// Same as `threshold_color_distance`, returning a binary mask instead of an
+/// RGBA image. Pixels at least `threshold` away from `color` (the ones that
+/// would be white) are set, and pixels close to it aren't.
+pub fn threshold_to_mask(image: &PhotonImage, color: &Rgb, threshold: f64) -> Mask {
+    threshold_to_mask_with_metric(image, color, threshold, DistanceMetric::DeltaE)
+}
+
+/// Same as `threshold_to_mask`, using the given color distance formula
+pub fn threshold_to_mask_with_metric(
+    image: &PhotonImage,
+    color: &Rgb,
+    threshold: f64,
+    metric: DistanceMetric,
+) -> Mask {
+    let color = (color.get_red(), color.get_green(), color.get_blue());
+    let pixels = image
+        .get_raw_pixels()
+        .chunks_exact(4)
+        .map(|pixel| metric.distance((pixel[0], pixel[1], pixel[2]), color) >= threshold)
+        .collect();
+    Mask {
+        width: image.get_width(),
+        height: image.get_height(),
+        pixels,
+    }
+}
+
+/// Adaptive (local) thresholding, for images with uneven lighting or gradient
+/// backgrounds where a single global threshold doesn't work.
+///
+/// Each pixel is compared to the mean brightness of the `block_size` x
+/// `block_size` window around it: pixels brighter than `mean - c` become white,
+/// and the rest (e.g. dark text) become black.
+#[wasm_bindgen]
+pub fn adaptive_threshold(image: &PhotonImage, block_size: u32, c: i32) -> PhotonImage {
+    let width = image.get_width() as usize;
+    let height = image.get_height() as usize;
+    let raw_pixels = image.get_raw_pixels();
+    let brightness: Vec<u64> = raw_pixels
+        .chunks_exact(4)
+        .map(|pixel| (pixel[0] as u64 + pixel[1] as u64 + pixel[2] as u64) / 3)
+        .collect();
+
+    // Summed-area table, with an extra row and column of zeroes, so the sum of
+    // any window can be computed in constant time
+    let mut integral = vec![0u64; (width + 1) * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = 0;
+        for x in 0..width {
+            row_sum += brightness[y * width + x];
+            integral[(y + 1) * (width + 1) + (x + 1)] =
+                integral[y * (width + 1) + (x + 1)] + row_sum;
+        }
+    }
+
+    let radius = (block_size / 2) as usize;
+    let mut new_vector = Vec::with_capacity(raw_pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let x1 = x.saturating_sub(radius);
+            let y1 = y.saturating_sub(radius);
+            let x2 = (x + radius + 1).min(width);
+            let y2 = (y + radius + 1).min(height);
+            let sum = integral[y2 * (width + 1) + x2] + integral[y1 * (width + 1) + x1]
+                - integral[y1 * (width + 1) + x2]
+                - integral[y2 * (width + 1) + x1];
+            let mean = sum as f64 / ((x2 - x1) * (y2 - y1)) as f64;
+
+            let v = if brightness[y * width + x] as f64 > mean - c as f64 {
+                255u8
+            } else {
+                0u8
+            };
+            new_vector.extend_from_slice(&[v, v, v, 255u8]);
+        }
     }
 
-    new_vector
+    PhotonImage::new(new_vector, image.get_width(), image.get_height())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        adaptive_threshold, delta_e, delta_e_2000_lab, delta_e_tuple, rgb2lab, rgb2lab_tuple,
+        threshold_color_distance, threshold_to_mask,
+    };
+    use photon_rs::{PhotonImage, Rgb};
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    use {
+        super::{threshold_pixels_parallel, threshold_pixels_serial, DistanceMetric},
+        std::time::Instant,
+    };
+
+    #[test]
+    fn adaptive_threshold_gradient() {
+        // Horizontal gradient background, with a dark vertical stroke of "text"
+        let (width, height) = (32, 8);
+        let is_text = |x: u32, y: u32| x == 16 && (2..7).contains(&y);
+        let mut raw_pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let background = 100 + x as u8 * 4;
+                let v = if is_text(x, y) {
+                    background - 60
+                } else {
+                    background
+                };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let image = PhotonImage::new(raw_pixels, width, height);
+
+        let thresholded = adaptive_threshold(&image, 7, 10).get_raw_pixels();
+        for y in 0..height {
+            for x in 0..width {
+                let expected = if is_text(x, y) { 0 } else { 255 };
+                assert_eq!(thresholded[((y * width + x) * 4) as usize], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn tuple_colors() {
+        let pairs = [
+            ((0, 0, 0), (255, 255, 255)),
+            ((255, 0, 0), (250, 10, 5)),
+            ((12, 200, 90), (30, 180, 120)),
+        ];
+        for (a, b) in pairs {
+            let rgb_a = Rgb::new(a.0, a.1, a.2);
+            let rgb_b = Rgb::new(b.0, b.1, b.2);
+            assert_eq!(rgb2lab_tuple(a), rgb2lab(&rgb_a));
+            assert_eq!(delta_e_tuple(a, b), delta_e(&rgb_a, &rgb_b));
+        }
+    }
+
+    #[test]
+    fn delta_e_2000_reference() {
+        // Test data from Sharma, Wu & Dalal (2005), "The CIEDE2000
+        // Color-Difference Formula: Implementation Notes, Supplementary Test
+        // Data, and Mathematical Observations"
+        let pairs = [
+            ((50.0, 2.6772, -79.7751), (50.0, 0.0, -82.7485), 2.0425),
+            ((50.0, 3.1571, -77.2803), (50.0, 0.0, -82.7485), 2.8615),
+            ((50.0, 2.8361, -74.0200), (50.0, 0.0, -82.7485), 3.4412),
+            ((50.0, 0.0, 0.0), (50.0, -1.0, 2.0), 2.3669),
+            ((50.0, -1.0, 2.0), (50.0, 0.0, 0.0), 2.3669),
+            ((50.0, 2.5, 0.0), (73.0, 25.0, -18.0), 27.1492),
+            ((50.0, 2.5, 0.0), (61.0, -5.0, 29.0), 22.8977),
+            (
+                (60.2574, -34.0099, 36.2677),
+                (60.4626, -34.1751, 39.4387),
+                1.2644,
+            ),
+            (
+                (63.0109, -31.0961, -5.8663),
+                (62.8187, -29.7946, -4.0864),
+                1.2630,
+            ),
+            (
+                (90.9257, -0.5406, -0.9208),
+                (88.6381, -0.8985, -0.7239),
+                1.5381,
+            ),
+            (
+                (2.0776, 0.0795, -1.1350),
+                (0.9033, -0.0636, -0.5514),
+                0.9082,
+            ),
+        ];
+        for (lab_a, lab_b, expected) in pairs {
+            let actual = delta_e_2000_lab(lab_a, lab_b);
+            assert!(
+                (actual - expected).abs() < 1e-4,
+                "{} != {}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    fn noise(len: usize) -> Vec<u8> {
+        // Deterministic pseudo-random pixels (xorshift)
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mask() {
+        // Black, white, red and near-black pixels
+        let pixels = [[0, 0, 0], [255, 255, 255], [255, 0, 0], [5, 5, 5]]
+            .iter()
+            .flat_map(|[r, g, b]| [*r, *g, *b, 255])
+            .collect();
+        let image = PhotonImage::new(pixels, 2, 2);
+        let black = Rgb::new(0, 0, 0);
+
+        let mask = threshold_to_mask(&image, &black, 20.0);
+        let rgba = threshold_color_distance(image, &black, 20.0);
+        let white_pixels = rgba
+            .get_raw_pixels()
+            .chunks_exact(4)
+            .filter(|pixel| pixel[0] == 255)
+            .count();
+        assert_eq!(mask.count(), white_pixels);
+        assert_eq!(mask.count(), 2);
+        assert_eq!(mask.iter_set().collect::<Vec<_>>(), vec![(1, 0), (0, 1)]);
+        assert_eq!(mask.to_gray_image().as_raw(), &vec![0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn threshold_keeps_alpha() {
+        let image = PhotonImage::new(vec![0, 0, 0, 0, 255, 255, 255, 128], 2, 1);
+        let thresholded = threshold_color_distance(image, &Rgb::new(0, 0, 0), 20.0);
+        assert_eq!(
+            thresholded.get_raw_pixels(),
+            vec![0, 0, 0, 0, 255, 255, 255, 128]
+        );
+    }
+
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    #[test]
+    fn parallel_threshold() {
+        let pixels = noise(640 * 360 * 4);
+        let color = (255, 255, 255);
+        for metric in [DistanceMetric::DeltaE, DistanceMetric::DeltaE2000] {
+            let serial = threshold_pixels_serial(&pixels, color, 42.0, metric);
+            let parallel = threshold_pixels_parallel(&pixels, color, 42.0, metric);
+            assert_eq!(serial, parallel);
+        }
+    }
+
+    /// Rough benchmark on a 1080p frame: `cargo test --release --features rayon
+    /// parallel_threshold_benchmark -- --ignored --nocapture`
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    #[test]
+    #[ignore]
+    fn parallel_threshold_benchmark() {
+        let pixels = noise(1920 * 1080 * 4);
+        let color = (255, 255, 255);
+        let now = Instant::now();
+        threshold_pixels_serial(&pixels, color, 42.0, DistanceMetric::DeltaE);
+        println!("serial: {}ms", now.elapsed().as_millis());
+        let now = Instant::now();
+        threshold_pixels_parallel(&pixels, color, 42.0, DistanceMetric::DeltaE);
+        println!("parallel: {}ms", now.elapsed().as_millis());
+    }
 }
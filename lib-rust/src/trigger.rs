@@ -2,22 +2,273 @@ use crate::{
     async_trigger::{AsyncTrigger, TriggerCommand},
     error::Result,
 };
-use image::RgbImage;
-use std::sync::mpsc::SyncSender;
+use image::{DynamicImage, RgbImage};
+use std::{
+    any::Any,
+    borrow::Cow,
+    sync::{mpsc::SyncSender, Arc},
+};
+#[cfg(feature = "photon")]
+use {
+    crate::photon::{rgb_to_photon, rgba_to_photon},
+    photon_rs::PhotonImage,
+    std::sync::OnceLock,
+};
 
 /// Represents a single frame of the input, including the raw image pixels as
-/// well as the time it appears in the input (frame_num and/or timestamp)
-#[derive(Clone, Debug, PartialEq)]
+/// well as the time it appears in the input (frame_num and/or timestamp).
+/// Fields may be added over time, so construct it with `Frame::new`.
+#[derive(Debug)]
+#[non_exhaustive]
 pub struct Frame {
     pub image: RgbImage,
+
+    /// Index of this frame among the *sampled* frames, starting from zero. This
+    /// is not the frame index in the source video, unless the sampling `fps`
+    /// matches the source framerate. The pipeline numbers frames itself, so
+    /// this always goes up by exactly one from each frame to the next
+    /// (including frames skipped before reaching the triggers).
     pub frame_num: u64,
+
+    /// The frame number reported by ffmpeg, which `frame_num` replaces. Kept
+    /// for debugging, since it can skip or restart in edge cases of the `fps`
+    /// filter and `-vsync`. Equal to `frame_num` for frames not from the
+    /// pipeline.
+    pub source_frame_num: u64,
+
+    /// Position of this frame in the source video, in seconds. With the default
+    /// `fps` sampling, the `fps` filter emits the source frame nearest to each
+    /// multiple of `1 / fps`, so this is `frame_num / fps`. With `timestamps`
+    /// or `Sampling::SceneChange`, it comes from the frame's own timestamp as
+    /// logged by ffmpeg, so frames are spaced irregularly. Otherwise (e.g. with
+    /// `disable_fps_filter` or a custom command) it's ffmpeg-sidecar's estimate
+    /// from the output framerate.
     pub timestamp: f64,
+
+    /// Width of the frame in the source video, before any processing scale was
+    /// applied. Multiply an x-coordinate in `image` by
+    /// `source_width / image.width()` to get the corresponding coordinate in
+    /// the source video.
+    pub source_width: u32,
+
+    /// Height of the frame in the source video, before any processing scale
+    /// was applied. See `source_width`.
+    pub source_height: u32,
+
+    /// The frame as decoded, when a `PixelFormat` other than the default 8-bit
+    /// RGB was requested (e.g. 16-bit for HDR, or grayscale). `image` is
+    /// always an 8-bit down-conversion of it, for triggers that only handle
+    /// 8-bit RGB. See `as_dynamic` to handle either case.
+    pub original: Option<DynamicImage>,
+
+    /// Arbitrary context attached by a custom input (e.g. a camera id or a
+    /// chapter), for triggers to read back with `get_metadata`. The pipeline
+    /// always leaves it `None`. Clones share it, and it's ignored when
+    /// comparing frames.
+    pub metadata: Option<Arc<dyn Any + Send + Sync>>,
+
+    /// The frame converted to a `PhotonImage`, filled by the first call to
+    /// `photon` or `to_photon`, so that several triggers on the same frame
+    /// only convert it once. Not cloned, since a clone's `image` may change.
+    #[cfg(feature = "photon")]
+    photon: OnceLock<PhotonImage>,
+}
+
+impl Clone for Frame {
+    fn clone(&self) -> Self {
+        Self {
+            image: self.image.clone(),
+            frame_num: self.frame_num,
+            source_frame_num: self.source_frame_num,
+            timestamp: self.timestamp,
+            source_width: self.source_width,
+            source_height: self.source_height,
+            original: self.original.clone(),
+            metadata: self.metadata.clone(),
+            #[cfg(feature = "photon")]
+            photon: OnceLock::new(),
+        }
+    }
+}
+
+impl PartialEq for Frame {
+    fn eq(&self, other: &Self) -> bool {
+        self.image == other.image
+            && self.frame_num == other.frame_num
+            && self.source_frame_num == other.source_frame_num
+            && self.timestamp == other.timestamp
+            && self.source_width == other.source_width
+            && self.source_height == other.source_height
+            && self.original == other.original
+    }
+}
+
+impl Frame {
+    /// Create a frame whose source dimensions are the same as the image's.
+    pub fn new(image: RgbImage, frame_num: u64, timestamp: f64) -> Self {
+        Self {
+            source_width: image.width(),
+            source_height: image.height(),
+            image,
+            frame_num,
+            source_frame_num: frame_num,
+            timestamp,
+            original: None,
+            metadata: None,
+            #[cfg(feature = "photon")]
+            photon: OnceLock::new(),
+        }
+    }
+
+    /// Attach `metadata` to the frame, replacing any already attached.
+    pub fn with_metadata<T>(mut self, metadata: T) -> Self
+    where
+        T: Any + Send + Sync,
+    {
+        self.metadata = Some(Arc::new(metadata));
+        self
+    }
+
+    /// The attached `metadata`, if there is any and it's a `T`.
+    pub fn get_metadata<T: Any>(&self) -> Option<&T> {
+        self.metadata.as_ref()?.downcast_ref()
+    }
+
+    /// Create a frame from an image in any format. Anything other than 8-bit
+    /// RGB is kept as the `original`, and down-converted for `image`.
+    pub fn from_dynamic(image: DynamicImage, frame_num: u64, timestamp: f64) -> Self {
+        match image {
+            DynamicImage::ImageRgb8(rgb) => Self::new(rgb, frame_num, timestamp),
+            original => {
+                let mut frame = Self::new(original.to_rgb8(), frame_num, timestamp);
+                frame.original = Some(original);
+                frame
+            }
+        }
+    }
+
+    /// The frame in the format it was decoded in, so that triggers can match
+    /// on the actual variant (e.g. `DynamicImage::ImageLuma8` for grayscale).
+    /// Only copies the image for 8-bit RGB frames, which have no `original`.
+    pub fn as_dynamic(&self) -> Cow<'_, DynamicImage> {
+        match &self.original {
+            Some(original) => Cow::Borrowed(original),
+            None => Cow::Owned(DynamicImage::ImageRgb8(self.image.clone())),
+        }
+    }
+
+    /// The frame as 8-bit RGB, i.e. `image`, which the pipeline already
+    /// down-converted from the `original` format when there is one.
+    pub fn rgb_image(&self) -> &RgbImage {
+        &self.image
+    }
+
+    /// Maps coordinates between this frame's `image` and the source video.
+    pub fn coordinate_mapper(&self) -> CoordinateMapper {
+        CoordinateMapper {
+            source_width: self.source_width,
+            source_height: self.source_height,
+            processed_width: self.image.width(),
+            processed_height: self.image.height(),
+        }
+    }
+}
+
+/// Maps pixel coordinates between the source video and a processed (scaled)
+/// frame, e.g. to line up a detection on the processed frame with the
+/// original video. See `Hypetrigger::set_processing_scale`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoordinateMapper {
+    pub source_width: u32,
+    pub source_height: u32,
+    pub processed_width: u32,
+    pub processed_height: u32,
+}
+
+impl CoordinateMapper {
+    /// Convert a coordinate in the processed frame to the source video
+    pub fn to_source(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            x * self.source_width as f64 / self.processed_width as f64,
+            y * self.source_height as f64 / self.processed_height as f64,
+        )
+    }
+
+    /// Convert a coordinate in the source video to the processed frame
+    pub fn to_processed(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            x * self.processed_width as f64 / self.source_width as f64,
+            y * self.processed_height as f64 / self.source_height as f64,
+        )
+    }
+}
+
+#[cfg(feature = "photon")]
+impl Frame {
+    /// The frame as a `PhotonImage` for preprocessing. Uses the `original`
+    /// image when it has an alpha channel (`PixelFormat::Rgba`), so that
+    /// transparency survives cropping and filtering.
+    ///
+    /// The conversion runs once per frame, on the first call from any
+    /// trigger, so `image` and `original` shouldn't be changed afterwards.
+    /// Clones start without it, and convert again if they need it.
+    pub fn photon(&self) -> &PhotonImage {
+        self.photon.get_or_init(|| match &self.original {
+            Some(DynamicImage::ImageRgba8(rgba)) => rgba_to_photon(rgba),
+            _ => rgb_to_photon(&self.image),
+        })
+    }
+
+    /// An owned copy of `photon`, for preprocessing steps that consume it
+    /// (e.g. `Crop::apply`).
+    pub fn to_photon(&self) -> PhotonImage {
+        self.photon().clone()
+    }
 }
 
 //// Triggers
 pub trait Trigger: Send + Sync {
+    /// Run on each sampled frame. Returning `Err(StopPipeline.into())` stops
+    /// the whole pipeline cleanly; see `crate::error::StopPipeline`.
     fn on_frame(&self, frame: &Frame) -> Result<()>;
 
+    /// The rate this trigger wants to sample frames at, if lower than the
+    /// pipeline's `fps`. The pipeline skips frames for this trigger so that it
+    /// runs at most once per `1 / desired_fps` seconds of video, based on the
+    /// frame timestamps. Frames can only be skipped, not added, so a rate above
+    /// the pipeline's `fps` has no effect. `None` runs on every frame.
+    fn desired_fps(&self) -> Option<f64> {
+        None
+    }
+
+    /// Block until any work this trigger has deferred (e.g. to another thread)
+    /// is done. Called by the pipeline once the input ends, before the
+    /// completion callbacks run.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once by the pipeline before ffmpeg is spawned, e.g. to open a
+    /// connection or load a model. An error aborts the run, without calling
+    /// `on_end`.
+    fn on_start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once by the pipeline after the last frame, when ffmpeg has
+    /// exited (successfully or not), to release anything set up in
+    /// `on_start`. Runs even if the run failed, but not if `on_start` did.
+    fn on_end(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Run this trigger on a single still image instead of a video, without
+    /// spawning ffmpeg, e.g. to unit test crops and filters against a
+    /// screenshot. The image is passed as frame 0, at timestamp 0.
+    fn run_on_image(&self, image: RgbImage) -> Result<()> {
+        self.on_frame(&Frame::new(image, 0, 0.0))
+    }
+
     /// Convert this Trigger into a `AsyncTrigger`, running on a separate thread.
     fn into_async(self, runner_tx: SyncSender<TriggerCommand>) -> AsyncTrigger
     where
@@ -26,3 +277,102 @@ pub trait Trigger: Send + Sync {
         AsyncTrigger::from_trigger(self, runner_tx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CoordinateMapper, Frame, Trigger};
+    use crate::{error::Result, simple_trigger::SimpleTrigger};
+    use image::{ColorType, DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    #[cfg(feature = "photon")]
+    fn shared_photon_conversion() -> Result<()> {
+        use crate::test_harness::{run_trigger, solid_frame};
+
+        let conversions = Arc::new(Mutex::new(Vec::new()));
+        let record = || {
+            let conversions = conversions.clone();
+            SimpleTrigger::new(move |frame| {
+                let photon = frame.photon() as *const _ as usize;
+                conversions.lock().unwrap().push(photon);
+            })
+        };
+        let (ocr, classifier) = (record(), record());
+
+        let frame = solid_frame([10, 20, 30], 4, 4);
+        run_trigger(&ocr, std::slice::from_ref(&frame))?;
+        run_trigger(&classifier, std::slice::from_ref(&frame))?;
+
+        // Both triggers got the same conversion, and it matches the image
+        let conversions = conversions.lock()?;
+        assert_eq!(conversions.len(), 2);
+        assert_eq!(conversions[0], conversions[1]);
+        assert_eq!(frame.to_photon().get_raw_pixels()[..4], [10, 20, 30, 255]);
+
+        // A clone with a different image converts its own
+        let mut clone = frame.clone();
+        clone.image.put_pixel(0, 0, Rgb([40, 50, 60]));
+        assert_eq!(clone.photon().get_raw_pixels()[..4], [40, 50, 60, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn coordinate_mapper() {
+        let mapper = CoordinateMapper {
+            source_width: 1280,
+            source_height: 720,
+            processed_width: 640,
+            processed_height: 360,
+        };
+        assert_eq!(mapper.to_source(100.0, 50.0), (200.0, 100.0));
+        assert_eq!(mapper.to_processed(200.0, 100.0), (100.0, 50.0));
+    }
+
+    #[test]
+    fn dynamic_frame() {
+        let gray = GrayImage::from_pixel(4, 2, Luma([100]));
+        let frame = Frame::from_dynamic(DynamicImage::ImageLuma8(gray.clone()), 1, 0.5);
+        assert_eq!(frame.as_dynamic().color(), ColorType::L8);
+        assert_eq!(frame.as_dynamic().as_luma8(), Some(&gray));
+        assert_eq!(frame.rgb_image().get_pixel(3, 1), &Rgb([100, 100, 100]));
+        assert_eq!((frame.source_width, frame.source_height), (4, 2));
+
+        let rgb = Frame::new(RgbImage::new(4, 2), 1, 0.5);
+        assert_eq!(rgb.as_dynamic().color(), ColorType::Rgb8);
+        assert!(rgb.original.is_none());
+    }
+
+    #[test]
+    fn metadata() -> Result<()> {
+        #[derive(Debug, PartialEq)]
+        struct Camera {
+            id: u32,
+            chapter: &'static str,
+        }
+
+        let cameras = Arc::new(Mutex::new(Vec::new()));
+        let cameras_clone = cameras.clone();
+        let trigger = SimpleTrigger::new(move |frame| {
+            let camera = frame.get_metadata::<Camera>().unwrap();
+            cameras_clone.lock().unwrap().push(camera.id);
+        });
+        let frame = Frame::new(RgbImage::new(4, 2), 0, 0.0).with_metadata(Camera {
+            id: 2,
+            chapter: "intro",
+        });
+        trigger.on_frame(&frame.clone())?;
+
+        assert_eq!(*cameras.lock()?, vec![2]);
+        assert_eq!(
+            frame.get_metadata(),
+            Some(&Camera {
+                id: 2,
+                chapter: "intro"
+            })
+        );
+        assert_eq!(frame.get_metadata::<String>(), None);
+        assert_eq!(frame, Frame::new(RgbImage::new(4, 2), 0, 0.0));
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use crate::error::Result;
+use crate::trigger::{Frame, Trigger};
+use std::sync::{
+    mpsc::{self, Receiver, Sender},
+    Arc, Mutex,
+};
+
+pub type TypedTriggerCallback<O> = Arc<dyn Fn(O) + Send + Sync>;
+
+/// A trigger that returns a result for each frame, instead of passing it to a
+/// callback, so that triggers can be composed with `map` and `filter`.
+///
+/// To add one to a pipeline, convert it into a `Trigger` with `on_output` (or
+/// `into_channel`). Going the other way, any `Trigger` can be used as a
+/// `TypedTrigger<Output = ()>` with `Untyped`.
+pub trait TypedTrigger: Send + Sync {
+    type Output;
+
+    /// Run on each frame, returning its result.
+    fn process(&self, frame: &Frame) -> Result<Self::Output>;
+
+    /// See `Trigger::desired_fps`.
+    fn desired_fps(&self) -> Option<f64> {
+        None
+    }
+
+    /// See `Trigger::flush`.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// See `Trigger::on_start`.
+    fn on_start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// See `Trigger::on_end`.
+    fn on_end(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Transform each result with `f`.
+    fn map<F, U>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Self::Output) -> U + Send + Sync,
+    {
+        Map { trigger: self, f }
+    }
+
+    /// Keep only the results that match `predicate`, as `Some`; the rest
+    /// become `None`.
+    fn filter<P>(self, predicate: P) -> Filter<Self, P>
+    where
+        Self: Sized,
+        P: Fn(&Self::Output) -> bool + Send + Sync,
+    {
+        Filter {
+            trigger: self,
+            predicate,
+        }
+    }
+
+    /// Convert into a `Trigger` that passes each result to `callback`.
+    fn on_output<C>(self, callback: C) -> OutputTrigger<Self>
+    where
+        Self: Sized,
+        C: Fn(Self::Output) + Send + Sync + 'static,
+    {
+        OutputTrigger {
+            trigger: self,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Convert into a `Trigger` that sends each result to the returned
+    /// `Receiver`, e.g. to consume them on another thread. Results are dropped
+    /// once the receiver hangs up.
+    fn into_channel(self) -> (OutputTrigger<Self>, Receiver<Self::Output>)
+    where
+        Self: Sized,
+        Self::Output: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let tx: Mutex<Sender<Self::Output>> = Mutex::new(tx);
+        let trigger = self.on_output(move |output| {
+            if let Ok(tx) = tx.lock() {
+                tx.send(output).ok();
+            }
+        });
+        (trigger, rx)
+    }
+}
+
+/// A `TypedTrigger` that transforms the results of another, see
+/// `TypedTrigger::map`.
+#[derive(Clone)]
+pub struct Map<T, F> {
+    pub trigger: T,
+    pub f: F,
+}
+
+impl<T, F, U> TypedTrigger for Map<T, F>
+where
+    T: TypedTrigger,
+    F: Fn(T::Output) -> U + Send + Sync,
+{
+    type Output = U;
+
+    fn process(&self, frame: &Frame) -> Result<U> {
+        self.trigger.process(frame).map(&self.f)
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.trigger.desired_fps()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.trigger.flush()
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.trigger.on_start()
+    }
+
+    fn on_end(&self) -> Result<()> {
+        self.trigger.on_end()
+    }
+}
+
+/// A `TypedTrigger` that discards some results of another, see
+/// `TypedTrigger::filter`.
+#[derive(Clone)]
+pub struct Filter<T, P> {
+    pub trigger: T,
+    pub predicate: P,
+}
+
+impl<T, P> TypedTrigger for Filter<T, P>
+where
+    T: TypedTrigger,
+    P: Fn(&T::Output) -> bool + Send + Sync,
+{
+    type Output = Option<T::Output>;
+
+    fn process(&self, frame: &Frame) -> Result<Self::Output> {
+        let output = self.trigger.process(frame)?;
+        Ok((self.predicate)(&output).then_some(output))
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.trigger.desired_fps()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.trigger.flush()
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.trigger.on_start()
+    }
+
+    fn on_end(&self) -> Result<()> {
+        self.trigger.on_end()
+    }
+}
+
+/// A `Trigger` that runs a `TypedTrigger` and passes each result to a
+/// callback, see `TypedTrigger::on_output`. Results that are `None` (e.g. from
+/// `filter`) are still passed on.
+pub struct OutputTrigger<T: TypedTrigger> {
+    pub trigger: T,
+    pub callback: TypedTriggerCallback<T::Output>,
+}
+
+impl<T> Trigger for OutputTrigger<T>
+where
+    T: TypedTrigger,
+{
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        (self.callback)(self.trigger.process(frame)?);
+        Ok(())
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.trigger.desired_fps()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.trigger.flush()
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.trigger.on_start()
+    }
+
+    fn on_end(&self) -> Result<()> {
+        self.trigger.on_end()
+    }
+}
+
+impl<T> Clone for OutputTrigger<T>
+where
+    T: TypedTrigger + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            trigger: self.trigger.clone(),
+            callback: self.callback.clone(),
+        }
+    }
+}
+
+/// Any callback-based `Trigger`, as a `TypedTrigger` with no output, so it can
+/// be used wherever a `TypedTrigger` is expected.
+#[derive(Clone)]
+pub struct Untyped<T>(pub T);
+
+impl<T> TypedTrigger for Untyped<T>
+where
+    T: Trigger,
+{
+    type Output = ();
+
+    fn process(&self, frame: &Frame) -> Result<()> {
+        self.0.on_frame(frame)
+    }
+
+    fn desired_fps(&self) -> Option<f64> {
+        self.0.desired_fps()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.0.flush()
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.0.on_start()
+    }
+
+    fn on_end(&self) -> Result<()> {
+        self.0.on_end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TypedTrigger, Untyped};
+    use crate::{
+        error::Result,
+        simple_trigger::SimpleTrigger,
+        trigger::{Frame, Trigger},
+    };
+    use image::{Rgb, RgbImage};
+    use std::sync::{Arc, Mutex};
+
+    /// Average brightness of the frame, from 0 to 255
+    struct Brightness;
+
+    impl TypedTrigger for Brightness {
+        type Output = u32;
+
+        fn process(&self, frame: &Frame) -> Result<u32> {
+            let sum: u64 = frame.image.as_raw().iter().map(|&v| v as u64).sum();
+            Ok((sum / frame.image.as_raw().len().max(1) as u64) as u32)
+        }
+    }
+
+    fn solid_frame(value: u8, frame_num: u64) -> Frame {
+        Frame::new(
+            RgbImage::from_pixel(4, 4, Rgb([value, value, value])),
+            frame_num,
+            0.0,
+        )
+    }
+
+    #[test]
+    fn typed_trigger() -> Result<()> {
+        assert_eq!(Brightness.process(&solid_frame(100, 0))?, 100);
+
+        let bright = Brightness.map(|b| b as f64 / 255.0).filter(|b| *b > 0.5);
+        assert_eq!(bright.process(&solid_frame(51, 0))?, None);
+        assert_eq!(bright.process(&solid_frame(255, 0))?, Some(1.0));
+
+        let outputs = Arc::new(Mutex::new(Vec::new()));
+        let outputs_clone = outputs.clone();
+        let trigger = Brightness.on_output(move |b| outputs_clone.lock().unwrap().push(b));
+        trigger.on_frame(&solid_frame(10, 0))?;
+        trigger.on_frame(&solid_frame(20, 1))?;
+        assert_eq!(*outputs.lock()?, vec![10, 20]);
+        Ok(())
+    }
+
+    #[test]
+    fn into_channel() -> Result<()> {
+        let (trigger, rx) = Brightness.into_channel();
+        trigger.on_frame(&solid_frame(42, 0))?;
+        drop(trigger);
+        assert_eq!(rx.iter().collect::<Vec<u32>>(), vec![42]);
+        Ok(())
+    }
+
+    #[test]
+    fn untyped() -> Result<()> {
+        let frames = Arc::new(Mutex::new(0));
+        let frames_clone = frames.clone();
+        let trigger = Untyped(SimpleTrigger::new(move |_| {
+            *frames_clone.lock().unwrap() += 1;
+        }));
+        trigger.process(&solid_frame(0, 0))?;
+        assert_eq!(*frames.lock()?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn forwards_lifecycle() -> Result<()> {
+        #[derive(Default)]
+        struct Lifecycle(Arc<Mutex<Vec<&'static str>>>);
+        impl Trigger for Lifecycle {
+            fn on_frame(&self, _frame: &Frame) -> Result<()> {
+                Ok(())
+            }
+            fn desired_fps(&self) -> Option<f64> {
+                Some(2.0)
+            }
+            fn flush(&self) -> Result<()> {
+                self.0.lock()?.push("flush");
+                Ok(())
+            }
+            fn on_start(&self) -> Result<()> {
+                self.0.lock()?.push("start");
+                Ok(())
+            }
+            fn on_end(&self) -> Result<()> {
+                self.0.lock()?.push("end");
+                Ok(())
+            }
+        }
+
+        let inner = Lifecycle::default();
+        let calls = inner.0.clone();
+        let trigger = Untyped(inner)
+            .map(|_| ())
+            .filter(|_| true)
+            .on_output(|_| {});
+        assert_eq!(trigger.desired_fps(), Some(2.0));
+        trigger.on_start()?;
+        trigger.flush()?;
+        trigger.on_end()?;
+        assert_eq!(*calls.lock()?, vec!["start", "flush", "end"]);
+        Ok(())
+    }
+}
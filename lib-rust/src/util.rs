@@ -27,54 +27,393 @@ pub fn command_to_string(cmd: &Command) -> String {
     command_string
 }
 
-/// Parses a line of ffmpeg stderr output, looking for the video size.
-/// We're looking for a line like this:
+/// Metadata about a video stream, as logged by ffmpeg. See
+/// `parse_ffmpeg_stream_info`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamInfo {
+    pub width: u32,
+    pub height: u32,
+
+    /// Frames per second, e.g. `29.97`
+    pub fps: Option<f64>,
+
+    /// Pixel format, e.g. `yuv420p` or `rgb24`
+    pub pix_fmt: Option<String>,
+
+    /// Duration of the input in seconds, from the separate `Duration:` line
+    /// (if it was included in `text` and isn't `N/A`)
+    pub duration: Option<f64>,
+}
+
+/// Parses ffmpeg stderr output, looking for the size, framerate and pixel
+/// format of a video stream, plus the input's duration if it's included. The
+/// text can be a single line or several. We're looking for lines like this:
+///
+/// `  Duration: 00:00:10.00, start: 0.000000, bitrate: N/A`
 ///
 /// `  Stream #0:0(und): Video: rawvideo (RGB[24] / 0x18424752), rgb24(pc, bt709, progressive), 1920x1080 [SAR 1:1 DAR 16:9], q=2-31, 99532 kb/s, 2 fps, 2 tbn (default)`
+pub fn parse_ffmpeg_stream_info(text: &str) -> Option<StreamInfo> {
+    lazy_static! {
+        static ref REGEX_STREAM: Regex =
+            Regex::new(r"  Stream .* Video: .* (\d+)x(\d+),? .*").unwrap();
+        static ref REGEX_PIX_FMT: Regex = Regex::new(r"Video: [^,]+, (\w+)").unwrap();
+        static ref REGEX_DURATION: Regex =
+            Regex::new(r"Duration: (\d+):(\d{2}):(\d{2}(?:\.\d+)?)").unwrap();
+    }
+
+    let capture = REGEX_STREAM.captures(text)?;
+    let stream = capture.get(0)?.as_str();
+    let width = capture.get(1)?.as_str().parse::<u32>().ok()?;
+    let height = capture.get(2)?.as_str().parse::<u32>().ok()?;
+    let pix_fmt = REGEX_PIX_FMT
+        .captures(stream)
+        .and_then(|capture| Some(capture.get(1)?.as_str().to_string()));
+    let duration = REGEX_DURATION.captures(text).and_then(|capture| {
+        let hours = capture.get(1)?.as_str().parse::<f64>().ok()?;
+        let minutes = capture.get(2)?.as_str().parse::<f64>().ok()?;
+        let seconds = capture.get(3)?.as_str().parse::<f64>().ok()?;
+        Some(hours * 3600.0 + minutes * 60.0 + seconds)
+    });
+
+    Some(StreamInfo {
+        width,
+        height,
+        fps: parse_ffmpeg_fps(stream),
+        pix_fmt,
+        duration,
+    })
+}
+
+/// Parses a line of ffmpeg stderr output, looking for the video size. See
+/// `parse_ffmpeg_stream_info` for the rest of the stream's metadata.
 pub fn parse_ffmpeg_output_size(text: &str) -> Option<(u32, u32)> {
+    parse_ffmpeg_stream_info(text).map(|info| (info.width, info.height))
+}
+
+/// Parses a line of ffmpeg stderr output describing a video stream, looking
+/// for its framerate (e.g. `30 fps` or `29.97 fps`).
+pub fn parse_ffmpeg_fps(text: &str) -> Option<f64> {
     lazy_static! {
-        static ref REGEX_SIZE: Regex = Regex::new(r"  Stream .* Video: .* (\d+)x(\d+),? ").unwrap();
+        static ref REGEX_FPS: Regex = Regex::new(r"(\d+(?:\.\d+)?) fps").unwrap();
     }
 
-    match REGEX_SIZE.captures(text) {
-        Some(capture) => {
-            let width = capture.get(1).unwrap().as_str().parse::<u32>().unwrap();
-            let height = capture.get(2).unwrap().as_str().parse::<u32>().unwrap();
-            Some((width, height))
-        }
-        None => None,
+    REGEX_FPS
+        .captures(text)
+        .and_then(|capture| capture.get(1)?.as_str().parse::<f64>().ok())
+}
+
+/// Parses a line of ffmpeg stderr output from the `showinfo` filter, looking
+/// for the index of the frame (counting from zero) and its presentation time
+/// in seconds, as `(n, pts_time)`. We're looking for a line like this:
+///
+/// `[Parsed_showinfo_1 @ 0000020b9ab8d3c0] n:   0 pts:  12800 pts_time:1       duration:    512 ...`
+pub fn parse_showinfo_frame(text: &str) -> Option<(u64, f64)> {
+    lazy_static! {
+        static ref REGEX_SHOWINFO: Regex =
+            Regex::new(r"Parsed_showinfo.* n:\s*(\d+) .* pts_time:\s*(-?\d+(?:\.\d+)?)").unwrap();
     }
+
+    let captures = REGEX_SHOWINFO.captures(text)?;
+    let n = captures.get(1)?.as_str().parse().ok()?;
+    let pts_time = captures.get(2)?.as_str().parse().ok()?;
+    Some((n, pts_time))
 }
 
-/// prints as e.g. `"1:23:45.5"`
-pub fn format_seconds(seconds: f64) -> String {
-    let mut time_left = seconds;
+/// Parses a line of ffmpeg's progress output, looking for the number of
+/// frames duplicated and dropped so far to keep the output framerate, as
+/// `(dup, drop)`. We're looking for a line like this:
+///
+/// `frame=   60 fps=0.0 q=-0.0 size=  162000kB time=00:00:02.00 bitrate=663552.0kbits/s dup=50 drop=0 speed=12.4x`
+pub fn parse_ffmpeg_dup_drop(text: &str) -> Option<(u64, u64)> {
+    lazy_static! {
+        static ref REGEX_DUP_DROP: Regex = Regex::new(r"dup=\s*(\d+)\s+drop=\s*(\d+)").unwrap();
+    }
 
-    let hours = time_left as u64 / 3600;
-    time_left -= hours as f64 * 3600.0;
+    let captures = REGEX_DUP_DROP.captures(text)?;
+    let dup = captures.get(1)?.as_str().parse().ok()?;
+    let drop = captures.get(2)?.as_str().parse().ok()?;
+    Some((dup, drop))
+}
 
-    let minutes = time_left as u64 / 60;
-    time_left -= minutes as f64 * 60.0;
+/// Progress of a run, as reported by ffmpeg's `-progress` option once every
+/// update period (half a second by default).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgressInfo {
+    /// Number of frames output so far
+    pub frame: u64,
 
-    let seconds = time_left as u64;
-    time_left -= seconds as f64;
+    /// Output frames per second of wall clock time, if reported
+    pub fps: Option<f64>,
 
-    let milliseconds = (time_left * 1000.0).round() as u64;
+    /// Position in the output, in seconds
+    pub out_time: f64,
 
-    let mut string = "".to_string();
-    if hours > 0 {
-        string += &format!("{}:", hours);
+    /// Speed relative to real time (e.g. `2.0` for twice as fast), or `None`
+    /// while ffmpeg reports it as `N/A`
+    pub speed: Option<f64>,
+
+    /// Frames duplicated so far to keep the output framerate
+    pub dup_frames: u64,
+
+    /// Frames dropped so far to keep the output framerate
+    pub drop_frames: u64,
+
+    /// Whether this is the last update (`progress=end`)
+    pub done: bool,
+}
+
+/// Splits a line of `-progress` output into its key and value, if it is one.
+/// We're looking for a line like `out_time_us=2000000` or `speed=12.4x`.
+pub fn parse_ffmpeg_progress_line(text: &str) -> Option<(&str, &str)> {
+    let (key, value) = text.trim().split_once('=')?;
+    let is_key = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    match is_key && !value.contains(char::is_whitespace) {
+        true => Some((key, value)),
+        false => None,
     }
-    if minutes < 10 {
-        string += "0";
+}
+
+/// Parses one block of ffmpeg's `-progress` output, which lists `key=value`
+/// pairs one per line, ending with `progress=continue` or `progress=end`:
+///
+/// ```text
+/// frame=60
+/// fps=30.00
+/// out_time_us=2000000
+/// out_time_ms=2000000
+/// out_time=00:00:02.000000
+/// dup_frames=0
+/// drop_frames=0
+/// speed=12.4x
+/// progress=continue
+/// ```
+///
+/// Returns `None` if the block has no `progress=` line.
+pub fn parse_ffmpeg_progress(block: &str) -> Option<ProgressInfo> {
+    let mut info = ProgressInfo::default();
+    let mut out_time_us: Option<f64> = None;
+    let mut out_time_ms: Option<f64> = None;
+    let mut out_time: Option<f64> = None;
+    let mut ended = None;
+    for (key, value) in block.lines().filter_map(parse_ffmpeg_progress_line) {
+        match key {
+            "frame" => info.frame = value.parse().unwrap_or_default(),
+            "fps" => info.fps = value.parse().ok(),
+            "out_time_us" => out_time_us = value.parse().ok(),
+            // Despite the name, also in microseconds
+            "out_time_ms" => out_time_ms = value.parse().ok(),
+            "out_time" => out_time = parse_timestamp(value),
+            "dup_frames" => info.dup_frames = value.parse().unwrap_or_default(),
+            "drop_frames" => info.drop_frames = value.parse().unwrap_or_default(),
+            "speed" => info.speed = value.trim_end_matches('x').parse().ok(),
+            "progress" => ended = Some(value == "end"),
+            _ => {}
+        }
+    }
+    info.done = ended?;
+    info.out_time = out_time_us
+        .or(out_time_ms)
+        .map(|us| us / 1_000_000.0)
+        .or(out_time)
+        .unwrap_or_default()
+        .max(0.0);
+    Some(info)
+}
+
+/// Parses a timestamp like `01:02:03.5` into seconds.
+fn parse_timestamp(text: &str) -> Option<f64> {
+    let mut parts = text.split(':').rev();
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next().map_or(Some(0.0), |m| m.parse().ok())?;
+    let hours: f64 = parts.next().map_or(Some(0.0), |h| h.parse().ok())?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Most decimal places `format_seconds` prints (nanoseconds)
+pub const MAX_SECONDS_PRECISION: usize = 9;
+
+/// Prints as e.g. `"1:23:45.5"`, or `"-1:05"` for negative values. Minutes and
+/// seconds are zero-padded when a larger unit comes before them.
+///
+/// With a `precision` (at most `MAX_SECONDS_PRECISION`), the seconds always
+/// have exactly that many decimal places (e.g. `"0:05.00"`). Without one,
+/// they're rounded to the millisecond and trailing zeros are dropped.
+///
+/// Values that aren't finite, or are too large to count in units of the
+/// precision, are printed as plain numbers (e.g. `"NaN"`).
+pub fn format_seconds<P>(seconds: f64, precision: P) -> String
+where
+    P: Into<Option<usize>>,
+{
+    let precision: Option<usize> = precision
+        .into()
+        .map(|precision| precision.min(MAX_SECONDS_PRECISION));
+    let decimals = precision.unwrap_or(3);
+    let scale = 10u128.pow(decimals as u32);
+
+    // Round once up front, so that e.g. 59.9999 doesn't print as "0:60"
+    let total = (seconds.abs() * scale as f64).round();
+    if !total.is_finite() || total >= u128::MAX as f64 {
+        return seconds.to_string();
+    }
+    let total = total as u128;
+    let fraction = total % scale;
+    let whole = total / scale;
+    let hours = whole / 3600;
+    let minutes = whole / 60 % 60;
+    let seconds_part = whole % 60;
+
+    let mut string = String::new();
+    if seconds < 0.0 && total > 0 {
+        string += "-";
     }
-    string += &format!("{}:", minutes);
-    if seconds < 10 {
-        string += "0";
+    if hours > 0 {
+        string += &format!("{}:{:02}:{:02}", hours, minutes, seconds_part);
+    } else {
+        string += &format!("{}:{:02}", minutes, seconds_part);
     }
-    string += &format!("{}", seconds);
-    if milliseconds > 0 {
-        string += &format!(".{}", milliseconds);
+
+    let fraction = match decimals {
+        0 => String::new(),
+        _ => format!("{:0width$}", fraction, width = decimals),
+    };
+    let fraction = match precision {
+        Some(_) => fraction.as_str(),
+        None => fraction.trim_end_matches('0'),
+    };
+    if !fraction.is_empty() {
+        string += ".";
+        string += fraction;
     }
     string
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        format_seconds, parse_ffmpeg_dup_drop, parse_ffmpeg_output_size, parse_ffmpeg_progress,
+        parse_ffmpeg_progress_line, parse_ffmpeg_stream_info, parse_showinfo_frame, ProgressInfo,
+        StreamInfo,
+    };
+
+    #[test]
+    fn dup_drop() {
+        let line = "frame=   60 fps=0.0 q=-0.0 Lsize=  162000kB time=00:00:02.00 bitrate=663552.0kbits/s dup=50 drop=3 speed=12.4x";
+        assert_eq!(parse_ffmpeg_dup_drop(line), Some((50, 3)));
+        let line = "frame=   60 fps=0.0 q=-0.0 size=  162000kB time=00:00:02.00 bitrate=663552.0kbits/s speed=12.4x";
+        assert_eq!(parse_ffmpeg_dup_drop(line), None);
+    }
+
+    #[test]
+    fn progress() {
+        let block = concat!(
+            "frame=60\n",
+            "fps=30.00\n",
+            "stream_0_0_q=-0.0\n",
+            "bitrate=N/A\n",
+            "total_size=N/A\n",
+            "out_time_us=2000000\n",
+            "out_time_ms=2000000\n",
+            "out_time=00:00:02.000000\n",
+            "dup_frames=50\n",
+            "drop_frames=3\n",
+            "speed=12.4x\n",
+            "progress=continue\n",
+        );
+        assert_eq!(
+            parse_ffmpeg_progress(block),
+            Some(ProgressInfo {
+                frame: 60,
+                fps: Some(30.0),
+                out_time: 2.0,
+                speed: Some(12.4),
+                dup_frames: 50,
+                drop_frames: 3,
+                done: false,
+            })
+        );
+
+        let block = "frame=0\nout_time=00:01:05.500000\nspeed=N/A\nprogress=end";
+        let info = parse_ffmpeg_progress(block).unwrap();
+        assert_eq!(info.out_time, 65.5);
+        assert_eq!(info.speed, None);
+        assert!(info.done);
+
+        assert_eq!(parse_ffmpeg_progress("frame=60\nfps=30.00"), None);
+        assert_eq!(
+            parse_ffmpeg_progress_line("speed=12.4x"),
+            Some(("speed", "12.4x"))
+        );
+        assert_eq!(
+            parse_ffmpeg_progress_line("frame=   60 fps=0.0 q=-0.0"),
+            None
+        );
+        assert_eq!(parse_ffmpeg_progress_line("Press [q] to stop"), None);
+    }
+
+    #[test]
+    fn showinfo_pts_time() {
+        let line = "[Parsed_showinfo_1 @ 0000020b9ab8d3c0] n:   3 pts:  12800 pts_time:1.5     duration:    512 fmt:rgb24";
+        assert_eq!(parse_showinfo_frame(line), Some((3, 1.5)));
+        assert_eq!(parse_showinfo_frame("frame=   20 fps=0.0 q=-0.0"), None);
+    }
+
+    #[test]
+    fn seconds_formatting() {
+        assert_eq!(format_seconds(0.0, None), "0:00");
+        assert_eq!(format_seconds(-65.0, None), "-1:05");
+        assert_eq!(format_seconds(3661.5, None), "1:01:01.5");
+        assert_eq!(format_seconds(3661.5, 2), "1:01:01.50");
+        assert_eq!(format_seconds(5.05, None), "0:05.05");
+        assert_eq!(format_seconds(59.9999, 2), "1:00.00");
+        assert_eq!(format_seconds(-0.0001, 1), "0:00.0");
+        assert_eq!(format_seconds(360_000.0, 0), "100:00:00");
+        assert_eq!(format_seconds(1.5, 20), "0:01.500000000");
+        assert_eq!(format_seconds(f64::NAN, None), "NaN");
+        assert_eq!(format_seconds(f64::NEG_INFINITY, 2), "-inf");
+        assert_eq!(format_seconds(1e300, None), 1e300.to_string());
+    }
+
+    #[test]
+    fn stream_info() {
+        let rawvideo = "  Stream #0:0(und): Video: rawvideo (RGB[24] / 0x18424752), rgb24(pc, bt709, progressive), 1920x1080 [SAR 1:1 DAR 16:9], q=2-31, 99532 kb/s, 2 fps, 2 tbn (default)";
+        assert_eq!(
+            parse_ffmpeg_stream_info(rawvideo),
+            Some(StreamInfo {
+                width: 1920,
+                height: 1080,
+                fps: Some(2.0),
+                pix_fmt: Some("rgb24".to_string()),
+                duration: None,
+            })
+        );
+        assert_eq!(parse_ffmpeg_output_size(rawvideo), Some((1920, 1080)));
+
+        let mp4 = concat!(
+            "  Duration: 00:01:05.43, start: 0.000000, bitrate: 6150 kb/s\n",
+            "  Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p(tv, bt709, progressive), 1280x720 [SAR 1:1 DAR 16:9], 6000 kb/s, 29.97 fps, 29.97 tbr, 30k tbn (default)\n",
+            "  Stream #0:1[0x2](und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s (default)",
+        );
+        assert_eq!(
+            parse_ffmpeg_stream_info(mp4),
+            Some(StreamInfo {
+                width: 1280,
+                height: 720,
+                fps: Some(29.97),
+                pix_fmt: Some("yuv420p".to_string()),
+                duration: Some(65.43),
+            })
+        );
+
+        let lavfi = "  Stream #0:0: Video: wrapped_avframe, rgb24, 320x240 [SAR 1:1 DAR 4:3], q=2-31, 200 kb/s, 25 fps, 25 tbn";
+        let info = parse_ffmpeg_stream_info(lavfi).unwrap();
+        assert_eq!((info.width, info.height), (320, 240));
+        assert_eq!(info.pix_fmt.as_deref(), Some("rgb24"));
+
+        let audio = "  Stream #0:1[0x2](und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s (default)";
+        assert_eq!(parse_ffmpeg_stream_info(audio), None);
+    }
+}
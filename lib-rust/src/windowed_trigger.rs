@@ -0,0 +1,84 @@
+use crate::error::Result;
+use crate::trigger::{Frame, Trigger};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+pub type WindowedTriggerCallback = Arc<dyn Fn(&[Arc<Frame>]) + Send + Sync>;
+
+/// A Trigger that keeps the last `size` frames, and passes all of them to a
+/// callback on each frame (oldest first, ending with the current frame), for
+/// temporal logic like "text appeared, then disappeared".
+///
+/// Frames are stored behind an `Arc`, so each one is only copied once no
+/// matter how many windows it appears in. Until `size` frames have been seen,
+/// the window is shorter.
+#[derive(Clone)]
+pub struct WindowedTrigger {
+    pub size: usize,
+    pub frames: Arc<Mutex<VecDeque<Arc<Frame>>>>,
+    pub callback: WindowedTriggerCallback,
+}
+
+impl Trigger for WindowedTrigger {
+    fn on_frame(&self, frame: &Frame) -> Result<()> {
+        let mut frames = self.frames.lock()?;
+        frames.push_back(Arc::new(frame.clone()));
+        while frames.len() > self.size.max(1) {
+            frames.pop_front();
+        }
+        (self.callback)(frames.make_contiguous());
+        Ok(())
+    }
+}
+
+impl WindowedTrigger {
+    pub fn new<T>(size: usize, on_window: T) -> Self
+    where
+        T: Fn(&[Arc<Frame>]) + Send + Sync + 'static,
+    {
+        Self {
+            size,
+            frames: Arc::new(Mutex::new(VecDeque::with_capacity(size))),
+            callback: Arc::new(on_window),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WindowedTrigger;
+    use crate::{
+        error::Result,
+        trigger::{Frame, Trigger},
+    };
+    use image::RgbImage;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn windowed_trigger() -> Result<()> {
+        let windows = Arc::new(Mutex::new(Vec::new()));
+        let windows_clone = windows.clone();
+        let trigger = WindowedTrigger::new(3, move |frames| {
+            let frame_nums: Vec<u64> = frames.iter().map(|frame| frame.frame_num).collect();
+            windows_clone.lock().unwrap().push(frame_nums);
+        });
+
+        for frame_num in 0..5 {
+            trigger.on_frame(&Frame::new(RgbImage::new(1, 1), frame_num, 0.0))?;
+        }
+
+        assert_eq!(
+            *windows.lock()?,
+            vec![
+                vec![0],
+                vec![0, 1],
+                vec![0, 1, 2],
+                vec![1, 2, 3],
+                vec![2, 3, 4],
+            ]
+        );
+        Ok(())
+    }
+}